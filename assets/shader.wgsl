@@ -1,12 +1,99 @@
+// The playground binds `input`, `output`, `globals`, `keyboard`, `dispatch` and `params` by
+// name, at whatever binding they're declared, as well as configured passes and image textures.
+// Any other @group(0) binding gets a zero-initialized resource of its own, sized like the state
+// textures. `#{STATE_FORMAT}` is replaced with the configured format of the state textures.
+
 @group(0) @binding(0)
-var img: texture_storage_2d<rgba8unorm, read_write>;
+var input: texture_storage_2d<#{STATE_FORMAT}, read>;
+@group(0) @binding(1)
+var output: texture_storage_2d<#{STATE_FORMAT}, write>;
+
+struct Globals {
+    time: f32,
+    delta_time: f32,
+    frame: u32,
+    resolution: vec2<u32>,
+    mouse: vec2<f32>,
+    mouse_click: vec2<f32>,
+    mouse_buttons: u32,
+    mouse_drag: vec2<f32>,
+    mouse_pressed: u32,
+    tick: u32,
+}
+
+@group(0) @binding(2)
+var<uniform> globals: Globals;
+
+// 256x3 keyboard state indexed by JavaScript key code: row 0 down, row 1 pressed, row 2 toggled.
+@group(0) @binding(3)
+var keyboard: texture_2d<f32>;
+
+// Offset of this dispatch, for textures too large to cover in a single dispatch.
+struct Dispatch {
+    offset: vec3<u32>,
+}
+
+@group(0) @binding(4)
+var<uniform> dispatch: Dispatch;
+
+// Edited in the parameter panel (F6) while the shader runs.
+struct Params {
+    // @param range(1, 32) default(4)
+    brush_radius: f32,
+    brush_color: vec3<f32>, // @color default(1, 1, 1)
+}
+
+@group(0) @binding(5)
+var<uniform> params: Params;
+
+fn key_down(key: u32) -> bool {
+    return textureLoad(keyboard, vec2<u32>(key, 0u), 0).r > 0.5;
+}
+
+// Colors are in [0, 1]. Integer state textures hold them counting up to 255, like they're
+// displayed.
+#ifdef STATE_FORMAT_INTEGER
+fn load_state(id: vec2<u32>) -> vec4<f32> {
+    return vec4<f32>(textureLoad(input, id)) / 255.0;
+}
+
+fn store_state(id: vec2<u32>, color: vec4<f32>) {
+    textureStore(output, id, vec4<u32>(round(saturate(color) * 255.0)));
+}
+#else
+fn load_state(id: vec2<u32>) -> vec4<f32> {
+    return textureLoad(input, id);
+}
+
+fn store_state(id: vec2<u32>, color: vec4<f32>) {
+    textureStore(output, id, color);
+}
+#endif
+
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn init(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let id = invocation_id + dispatch.offset;
+    let dims = textureDimensions(output);
+    if (id.x >= dims.x || id.y >= dims.y) { return; }
+
+    let uv = vec2<f32>(f32(id.x), f32(id.y)) / vec2<f32>(f32(dims.x), f32(dims.y));
+
+    store_state(id.xy, vec4<f32>(1.0, 0.0, 0.0, uv.y));
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let id = invocation_id + dispatch.offset;
+    let dims = textureDimensions(output);
+    if (id.x >= dims.x || id.y >= dims.y) { return; }
 
-@compute @workgroup_size(8, 8)
-fn init(@builtin(global_invocation_id) id: vec3<u32>                                                                                                                                                                                            ) {
-    let dims = textureDimensions(img);
-     if (id.x >= dims.x || id.y >= dims.y) { return; }
+    var color = load_state(id.xy);
 
-        let uv = vec2<f32>(f32(id.x), f32(id.y)) / vec2<f32>(f32(dims.x), f32(dims.y));
+    // Paint with the left mouse button, erase while holding shift.
+    let brush = distance(vec2<f32>(id.xy), globals.mouse) < params.brush_radius;
+    if (brush && (globals.mouse_buttons & 1u) != 0u) {
+        color = select(vec4<f32>(params.brush_color, 1.0), vec4<f32>(0.0), key_down(16u));
+    }
 
-        textureStore(img, id.xy, vec4<f32>(255, 0 , 0, uv.y));
+    store_state(id.xy, color);
 }
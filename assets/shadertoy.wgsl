@@ -0,0 +1,10 @@
+// Shadertoy's default shader. Run with `--shader shadertoy.wgsl --mode shadertoy`.
+fn mainImage(fragCoord: vec2<f32>) -> vec4<f32> {
+    // Normalized pixel coordinates (from 0 to 1)
+    let uv = fragCoord / iResolution.xy;
+
+    // Time varying pixel color
+    let col = 0.5 + 0.5 * cos(iTime + uv.xyx + vec3<f32>(0.0, 2.0, 4.0));
+
+    return vec4<f32>(col, 1.0);
+}
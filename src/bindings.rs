@@ -0,0 +1,1233 @@
+//! Reflection of the compute shader's bindings, and the GPU resources that back them.
+//!
+//! The bind group layout follows whatever the shader declares in `@group(0)`. Variables named
+//! after one of the playground's own resources are bound to it, see [`BindingSource`]; every
+//! other binding gets a zero-initialized resource of its own. The shaders of passes are
+//! reflected the same way.
+
+use std::collections::HashMap;
+use std::mem;
+use std::num::NonZeroU64;
+
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::asset::{AssetEvent, AssetServer, Assets, Handle};
+use bevy::log::info;
+use bevy::math::UVec2;
+use bevy::prelude::{Commands, EventReader, Res, ResMut, Resource};
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource, BindingType,
+    Buffer, BufferBindingType, BufferDescriptor, BufferUsages, CommandEncoder, CompareFunction,
+    ComputePassDescriptor, ComputePipeline, Extent3d, FilterMode, ImageSubresourceRange, Sampler,
+    SamplerBindingType, SamplerDescriptor, Shader, ShaderStages, ShaderType, Source,
+    StorageTextureAccess, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+use bevy::render::renderer::RenderDevice;
+use bevy::render::settings::WgpuLimits;
+use bevy::render::texture::GpuImage;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{AddressSpace, GlobalVariable, ImageClass, ImageDimension, Module, ScalarKind};
+use wgpu::ComputePassTimestampWrites;
+
+use crate::config::{PlaygroundConfig, expand_state_format};
+use crate::cpu::compose_module;
+use crate::dispatch::{ComputeShaderDispatch, DispatchOffset, DispatchPlan};
+use crate::format::StorageFormat;
+use crate::globals::ComputeShaderGlobals;
+use crate::params::{PARAMS_NAME, ParamsLayout, reflect_params};
+use crate::shader_error::ShaderDiagnostic;
+use crate::shadertoy::channel_source;
+use crate::storage::{StorageSupport, TextureCopy, copy_read_write, write_wgsl};
+use crate::{INIT_ENTRY_POINT, UPDATE_ENTRY_POINT};
+
+/// Where the resource bound to a shader variable comes from, decided by the variable's name.
+/// `iChannel0` to `iChannel3` are bound to the configured
+/// [`ChannelInput`](crate::shadertoy::ChannelInput)s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindingSource {
+    /// `input`: the state texture written by the previous frame.
+    Input,
+    /// `output`: the state texture this frame writes, which is displayed.
+    Output,
+    /// `globals`: the [`ComputeShaderGlobals`] uniform.
+    Globals,
+    /// `keyboard`: the keyboard state texture.
+    Keyboard,
+    /// `dispatch`: the [`DispatchOffset`] of the current dispatch.
+    Dispatch,
+    /// `params`: the values of the parameter panel, see [`crate::params`].
+    Params,
+    /// The texture of the pass at `index` in [`PlaygroundConfig::passes`], by the pass's name:
+    /// the one it writes this frame, or with a `_previous` suffix the one it wrote last frame.
+    Pass { index: usize, previous: bool },
+    /// The image of [`PlaygroundConfig::textures`] at this index, by the texture's name.
+    Texture(usize),
+    /// The sampler of the texture at this index, by the texture's name with `_sampler` appended.
+    TextureSampler(usize),
+    /// A read-only copy of the storage texture at this binding, which is declared `read_write`
+    /// but bound write-only, see [`crate::storage`]. Allocated like [`BindingSource::Allocated`].
+    Copy(u32),
+    /// Anything else, backed by a resource allocated for the binding.
+    Allocated,
+}
+
+impl BindingSource {
+    fn from_name(name: &str, config: &PlaygroundConfig) -> Self {
+        if let Some(source) = channel_source(name, &config.channels) {
+            return source;
+        }
+        let pass = |name: &str| config.passes.iter().position(|pass| pass.name == name);
+        if let Some(index) = pass(name) {
+            return BindingSource::Pass {
+                index,
+                previous: false,
+            };
+        }
+        if let Some(index) = name.strip_suffix("_previous").and_then(pass) {
+            return BindingSource::Pass {
+                index,
+                previous: true,
+            };
+        }
+        let texture = |name: &str| config.textures.iter().position(|t| t.name == name);
+        if let Some(index) = texture(name) {
+            return BindingSource::Texture(index);
+        }
+        if let Some(index) = name.strip_suffix("_sampler").and_then(texture) {
+            return BindingSource::TextureSampler(index);
+        }
+        match name {
+            "input" => BindingSource::Input,
+            "output" => BindingSource::Output,
+            "globals" => BindingSource::Globals,
+            "keyboard" => BindingSource::Keyboard,
+            "dispatch" => BindingSource::Dispatch,
+            PARAMS_NAME => BindingSource::Params,
+            _ => BindingSource::Allocated,
+        }
+    }
+}
+
+/// One `@group(0)` binding of the compute shader.
+#[derive(Clone, Debug)]
+pub struct ShaderBinding {
+    pub binding: u32,
+    pub name: String,
+    pub source: BindingSource,
+    pub ty: BindingType,
+    /// Size in bytes of the buffer to allocate, 0 for textures and samplers.
+    pub buffer_size: u64,
+}
+
+impl ShaderBinding {
+    /// Format of the texture allocated for this binding.
+    pub fn texture_format(&self) -> Option<TextureFormat> {
+        match self.ty {
+            BindingType::StorageTexture { format, .. } => Some(format),
+            BindingType::Texture { sample_type, .. } => Some(match sample_type {
+                TextureSampleType::Sint => TextureFormat::Rgba8Sint,
+                TextureSampleType::Uint => TextureFormat::Rgba8Uint,
+                _ => TextureFormat::Rgba8Unorm,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Size of the texture allocated for this binding: the size of the state textures, or a
+    /// single row of it for 1D textures.
+    pub fn texture_size(&self, size: UVec2) -> UVec2 {
+        match self.texture_dimension() {
+            TextureDimension::D1 => UVec2::new(size.x, 1),
+            _ => size,
+        }
+    }
+
+    fn texture_dimension(&self) -> TextureDimension {
+        match self.ty {
+            BindingType::StorageTexture {
+                view_dimension: TextureViewDimension::D1,
+                ..
+            }
+            | BindingType::Texture {
+                view_dimension: TextureViewDimension::D1,
+                ..
+            } => TextureDimension::D1,
+            _ => TextureDimension::D2,
+        }
+    }
+}
+
+/// Which of the playground's shaders a module is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderTarget {
+    /// The main shader, which writes the displayed state textures.
+    Main,
+    /// The shader of the pass at this index in [`PlaygroundConfig::passes`].
+    Pass(usize),
+}
+
+impl ShaderTarget {
+    fn entry_points(self, config: &PlaygroundConfig) -> Vec<&str> {
+        match self {
+            ShaderTarget::Main => vec![INIT_ENTRY_POINT, UPDATE_ENTRY_POINT],
+            ShaderTarget::Pass(index) => vec![config.passes[index].entry_point.as_str()],
+        }
+    }
+
+    /// Size of the textures bound as `input` and `output`, which the shader is dispatched over.
+    fn size(self, config: &PlaygroundConfig) -> UVec2 {
+        match self {
+            ShaderTarget::Main => config.size,
+            ShaderTarget::Pass(index) => config.passes[index].size.unwrap_or(config.size),
+        }
+    }
+
+    /// Format of the textures bound as `input` and `output`. A pass's `input` and `output` are
+    /// its own textures of the previous and the current frame.
+    pub fn state_format(self, config: &PlaygroundConfig) -> StorageFormat {
+        match self {
+            ShaderTarget::Main => config.format,
+            ShaderTarget::Pass(index) => config.passes[index].format,
+        }
+    }
+}
+
+/// Check everything the compute pipeline needs from a validated module, and reflect its
+/// bindings sorted by binding number.
+pub fn reflect(
+    module: &Module,
+    config: &PlaygroundConfig,
+    target: ShaderTarget,
+    limits: &WgpuLimits,
+) -> Result<Vec<ShaderBinding>, Vec<String>> {
+    let mut problems = Vec::new();
+    for name in target.entry_points(config) {
+        match module.entry_points.iter().find(|ep| ep.name == name) {
+            None => problems.push(format!("no entry point named `{name}`")),
+            Some(ep) if ep.stage != naga::ShaderStage::Compute => problems.push(format!(
+                "`{name}` is a {:?} entry point, the pipeline needs @compute",
+                ep.stage
+            )),
+            Some(_) => {}
+        }
+    }
+
+    let mut bindings: Vec<ShaderBinding> = Vec::new();
+    for (_, global) in module.global_variables.iter() {
+        let Some(binding) = &global.binding else {
+            continue;
+        };
+        let name = global.name.clone().unwrap_or_default();
+        let reflected = if binding.group != 0 {
+            Err("the pipeline only has @group(0)".to_string())
+        } else if bindings.iter().any(|b| b.binding == binding.binding) {
+            Err("the binding is declared twice".to_string())
+        } else {
+            reflect_binding(module, global, &name, config, target, limits)
+        };
+        match reflected {
+            Ok((source, ty, buffer_size)) => bindings.push(ShaderBinding {
+                binding: binding.binding,
+                name,
+                source,
+                ty,
+                buffer_size,
+            }),
+            Err(problem) => problems.push(format!(
+                "`{name}` at @group({}) @binding({}): {problem}",
+                binding.group, binding.binding
+            )),
+        }
+    }
+
+    if problems.is_empty() {
+        problems = check_sampling(module, &target.entry_points(config), &mut bindings);
+    }
+    // Problems with the workgroup size itself are reported when dispatching.
+    let workgroup_size = config.dispatch_shape.workgroup_size(config.workgroup_size);
+    if problems.is_empty()
+        && let Ok(plan) = DispatchPlan::new(target.size(config).extend(1), workgroup_size, limits)
+        && let Err(err) = plan.check_offset(has_dynamic_offset(&bindings))
+    {
+        problems.push(err.to_string());
+    }
+    if problems.is_empty() {
+        bindings.sort_by_key(|binding| binding.binding);
+        Ok(bindings)
+    } else {
+        Err(problems)
+    }
+}
+
+/// Filtering samplers can only sample filterable textures, and not every format is filterable.
+/// Allocated samplers that sample such a texture are made non-filtering; any other filtering
+/// sampler that does is a problem.
+fn check_sampling(
+    module: &Module,
+    entry_points: &[&str],
+    bindings: &mut [ShaderBinding],
+) -> Vec<String> {
+    // The module has been validated already, this only collects what its entry points sample.
+    let Ok(info) = Validator::new(ValidationFlags::all(), Capabilities::all()).validate(module)
+    else {
+        return Vec::new();
+    };
+    let index = |global: naga::Handle<GlobalVariable>| {
+        let binding = module.global_variables[global].binding.as_ref()?;
+        bindings.iter().position(|b| b.binding == binding.binding)
+    };
+    let pairs: Vec<_> = module
+        .entry_points
+        .iter()
+        .enumerate()
+        .filter(|(_, entry_point)| entry_points.contains(&entry_point.name.as_str()))
+        .flat_map(|(i, _)| &info.get_entry_point(i).sampling_set)
+        .filter_map(|key| Some((index(key.image)?, index(key.sampler)?)))
+        .collect();
+
+    let mut problems = Vec::new();
+    for (image, sampler) in pairs {
+        let unfilterable = matches!(
+            bindings[image].ty,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                ..
+            }
+        );
+        if !unfilterable
+            || bindings[sampler].ty != BindingType::Sampler(SamplerBindingType::Filtering)
+        {
+            continue;
+        }
+        if bindings[sampler].source == BindingSource::Allocated {
+            bindings[sampler].ty = BindingType::Sampler(SamplerBindingType::NonFiltering);
+        } else {
+            problems.push(format!(
+                "`{}` filters, but samples `{}`, whose format isn't filterable",
+                bindings[sampler].name, bindings[image].name
+            ));
+        }
+    }
+    problems
+}
+
+/// Whether the bindings include the `dispatch` offset, which takes a dynamic offset.
+pub fn has_dynamic_offset(bindings: &[ShaderBinding]) -> bool {
+    bindings
+        .iter()
+        .any(|binding| binding.source == BindingSource::Dispatch)
+}
+
+/// The bind group layout for reflected bindings.
+pub fn layout_entries(bindings: &[ShaderBinding]) -> Vec<BindGroupLayoutEntry> {
+    bindings
+        .iter()
+        .map(|binding| BindGroupLayoutEntry {
+            binding: binding.binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: binding.ty,
+            count: None,
+        })
+        .collect()
+}
+
+fn reflect_binding(
+    module: &Module,
+    global: &GlobalVariable,
+    name: &str,
+    config: &PlaygroundConfig,
+    target: ShaderTarget,
+    limits: &WgpuLimits,
+) -> Result<(BindingSource, BindingType, u64), String> {
+    let source = BindingSource::from_name(name, config);
+    let (ty, buffer_size) = binding_type(module, global, config.size, limits)?;
+    let declared = || describe_global(module, global);
+    let texture = |format: TextureFormat, write: bool| {
+        texture_binding(ty, format, write).ok_or_else(|| {
+            format!(
+                "the playground binds {} here, but the shader declares {}",
+                texture_types(format, write),
+                declared()
+            )
+        })
+    };
+    let float_texture = |ty: &BindingType| {
+        matches!(
+            ty,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { .. },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            }
+        )
+    };
+    let uniform = |max: u64| match ty {
+        BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            ..
+        } if buffer_size > max => Err(format!(
+            "{} is {buffer_size} bytes, but the playground binds {max} bytes; \
+             does it match the Rust struct?",
+            declared()
+        )),
+        BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            ..
+        } => Ok(()),
+        _ => Err(format!(
+            "the playground binds a uniform buffer here, but the shader declares {}",
+            declared()
+        )),
+    };
+    let expect = |matches: bool, expected: &str| {
+        if matches {
+            Ok(())
+        } else {
+            Err(format!(
+                "the playground binds {expected} here, but the shader declares {}",
+                declared()
+            ))
+        }
+    };
+
+    match source {
+        BindingSource::Input => {
+            return Ok((
+                source,
+                texture(target.state_format(config).texture_format(), false)?,
+                0,
+            ));
+        }
+        BindingSource::Output => {
+            return Ok((
+                source,
+                texture(target.state_format(config).texture_format(), true)?,
+                0,
+            ));
+        }
+        BindingSource::Pass { index, previous } => {
+            let pass = &config.passes[index];
+            if let ShaderTarget::Pass(reader) = target {
+                let reader = &config.passes[reader];
+                let listed = if previous {
+                    &reader.feedback
+                } else {
+                    &reader.reads
+                };
+                if reader.name == pass.name && !previous {
+                    return Err("a pass binds the texture it writes as `output`".to_string());
+                }
+                if reader.name != pass.name && !listed.contains(&pass.name) {
+                    let list = if previous { "feedback" } else { "reads" };
+                    return Err(format!(
+                        "`{}` doesn't list `{}` in its `{list}`",
+                        reader.name, pass.name
+                    ));
+                }
+            }
+            return Ok((source, texture(pass.format.texture_format(), false)?, 0));
+        }
+        BindingSource::Keyboard => expect(float_texture(&ty), "texture_2d<f32>")?,
+        BindingSource::Texture(index) => {
+            expect(float_texture(&ty), "texture_2d<f32>")?;
+            let ty = BindingType::Texture {
+                sample_type: config.textures[index].sample_type(),
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            };
+            return Ok((source, ty, 0));
+        }
+        BindingSource::TextureSampler(index) => {
+            expect(
+                ty == BindingType::Sampler(SamplerBindingType::Filtering),
+                "sampler",
+            )?;
+            let ty = BindingType::Sampler(config.textures[index].sampler_type());
+            return Ok((source, ty, 0));
+        }
+        BindingSource::Globals => uniform(ComputeShaderGlobals::min_size().get())?,
+        BindingSource::Dispatch => {
+            uniform(DispatchOffset::min_size().get())?;
+            let ty = BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: NonZeroU64::new(buffer_size),
+            };
+            return Ok((source, ty, buffer_size));
+        }
+        // The buffer is sized for the largest `params` of any shader.
+        BindingSource::Params => uniform(u64::MAX)?,
+        BindingSource::Allocated | BindingSource::Copy(_) => match ty {
+            BindingType::Texture {
+                sample_type: TextureSampleType::Depth,
+                ..
+            } => return Err("depth textures can't be allocated".to_string()),
+            BindingType::Texture {
+                multisampled: true, ..
+            } => return Err("multisampled textures can't be allocated".to_string()),
+            BindingType::Texture { view_dimension, .. }
+            | BindingType::StorageTexture { view_dimension, .. }
+                if !matches!(
+                    view_dimension,
+                    TextureViewDimension::D1 | TextureViewDimension::D2
+                ) =>
+            {
+                return Err(format!(
+                    "only 1D and 2D textures can be allocated, not {}",
+                    declared()
+                ));
+            }
+            _ => {}
+        },
+    }
+    Ok((source, ty, buffer_size))
+}
+
+/// The binding type a global is declared with, and the size of its buffer if it is one.
+fn binding_type(
+    module: &Module,
+    global: &GlobalVariable,
+    size: UVec2,
+    limits: &WgpuLimits,
+) -> Result<(BindingType, u64), String> {
+    let inner = &module.types[global.ty].inner;
+    let ty = match inner {
+        naga::TypeInner::Image {
+            dim,
+            arrayed,
+            class,
+        } => {
+            let view_dimension = match (dim, arrayed) {
+                (ImageDimension::D1, false) => TextureViewDimension::D1,
+                (ImageDimension::D2, false) => TextureViewDimension::D2,
+                (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+                (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+                (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+                (ImageDimension::D3, false) => TextureViewDimension::D3,
+                _ => {
+                    return Err(format!(
+                        "{} can't be bound",
+                        describe_global(module, global)
+                    ));
+                }
+            };
+            match class {
+                ImageClass::Sampled { kind, multi } => BindingType::Texture {
+                    sample_type: match kind {
+                        ScalarKind::Float => TextureSampleType::Float { filterable: true },
+                        ScalarKind::Sint => TextureSampleType::Sint,
+                        ScalarKind::Uint => TextureSampleType::Uint,
+                        _ => {
+                            return Err(format!(
+                                "{} can't be bound",
+                                describe_global(module, global)
+                            ));
+                        }
+                    },
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                ImageClass::Depth { multi } => BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                ImageClass::Storage { format, access } => BindingType::StorageTexture {
+                    access: match (
+                        access.contains(naga::StorageAccess::LOAD),
+                        access.contains(naga::StorageAccess::STORE),
+                    ) {
+                        (true, true) => StorageTextureAccess::ReadWrite,
+                        (true, false) => StorageTextureAccess::ReadOnly,
+                        _ => StorageTextureAccess::WriteOnly,
+                    },
+                    format: storage_format(*format),
+                    view_dimension,
+                },
+            }
+        }
+        naga::TypeInner::Sampler { comparison } => BindingType::Sampler(if *comparison {
+            SamplerBindingType::Comparison
+        } else {
+            SamplerBindingType::Filtering
+        }),
+        _ => {
+            let min_size = inner.size(module.to_ctx()) as u64;
+            let ty = match global.space {
+                AddressSpace::Uniform => BufferBindingType::Uniform,
+                AddressSpace::Storage { access } => BufferBindingType::Storage {
+                    read_only: !access.contains(naga::StorageAccess::STORE),
+                },
+                _ => {
+                    return Err(format!(
+                        "{} can't be bound",
+                        describe_global(module, global)
+                    ));
+                }
+            };
+            // Runtime-sized arrays get one element per texel of the state textures.
+            let buffer_size = match runtime_array_stride(module, global.ty) {
+                Some(stride) => {
+                    min_size + stride as u64 * (size.element_product() as u64).saturating_sub(1)
+                }
+                None => min_size,
+            };
+            let max = limits.max_storage_buffer_binding_size as u64;
+            if buffer_size > max && matches!(ty, BufferBindingType::Storage { .. }) {
+                return Err(format!(
+                    "{} takes {buffer_size} bytes with one element per texel of the state \
+                     textures, more than the {max} bytes a storage buffer binding can have",
+                    describe_global(module, global)
+                ));
+            }
+            return Ok((
+                BindingType::Buffer {
+                    ty,
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(min_size),
+                },
+                buffer_size,
+            ));
+        }
+    };
+    Ok((ty, 0))
+}
+
+/// The binding type for textures of `format` if `ty` can bind them: either sampled, or as a
+/// storage texture of the same format that's read-only unless the shader `write`s it, and then
+/// isn't. Sampled textures take the sample type of the format, since not every format is
+/// filterable.
+fn texture_binding(ty: BindingType, format: TextureFormat, write: bool) -> Option<BindingType> {
+    match ty {
+        BindingType::StorageTexture {
+            access,
+            format: declared,
+            view_dimension: TextureViewDimension::D2,
+        } if declared == format && write == (access != StorageTextureAccess::ReadOnly) => Some(ty),
+        BindingType::Texture {
+            sample_type,
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        } if !write => {
+            let format_type = format.sample_type(None, None)?;
+            (mem::discriminant(&sample_type) == mem::discriminant(&format_type)).then_some(
+                BindingType::Texture {
+                    sample_type: format_type,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+            )
+        }
+        _ => None,
+    }
+}
+
+/// The WGSL types [`texture_binding`] accepts.
+fn texture_types(format: TextureFormat, write: bool) -> String {
+    let wgsl_format = format!("{format:?}").to_lowercase();
+    if write {
+        return format!("texture_storage_2d<{wgsl_format}, write>");
+    }
+    let scalar = match format.sample_type(None, None) {
+        Some(TextureSampleType::Uint) => "u32",
+        Some(TextureSampleType::Sint) => "i32",
+        _ => "f32",
+    };
+    format!("texture_storage_2d<{wgsl_format}, read> or texture_2d<{scalar}>")
+}
+
+/// Stride of the runtime-sized array at the end of a type, if it has one.
+fn runtime_array_stride(module: &Module, ty: naga::Handle<naga::Type>) -> Option<u32> {
+    match &module.types[ty].inner {
+        naga::TypeInner::Array {
+            size: naga::ArraySize::Dynamic,
+            stride,
+            ..
+        } => Some(*stride),
+        naga::TypeInner::Struct { members, .. } => members
+            .last()
+            .and_then(|member| runtime_array_stride(module, member.ty)),
+        _ => None,
+    }
+}
+
+/// The WGSL type of a global. naga's WGSL names drop the `storage` in storage texture types,
+/// so those are spelled out here.
+fn describe_global(module: &Module, global: &GlobalVariable) -> String {
+    match &module.types[global.ty].inner {
+        naga::TypeInner::Image {
+            dim,
+            arrayed,
+            class: ImageClass::Storage { format, access },
+        } => {
+            let dim = match dim {
+                ImageDimension::D1 => "1d",
+                ImageDimension::D2 => "2d",
+                ImageDimension::D3 => "3d",
+                ImageDimension::Cube => "cube",
+            };
+            let arrayed = if *arrayed { "_array" } else { "" };
+            let access = match (
+                access.contains(naga::StorageAccess::LOAD),
+                access.contains(naga::StorageAccess::STORE),
+            ) {
+                (true, true) => "read_write",
+                (true, false) => "read",
+                _ => "write",
+            };
+            format!(
+                "texture_storage_{dim}{arrayed}<{}, {access}>",
+                format!("{format:?}").to_lowercase()
+            )
+        }
+        _ => {
+            let ty = global.ty.to_wgsl(&module.to_ctx());
+            match global.space {
+                AddressSpace::Uniform => format!("var<uniform> {ty}"),
+                AddressSpace::Storage { .. } => format!("var<storage> {ty}"),
+                _ => ty,
+            }
+        }
+    }
+}
+
+/// The texture format a WGSL storage format names.
+pub fn storage_format(format: naga::StorageFormat) -> TextureFormat {
+    use naga::StorageFormat as S;
+    match format {
+        S::R8Unorm => TextureFormat::R8Unorm,
+        S::R8Snorm => TextureFormat::R8Snorm,
+        S::R8Uint => TextureFormat::R8Uint,
+        S::R8Sint => TextureFormat::R8Sint,
+        S::R16Uint => TextureFormat::R16Uint,
+        S::R16Sint => TextureFormat::R16Sint,
+        S::R16Float => TextureFormat::R16Float,
+        S::Rg8Unorm => TextureFormat::Rg8Unorm,
+        S::Rg8Snorm => TextureFormat::Rg8Snorm,
+        S::Rg8Uint => TextureFormat::Rg8Uint,
+        S::Rg8Sint => TextureFormat::Rg8Sint,
+        S::R32Uint => TextureFormat::R32Uint,
+        S::R32Sint => TextureFormat::R32Sint,
+        S::R32Float => TextureFormat::R32Float,
+        S::Rg16Uint => TextureFormat::Rg16Uint,
+        S::Rg16Sint => TextureFormat::Rg16Sint,
+        S::Rg16Float => TextureFormat::Rg16Float,
+        S::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+        S::Rgba8Snorm => TextureFormat::Rgba8Snorm,
+        S::Rgba8Uint => TextureFormat::Rgba8Uint,
+        S::Rgba8Sint => TextureFormat::Rgba8Sint,
+        S::Bgra8Unorm => TextureFormat::Bgra8Unorm,
+        S::Rgb10a2Uint => TextureFormat::Rgb10a2Uint,
+        S::Rgb10a2Unorm => TextureFormat::Rgb10a2Unorm,
+        S::Rg11b10Ufloat => TextureFormat::Rg11b10Ufloat,
+        S::R64Uint => TextureFormat::R64Uint,
+        S::Rg32Uint => TextureFormat::Rg32Uint,
+        S::Rg32Sint => TextureFormat::Rg32Sint,
+        S::Rg32Float => TextureFormat::Rg32Float,
+        S::Rgba16Uint => TextureFormat::Rgba16Uint,
+        S::Rgba16Sint => TextureFormat::Rgba16Sint,
+        S::Rgba16Float => TextureFormat::Rgba16Float,
+        S::Rgba32Uint => TextureFormat::Rgba32Uint,
+        S::Rgba32Sint => TextureFormat::Rgba32Sint,
+        S::Rgba32Float => TextureFormat::Rgba32Float,
+        S::R16Unorm => TextureFormat::R16Unorm,
+        S::R16Snorm => TextureFormat::R16Snorm,
+        S::Rg16Unorm => TextureFormat::Rg16Unorm,
+        S::Rg16Snorm => TextureFormat::Rg16Snorm,
+        S::Rgba16Unorm => TextureFormat::Rgba16Unorm,
+        S::Rgba16Snorm => TextureFormat::Rgba16Snorm,
+    }
+}
+
+/// The shader assets, as loaded from the configured paths.
+#[derive(Resource)]
+struct ComputeShaderSource {
+    main: Handle<Shader>,
+    passes: Vec<Handle<Shader>>,
+}
+
+impl ComputeShaderSource {
+    fn handles(&self) -> impl Iterator<Item = (ShaderTarget, &Handle<Shader>)> {
+        let passes = self.passes.iter().enumerate();
+        let passes = passes.map(|(index, handle)| (ShaderTarget::Pass(index), handle));
+        [(ShaderTarget::Main, &self.main)].into_iter().chain(passes)
+    }
+}
+
+/// A shader as of its last successful reflection.
+#[derive(Clone, Default)]
+pub struct ReflectedShader {
+    /// A copy of the shader that was reflected, `None` until it first loads. The pipelines
+    /// compile the copy, so a later edit can't change the shader under a layout that no longer
+    /// fits it.
+    pub shader: Option<Handle<Shader>>,
+    pub bindings: Vec<ShaderBinding>,
+    /// The parameters the shader declares, if it binds `params`.
+    pub params: Option<ParamsLayout>,
+    /// Why the latest version of the shader couldn't be reflected. The previous copy keeps
+    /// running until it's fixed.
+    pub error: Option<ShaderDiagnostic>,
+}
+
+/// The main shader and the shaders of the passes, indexed like [`PlaygroundConfig::passes`].
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct ComputeShaderLayout {
+    pub main: ReflectedShader,
+    pub passes: Vec<ReflectedShader>,
+}
+
+impl ComputeShaderLayout {
+    fn get_mut(&mut self, target: ShaderTarget) -> &mut ReflectedShader {
+        match target {
+            ShaderTarget::Main => &mut self.main,
+            ShaderTarget::Pass(index) => &mut self.passes[index],
+        }
+    }
+
+    /// The first reflection error of any shader.
+    pub fn error(&self) -> Option<ShaderDiagnostic> {
+        let mut shaders = [&self.main].into_iter().chain(&self.passes);
+        shaders.find_map(|shader| shader.error.clone())
+    }
+}
+
+pub struct ShaderBindingsPlugin;
+
+impl Plugin for ShaderBindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ComputeShaderLayout>()
+            .add_plugins(ExtractResourcePlugin::<ComputeShaderLayout>::default())
+            .add_systems(Startup, load_shaders)
+            .add_systems(Update, reflect_shaders);
+    }
+}
+
+fn load_shaders(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<PlaygroundConfig>,
+    mut layout: ResMut<ComputeShaderLayout>,
+) {
+    commands.insert_resource(ComputeShaderSource {
+        main: asset_server.load(config.shader.clone()),
+        passes: config
+            .passes
+            .iter()
+            .map(|pass| asset_server.load(pass.shader.clone()))
+            .collect(),
+    });
+    layout
+        .passes
+        .resize_with(config.passes.len(), Default::default);
+}
+
+/// Reflect each shader whenever it (re)loads.
+fn reflect_shaders(
+    mut events: EventReader<AssetEvent<Shader>>,
+    source: Res<ComputeShaderSource>,
+    mut shaders: ResMut<Assets<Shader>>,
+    config: Res<PlaygroundConfig>,
+    support: Option<Res<StorageSupport>>,
+    mut layout: ResMut<ComputeShaderLayout>,
+) {
+    let mut changed = Vec::new();
+    for event in events.read() {
+        for (target, handle) in source.handles() {
+            if (event.is_loaded_with_dependencies(handle) || event.is_modified(handle))
+                && !changed.contains(&target)
+            {
+                changed.push(target);
+            }
+        }
+    }
+
+    for target in changed {
+        let handle = source.handles().find(|(t, _)| *t == target).unwrap().1;
+        let Some(shader) = shaders.get(handle) else {
+            continue;
+        };
+        let path = shader.path.clone();
+        let reflected = reflect_shader(shader, &config, target, support.as_deref());
+        let layout = layout.get_mut(target);
+        match reflected {
+            Ok((wgsl, bindings, params)) => {
+                for binding in &bindings {
+                    if let BindingSource::Copy(of) = binding.source {
+                        let texture = bindings.iter().find(|b| b.binding == of).unwrap();
+                        info!(
+                            "{}: `{}` is bound write-only, its loads read `{}` instead",
+                            path, texture.name, binding.name
+                        );
+                    }
+                }
+                layout.shader = Some(shaders.add(Shader::from_wgsl(wgsl, path)));
+                layout.bindings = bindings;
+                layout.params = params;
+                layout.error = None;
+            }
+            Err(diagnostic) => layout.error = Some(diagnostic),
+        }
+    }
+}
+
+/// The source the pipelines compile for a shader asset, its bindings and its parameters. With the
+/// adapter's [`StorageSupport`], `read_write` storage textures it can't bind are rewritten to load
+/// from a copy.
+fn reflect_shader(
+    shader: &Shader,
+    config: &PlaygroundConfig,
+    target: ShaderTarget,
+    support: Option<&StorageSupport>,
+) -> Result<(String, Vec<ShaderBinding>, Option<ParamsLayout>), ShaderDiagnostic> {
+    let path = &shader.path;
+    let Source::Wgsl(wgsl) = &shader.source else {
+        return Err(ShaderDiagnostic {
+            path: path.clone(),
+            line: 0,
+            column: 0,
+            message: "the compute shader must be WGSL".to_string(),
+        });
+    };
+    // Shadertoy mode only wraps the main shader.
+    let wgsl = match target {
+        ShaderTarget::Main => config.mode.expand(wgsl, &config.channels).into_owned(),
+        ShaderTarget::Pass(_) => wgsl.to_string(),
+    };
+    let format = target.state_format(config);
+    let wgsl = expand_state_format(&wgsl, format).into_owned();
+    let mut module = compose_module(&wgsl, path, &config.shader_defs(format))?;
+    let diagnostic = |problems: Vec<String>| ShaderDiagnostic {
+        path: path.clone(),
+        line: 0,
+        column: 0,
+        message: problems.join("\n"),
+    };
+    let copies = match support {
+        Some(support) => {
+            copy_read_write(&mut module, |format| support.flags(format)).map_err(diagnostic)?
+        }
+        None => None,
+    };
+    let limits = support.map_or_else(WgpuLimits::default, StorageSupport::limits);
+    let mut bindings = reflect(&module, config, target, &limits).map_err(diagnostic)?;
+    let params = reflect_params(&module, &wgsl).map_err(diagnostic)?;
+    let Some(copies) = copies else {
+        return Ok((wgsl, bindings, params));
+    };
+    for (of, copy) in copies {
+        let binding = bindings.iter_mut().find(|b| b.binding == copy).unwrap();
+        binding.source = BindingSource::Copy(of);
+    }
+    // The rewritten module has been composed already, so its WGSL has no imports or defs left.
+    let wgsl = write_wgsl(&module).map_err(|problem| diagnostic(vec![problem]))?;
+    Ok((wgsl, bindings, params))
+}
+
+/// The resources a bind group can bind, by [`BindingSource`].
+pub struct BoundResources<'a> {
+    pub input: &'a GpuImage,
+    pub output: &'a GpuImage,
+    pub globals: BindingResource<'a>,
+    pub keyboard: &'a TextureView,
+    pub dispatch: BindingResource<'a>,
+    pub params: BindingResource<'a>,
+    pub allocated: &'a AllocatedBindings,
+    /// The current and previous texture of every pass.
+    pub passes: &'a [[&'a GpuImage; 2]],
+    pub textures: &'a [&'a TextureView],
+    pub texture_samplers: &'a [Sampler],
+}
+
+impl<'a> BoundResources<'a> {
+    /// The bind group entries for reflected bindings.
+    pub fn entries(&self, bindings: &[ShaderBinding]) -> Vec<BindGroupEntry<'a>> {
+        bindings
+            .iter()
+            .map(|binding| BindGroupEntry {
+                binding: binding.binding,
+                resource: match binding.source {
+                    BindingSource::Input => BindingResource::TextureView(&self.input.texture_view),
+                    BindingSource::Output => {
+                        BindingResource::TextureView(&self.output.texture_view)
+                    }
+                    BindingSource::Globals => self.globals.clone(),
+                    BindingSource::Keyboard => BindingResource::TextureView(self.keyboard),
+                    BindingSource::Dispatch => self.dispatch.clone(),
+                    BindingSource::Params => self.params.clone(),
+                    BindingSource::Pass { index, previous } => BindingResource::TextureView(
+                        &self.passes[index][previous as usize].texture_view,
+                    ),
+                    BindingSource::Texture(index) => {
+                        BindingResource::TextureView(self.textures[index])
+                    }
+                    BindingSource::TextureSampler(index) => {
+                        BindingResource::Sampler(&self.texture_samplers[index])
+                    }
+                    BindingSource::Allocated | BindingSource::Copy(_) => {
+                        self.allocated.binding(binding.binding).unwrap()
+                    }
+                },
+            })
+            .collect()
+    }
+
+    /// The bind group for reflected bindings, with the copies its dispatches need.
+    pub fn bind_group(
+        &self,
+        layout: &BindGroupLayout,
+        bindings: &[ShaderBinding],
+        device: &RenderDevice,
+    ) -> StepBindGroup {
+        StepBindGroup {
+            bind_group: device.create_bind_group(None, layout, &self.entries(bindings)),
+            copies: self.copies(bindings),
+        }
+    }
+
+    /// The copies to make before each dispatch, into the [`BindingSource::Copy`] bindings.
+    fn copies(&self, bindings: &[ShaderBinding]) -> Vec<TextureCopy> {
+        bindings
+            .iter()
+            .filter_map(|binding| {
+                let BindingSource::Copy(of) = binding.source else {
+                    return None;
+                };
+                let source = match bindings.iter().find(|b| b.binding == of)?.source {
+                    BindingSource::Input => &self.input.texture,
+                    BindingSource::Output => &self.output.texture,
+                    BindingSource::Pass { index, previous } => {
+                        &self.passes[index][previous as usize].texture
+                    }
+                    BindingSource::Allocated => self.allocated.texture(of)?,
+                    _ => return None,
+                };
+                Some(TextureCopy {
+                    source: source.clone(),
+                    destination: self.allocated.texture(binding.binding)?.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The bind group of one step of a shader.
+pub struct StepBindGroup {
+    pub bind_group: BindGroup,
+    /// Made before each dispatch, see [`crate::storage`].
+    pub copies: Vec<TextureCopy>,
+}
+
+impl StepBindGroup {
+    /// Make the copies, and dispatch `pipeline` in a compute pass of its own.
+    pub fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline: &ComputePipeline,
+        dispatch: &ComputeShaderDispatch,
+        dynamic_offset: bool,
+        timestamp_writes: Option<ComputePassTimestampWrites>,
+    ) {
+        for copy in &self.copies {
+            copy.record(encoder);
+        }
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: None,
+            timestamp_writes,
+        });
+        pass.set_pipeline(pipeline);
+        dispatch.record(&mut pass, &self.bind_group, dynamic_offset);
+    }
+}
+
+/// A resource allocated for an [`BindingSource::Allocated`] binding.
+enum Allocation {
+    Texture(Texture, TextureView),
+    Buffer(Buffer),
+    Sampler(Sampler),
+}
+
+/// The zero-initialized resources behind the bindings the playground doesn't provide itself.
+#[derive(Resource, Default)]
+pub struct AllocatedBindings {
+    allocations: HashMap<u32, (BindingType, u64, Allocation)>,
+}
+
+impl AllocatedBindings {
+    /// Allocate resources for `bindings`. Resources whose binding is declared the same way as
+    /// before are kept, so editing the shader doesn't reset them.
+    pub fn allocate(&mut self, bindings: &[ShaderBinding], size: UVec2, device: &RenderDevice) {
+        let mut allocations = HashMap::new();
+        for binding in bindings {
+            if !matches!(
+                binding.source,
+                BindingSource::Allocated | BindingSource::Copy(_)
+            ) {
+                continue;
+            }
+            let allocation = match self.allocations.remove(&binding.binding) {
+                Some(existing) if existing.0 == binding.ty && existing.1 == binding.buffer_size => {
+                    existing
+                }
+                _ => (
+                    binding.ty,
+                    binding.buffer_size,
+                    allocate(binding, size, device),
+                ),
+            };
+            allocations.insert(binding.binding, allocation);
+        }
+        self.allocations = allocations;
+    }
+
+    pub fn binding(&self, binding: u32) -> Option<BindingResource<'_>> {
+        Some(match &self.allocations.get(&binding)?.2 {
+            Allocation::Texture(_, view) => BindingResource::TextureView(view),
+            Allocation::Buffer(buffer) => buffer.as_entire_binding(),
+            Allocation::Sampler(sampler) => BindingResource::Sampler(sampler),
+        })
+    }
+
+    /// Zero every allocated texture and buffer.
+    pub fn clear(&self, encoder: &mut CommandEncoder) {
+        for (_, _, allocation) in self.allocations.values() {
+            match allocation {
+                Allocation::Texture(texture, _) => {
+                    encoder.clear_texture(texture, &ImageSubresourceRange::default());
+                }
+                Allocation::Buffer(buffer) => encoder.clear_buffer(buffer, 0, None),
+                Allocation::Sampler(_) => {}
+            }
+        }
+    }
+
+    fn texture(&self, binding: u32) -> Option<&Texture> {
+        match &self.allocations.get(&binding)?.2 {
+            Allocation::Texture(texture, _) => Some(texture),
+            _ => None,
+        }
+    }
+}
+
+fn allocate(binding: &ShaderBinding, size: UVec2, device: &RenderDevice) -> Allocation {
+    let label = Some(binding.name.as_str());
+    match binding.ty {
+        BindingType::Buffer { ty, .. } => {
+            let usage = match ty {
+                BufferBindingType::Uniform => BufferUsages::UNIFORM,
+                BufferBindingType::Storage { .. } => BufferUsages::STORAGE,
+            };
+            Allocation::Buffer(device.create_buffer(&BufferDescriptor {
+                label,
+                size: binding.buffer_size,
+                usage: usage | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }))
+        }
+        BindingType::Sampler(ty) => {
+            let filter = if ty == SamplerBindingType::NonFiltering {
+                FilterMode::Nearest
+            } else {
+                FilterMode::Linear
+            };
+            Allocation::Sampler(device.create_sampler(&SamplerDescriptor {
+                label,
+                mag_filter: filter,
+                min_filter: filter,
+                compare:
+                    (ty == SamplerBindingType::Comparison).then_some(CompareFunction::LessEqual),
+                ..Default::default()
+            }))
+        }
+        _ => {
+            let format = binding.texture_format().unwrap();
+            let size = binding.texture_size(size);
+            let mut usage =
+                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::COPY_SRC;
+            if matches!(binding.ty, BindingType::StorageTexture { .. }) {
+                usage |= TextureUsages::STORAGE_BINDING;
+            }
+            let texture = device.create_texture(&TextureDescriptor {
+                label,
+                size: Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: binding.texture_dimension(),
+                format,
+                usage,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            Allocation::Texture(texture, view)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::compose_module;
+
+    /// Reflect the main shader from WGSL source with the default config.
+    fn reflect_source(
+        source: &str,
+        limits: &WgpuLimits,
+    ) -> Result<Vec<ShaderBinding>, Vec<String>> {
+        let config = PlaygroundConfig::default();
+        let module =
+            compose_module(source, "test.wgsl", &config.shader_defs(config.format)).unwrap();
+        reflect(&module, &config, ShaderTarget::Main, limits)
+    }
+
+    /// A main shader that copies `input` to `output`, with `declarations` added.
+    fn state_shader(input_access: &str, declarations: &str) -> String {
+        format!(
+            "@group(0) @binding(0) var input: texture_storage_2d<rgba8unorm, {input_access}>;
+            @group(0) @binding(1) var output: texture_storage_2d<rgba8unorm, write>;
+            {declarations}
+
+            @compute @workgroup_size(8, 8, 1)
+            fn init(@builtin(global_invocation_id) id: vec3<u32>) {{
+                textureStore(output, id.xy, vec4<f32>(0.0));
+            }}
+
+            @compute @workgroup_size(8, 8, 1)
+            fn update(@builtin(global_invocation_id) id: vec3<u32>) {{
+                textureStore(output, id.xy, textureLoad(input, id.xy));
+            }}"
+        )
+    }
+
+    #[test]
+    fn read_textures_must_be_read_only() {
+        let limits = WgpuLimits::default();
+        assert!(reflect_source(&state_shader("read", ""), &limits).is_ok());
+        let problems = reflect_source(&state_shader("read_write", ""), &limits).unwrap_err();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("`input` at @group(0) @binding(0)"));
+    }
+
+    #[test]
+    fn runtime_arrays_must_fit_the_binding_limit() {
+        let config = PlaygroundConfig::default();
+        let texels = config.size.element_product() as u64;
+        let shader = state_shader(
+            "read",
+            "@group(0) @binding(2) var<storage, read_write> cells: array<vec4<f32>>;",
+        );
+        let limits = |max_storage_buffer_binding_size| WgpuLimits {
+            max_storage_buffer_binding_size,
+            ..WgpuLimits::default()
+        };
+
+        let bindings = reflect_source(&shader, &limits(16 * texels as u32)).unwrap();
+        assert_eq!(bindings[2].buffer_size, 16 * texels);
+        let problems = reflect_source(&shader, &limits(16 * texels as u32 - 1)).unwrap_err();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("`cells` at @group(0) @binding(2)"));
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use bevy::app::{App, Plugin, Update};
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::log::{error, info};
+use bevy::prelude::{Event, EventReader, EventWriter, IntoScheduleConfigs, Res, ResMut, Resource};
+use bevy::render::render_resource::TextureFormat;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{IoTaskPool, Task, block_on};
+use image::RgbaImage;
+
+use crate::config::PlaygroundConfig;
+use crate::export::{ExportFormat, save_texture};
+use crate::format::to_rgba8;
+use crate::globals::ComputeShaderGlobals;
+use crate::passes::{PassImages, swap_pass_images};
+use crate::readback::{
+    ReadbackId, ReadbackRequest, TextureReadback, TextureReadbackComplete, TextureReadbacks,
+};
+use crate::{ComputeShaderImage, swap_textures};
+
+/// Key that saves the current frame.
+const CAPTURE_KEY: KeyCode = KeyCode::F12;
+
+/// Name `capture_textures` gives the output of the compute shader.
+pub const OUTPUT_TEXTURE: &str = "output";
+
+/// Save the configured `capture_textures` of the current frame in the configured
+/// `capture_format`.
+#[derive(Event, Clone, Debug, Default)]
+pub struct CaptureFrame {
+    /// Where to write the output. `None` picks a numbered file in the configured capture
+    /// directory. Pass textures are written next to it, see [`pass_capture_path`].
+    pub path: Option<PathBuf>,
+}
+
+/// Sent once a texture of a [`CaptureFrame`] has been written to disk, or failed to.
+#[derive(Event, Debug)]
+pub struct FrameSaved {
+    pub path: PathBuf,
+    pub result: Result<(), CaptureError>,
+}
+
+/// Captures waiting for their readback, and the files being encoded.
+#[derive(Resource, Default)]
+pub struct PendingCaptures {
+    readbacks: HashMap<ReadbackId, (PathBuf, ExportFormat)>,
+    saving: Vec<Task<FrameSaved>>,
+    /// Numbered files that will be written but may not exist yet.
+    numbered: HashSet<PathBuf>,
+}
+
+impl PendingCaptures {
+    /// Whether every requested capture has been saved.
+    pub fn is_empty(&self) -> bool {
+        self.readbacks.is_empty() && self.saving.is_empty()
+    }
+
+    /// A file for a capture of `frame` in `dir` that doesn't exist and isn't about to be
+    /// written: `frame-000010.png`, then `frame-000010-2.png` and so on, so capturing a paused
+    /// frame again doesn't overwrite the earlier capture.
+    fn numbered_path(&mut self, dir: &Path, frame: u32, format: ExportFormat) -> PathBuf {
+        let extension = format.extension();
+        let path = (1..)
+            .map(|n| match n {
+                1 => dir.join(format!("frame-{frame:06}.{extension}")),
+                n => dir.join(format!("frame-{frame:06}-{n}.{extension}")),
+            })
+            .find(|path| !self.numbered.contains(path) && !path.exists())
+            .unwrap();
+        self.numbered.insert(path.clone());
+        path
+    }
+}
+
+pub struct CapturePlugin;
+
+impl Plugin for CapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CaptureFrame>()
+            .add_event::<FrameSaved>()
+            .init_resource::<PendingCaptures>()
+            .add_systems(
+                Update,
+                (
+                    capture_hotkey,
+                    request_captures
+                        .after(swap_textures)
+                        .after(swap_pass_images),
+                    save_captures,
+                    finish_saves,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn capture_hotkey(keys: Res<ButtonInput<KeyCode>>, mut captures: EventWriter<CaptureFrame>) {
+    if keys.just_pressed(CAPTURE_KEY) {
+        captures.write_default();
+    }
+}
+
+/// Where the texture of `pass` is captured to when the output is captured to `path`: next to
+/// it, with `-<pass>` added to its name.
+pub fn pass_capture_path(path: &Path, pass: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{pass}"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+fn request_captures(
+    mut captures: EventReader<CaptureFrame>,
+    image: Res<ComputeShaderImage>,
+    pass_images: Res<PassImages>,
+    config: Res<PlaygroundConfig>,
+    globals: Res<ComputeShaderGlobals>,
+    mut readbacks: ResMut<TextureReadbacks>,
+    mut pending: ResMut<PendingCaptures>,
+) {
+    let format = config.capture_format;
+    for capture in captures.read() {
+        let path = capture
+            .path
+            .clone()
+            .unwrap_or_else(|| pending.numbered_path(&config.capture_dir, globals.frame, format));
+        for name in &config.capture_textures {
+            let (texture, path) = if name == OUTPUT_TEXTURE {
+                (image.output.clone(), path.clone())
+            } else {
+                // The names are checked against the passes when loading.
+                let Some(pass) = config
+                    .passes
+                    .iter()
+                    .position(|pass| pass.name == *name)
+                    .and_then(|pass| pass_images.0.get(pass))
+                else {
+                    continue;
+                };
+                (pass.current.clone(), pass_capture_path(&path, name))
+            };
+            let id = readbacks.request(ReadbackRequest::new(texture));
+            pending.readbacks.insert(id, (path, format));
+        }
+    }
+}
+
+fn save_captures(
+    mut readbacks: EventReader<TextureReadbackComplete>,
+    mut pending: ResMut<PendingCaptures>,
+) {
+    for complete in readbacks.read() {
+        let Some((path, format)) = pending.readbacks.remove(&complete.id) else {
+            continue;
+        };
+        let readback = complete.readback.clone();
+        // Encode off the main thread so saving doesn't stall the frame.
+        let task = IoTaskPool::get().spawn(async move {
+            let result = save_texture(&readback, &path, format);
+            FrameSaved { path, result }
+        });
+        pending.saving.push(task);
+    }
+}
+
+fn finish_saves(mut pending: ResMut<PendingCaptures>, mut saved: EventWriter<FrameSaved>) {
+    let pending = &mut *pending;
+    pending.saving.retain_mut(|task| {
+        let Some(frame) = block_on(future::poll_once(task)) else {
+            return true;
+        };
+        match &frame.result {
+            Ok(()) => info!("Saved {}", frame.path.display()),
+            Err(err) => error!("Failed to save {}: {err}", frame.path.display()),
+        }
+        pending.numbered.remove(&frame.path);
+        saved.write(frame);
+        false
+    });
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    UnsupportedFormat(TextureFormat),
+    Io(io::Error),
+    Encode(image::ImageError),
+    Png(png::EncodingError),
+    Exr(exr::error::Error),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::UnsupportedFormat(format) => write!(f, "can't encode {format:?}"),
+            CaptureError::Io(err) => err.fmt(f),
+            CaptureError::Encode(err) => err.fmt(f),
+            CaptureError::Png(err) => err.fmt(f),
+            CaptureError::Exr(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+pub fn save_png(readback: &TextureReadback, path: &Path) -> Result<(), CaptureError> {
+    // Saved the way it's displayed.
+    let pixels = to_rgba8(readback.format, &readback.data)
+        .ok_or(CaptureError::UnsupportedFormat(readback.format))?;
+    let image = RgbaImage::from_raw(readback.size.x, readback.size.y, pixels)
+        .ok_or(CaptureError::UnsupportedFormat(readback.format))?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(CaptureError::Io)?;
+    }
+    image.save(path).map_err(CaptureError::Encode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_paths_skip_pending_and_existing_files() {
+        let dir = std::env::temp_dir().join(format!("capture-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("frame-000007-2.npy"), []).unwrap();
+
+        let mut pending = PendingCaptures::default();
+        let mut next = || pending.numbered_path(&dir, 7, ExportFormat::Npy);
+        assert_eq!(next(), dir.join("frame-000007.npy"));
+        assert_eq!(next(), dir.join("frame-000007-3.npy"));
+        assert_eq!(next(), dir.join("frame-000007-4.npy"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
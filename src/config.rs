@@ -0,0 +1,422 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use bevy::asset::io::file::FileAssetReader;
+use bevy::math::UVec2;
+use bevy::prelude::Resource;
+use bevy::render::render_resource::ShaderDefVal;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::OUTPUT_TEXTURE;
+use crate::dispatch::DispatchShape;
+use crate::export::ExportFormat;
+use crate::format::StorageFormat;
+use crate::passes::{PassConfig, pass_order};
+use crate::recording::RecordFormat;
+use crate::shadertoy::{CHANNEL_COUNT, ChannelInput, ShaderMode};
+use crate::textures::TextureInput;
+
+/// Playground settings, read from an optional RON file and overridden by command-line
+/// arguments.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaygroundConfig {
+    /// Asset path of the compute shader.
+    pub shader: String,
+    /// Whether `shader` is a compute shader or a Shadertoy-style `mainImage` function.
+    pub mode: ShaderMode,
+    /// What the `iChannel0` to `iChannel3` textures show. Missing channels are black.
+    pub channels: Vec<ChannelInput>,
+    /// Size of the state textures in texels.
+    pub size: UVec2,
+    /// Format of the state textures.
+    pub format: StorageFormat,
+    /// How many window pixels each texel covers.
+    pub display_factor: u32,
+    /// Edge length of a workgroup, passed to the shader as the `WORKGROUP_SIZE` def.
+    pub workgroup_size: u32,
+    /// Which workgroup dimensions use `workgroup_size`, passed to the shader as the
+    /// `WORKGROUP_SIZE_X`, `WORKGROUP_SIZE_Y` and `WORKGROUP_SIZE_Z` defs.
+    pub dispatch_shape: DispatchShape,
+    /// Directory that frame captures are written to.
+    pub capture_dir: PathBuf,
+    /// What captures and the frames saved by `headless` and `cpu` are written as, see
+    /// [`crate::export`].
+    pub capture_format: ExportFormat,
+    /// The state textures a capture saves: `output` for the output of the compute shader, or
+    /// the name of a pass for its texture.
+    pub capture_textures: Vec<String>,
+    /// Compute passes that run before `shader` every frame, each writing a texture of its own.
+    pub passes: Vec<PassConfig>,
+    /// Image files bound to the shaders as sampled textures.
+    pub textures: Vec<TextureInput>,
+    /// `update` steps per second of simulated time. Each step then advances `time` by exactly
+    /// `1 / tick_rate`, and every frame runs the steps that have come due since the last one,
+    /// so the simulation runs at the same rate at any frame rate. Without it every frame runs
+    /// one step as long as the frame.
+    pub tick_rate: Option<f64>,
+    /// Most steps a frame runs at `tick_rate` to catch up after a slow frame. Steps beyond it
+    /// are dropped, so the simulation falls behind instead of stalling.
+    pub max_catch_up_steps: u32,
+    /// Preset the shader parameters start from, by name, see [`crate::presets`].
+    pub preset: Option<String>,
+    /// Seconds it takes to crossfade to a preset picked with its key. 0 switches at once.
+    pub preset_crossfade: f32,
+    /// What recordings are saved as, see [`crate::recording`].
+    pub record_format: RecordFormat,
+    /// Record every this many frames that run `update`.
+    pub record_every: u32,
+    /// Frames after which a recording stops by itself.
+    pub record_max_frames: Option<u32>,
+    /// Frames per second of simulated time while recording, independent of how fast frames
+    /// render. Without it recordings follow the frame rate.
+    pub record_fps: Option<f64>,
+}
+
+impl Default for PlaygroundConfig {
+    fn default() -> Self {
+        Self {
+            shader: "shader.wgsl".to_string(),
+            mode: ShaderMode::Compute,
+            channels: Vec::new(),
+            size: UVec2::new(1280 / 4, 720 / 4),
+            format: StorageFormat::Rgba8Unorm,
+            display_factor: 4,
+            workgroup_size: 8,
+            dispatch_shape: DispatchShape::D2,
+            capture_dir: PathBuf::from("captures"),
+            capture_format: ExportFormat::Png,
+            capture_textures: vec![OUTPUT_TEXTURE.to_string()],
+            passes: Vec::new(),
+            textures: Vec::new(),
+            tick_rate: None,
+            max_catch_up_steps: 8,
+            preset: None,
+            preset_crossfade: 1.0,
+            record_format: RecordFormat::Png,
+            record_every: 1,
+            record_max_frames: None,
+            record_fps: None,
+        }
+    }
+}
+
+#[derive(Args, Debug, Default)]
+pub struct ConfigArgs {
+    /// RON file with a `PlaygroundConfig`; command-line arguments override its values.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Asset path of the compute shader.
+    #[arg(long)]
+    pub shader: Option<String>,
+    /// Whether the shader is a compute shader or a Shadertoy-style `mainImage` function.
+    #[arg(long, value_enum)]
+    pub mode: Option<ShaderMode>,
+    /// What `iChannel0`, `iChannel1`, ... show, separated by commas.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub channels: Option<Vec<ChannelInput>>,
+    /// Width of the state textures in texels.
+    #[arg(long)]
+    pub width: Option<u32>,
+    /// Height of the state textures in texels.
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// Format of the state textures.
+    #[arg(long, value_enum)]
+    pub format: Option<StorageFormat>,
+    /// How many window pixels each texel covers.
+    #[arg(long)]
+    pub display_factor: Option<u32>,
+    /// Edge length of a compute workgroup.
+    #[arg(long)]
+    pub workgroup_size: Option<u32>,
+    /// Number of workgroup dimensions that span more than one invocation.
+    #[arg(long, value_enum)]
+    pub dispatch_shape: Option<DispatchShape>,
+    /// Directory that frame captures are written to.
+    #[arg(long)]
+    pub capture_dir: Option<PathBuf>,
+    /// What captures and saved frames are written as.
+    #[arg(long, value_enum)]
+    pub capture_format: Option<ExportFormat>,
+    /// A state texture to capture instead of the configured ones, `output` or the name of a
+    /// pass. Can be given more than once.
+    #[arg(long = "capture-texture", value_name = "NAME")]
+    pub capture_textures: Vec<String>,
+    /// Bind an image file from the asset folder to the shader variable NAME, in addition to the
+    /// configured textures.
+    #[arg(long = "texture", value_name = "NAME=PATH")]
+    pub textures: Vec<TextureInput>,
+    /// `update` steps per second of simulated time, independent of the frame rate.
+    #[arg(long)]
+    pub tick_rate: Option<f64>,
+    /// Most steps a frame runs at the tick rate to catch up after a slow frame.
+    #[arg(long)]
+    pub max_catch_up_steps: Option<u32>,
+    /// Preset the shader parameters start from, from the presets file next to the shader.
+    #[arg(long)]
+    pub preset: Option<String>,
+    /// Seconds it takes to crossfade to a preset picked with its key.
+    #[arg(long)]
+    pub preset_crossfade: Option<f32>,
+    /// What recordings are saved as.
+    #[arg(long, value_enum)]
+    pub record_format: Option<RecordFormat>,
+    /// Record every this many frames.
+    #[arg(long)]
+    pub record_every: Option<u32>,
+    /// Frames after which a recording stops by itself.
+    #[arg(long)]
+    pub record_max_frames: Option<u32>,
+    /// Frames per second of simulated time while recording, independent of the frame rate.
+    #[arg(long)]
+    pub record_fps: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Invalid(&'static str),
+    Passes(String),
+    Textures(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => write!(f, "failed to read {}: {err}", path.display()),
+            ConfigError::Parse(path, err) => write!(f, "{}:{err}", path.display()),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {reason}"),
+            ConfigError::Passes(reason) => write!(f, "invalid passes: {reason}"),
+            ConfigError::Textures(reason) => write!(f, "invalid textures: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl PlaygroundConfig {
+    pub fn load(args: &ConfigArgs) -> Result<Self, ConfigError> {
+        let mut config = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        if let Some(shader) = &args.shader {
+            config.shader = shader.clone();
+        }
+        if let Some(mode) = args.mode {
+            config.mode = mode;
+        }
+        if let Some(channels) = &args.channels {
+            config.channels = channels.clone();
+        }
+        if let Some(width) = args.width {
+            config.size.x = width;
+        }
+        if let Some(height) = args.height {
+            config.size.y = height;
+        }
+        if let Some(format) = args.format {
+            config.format = format;
+        }
+        if let Some(display_factor) = args.display_factor {
+            config.display_factor = display_factor;
+        }
+        if let Some(workgroup_size) = args.workgroup_size {
+            config.workgroup_size = workgroup_size;
+        }
+        if let Some(dispatch_shape) = args.dispatch_shape {
+            config.dispatch_shape = dispatch_shape;
+        }
+        if let Some(capture_dir) = &args.capture_dir {
+            config.capture_dir = capture_dir.clone();
+        }
+        if let Some(capture_format) = args.capture_format {
+            config.capture_format = capture_format;
+        }
+        if !args.capture_textures.is_empty() {
+            config.capture_textures = args.capture_textures.clone();
+        }
+        config.textures.extend(args.textures.iter().cloned());
+        if let Some(tick_rate) = args.tick_rate {
+            config.tick_rate = Some(tick_rate);
+        }
+        if let Some(max_catch_up_steps) = args.max_catch_up_steps {
+            config.max_catch_up_steps = max_catch_up_steps;
+        }
+        if let Some(preset) = &args.preset {
+            config.preset = Some(preset.clone());
+        }
+        if let Some(preset_crossfade) = args.preset_crossfade {
+            config.preset_crossfade = preset_crossfade;
+        }
+        if let Some(record_format) = args.record_format {
+            config.record_format = record_format;
+        }
+        if let Some(record_every) = args.record_every {
+            config.record_every = record_every;
+        }
+        if let Some(record_max_frames) = args.record_max_frames {
+            config.record_max_frames = Some(record_max_frames);
+        }
+        if let Some(record_fps) = args.record_fps {
+            config.record_fps = Some(record_fps);
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|err| ConfigError::Read(path.into(), err))?;
+        ron::from_str(&text).map_err(|err| ConfigError::Parse(path.into(), err))
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.size.x == 0 || self.size.y == 0 {
+            return Err(ConfigError::Invalid("size must be at least 1x1"));
+        }
+        if self.display_factor == 0 {
+            return Err(ConfigError::Invalid("display_factor must be at least 1"));
+        }
+        if self.size.x.checked_mul(self.display_factor).is_none()
+            || self.size.y.checked_mul(self.display_factor).is_none()
+        {
+            return Err(ConfigError::Invalid(
+                "size times display_factor is too large for a window",
+            ));
+        }
+        if self.workgroup_size == 0 {
+            return Err(ConfigError::Invalid("workgroup_size must be at least 1"));
+        }
+        if self.mode == ShaderMode::Shadertoy && self.format.is_integer() {
+            return Err(ConfigError::Invalid(
+                "Shadertoy mode writes colors, which needs a float format",
+            ));
+        }
+        if let Some(tick_rate) = self.tick_rate
+            && (!tick_rate.is_finite() || tick_rate <= 0.0)
+        {
+            return Err(ConfigError::Invalid("tick_rate must be positive"));
+        }
+        if self.max_catch_up_steps == 0 {
+            return Err(ConfigError::Invalid(
+                "max_catch_up_steps must be at least 1",
+            ));
+        }
+        if !self.preset_crossfade.is_finite() || self.preset_crossfade < 0.0 {
+            return Err(ConfigError::Invalid(
+                "preset_crossfade must be 0 or more seconds",
+            ));
+        }
+        if self.record_every == 0 {
+            return Err(ConfigError::Invalid("record_every must be at least 1"));
+        }
+        if self.record_max_frames == Some(0) {
+            return Err(ConfigError::Invalid("record_max_frames must be at least 1"));
+        }
+        if let Some(record_fps) = self.record_fps
+            && (!record_fps.is_finite() || record_fps <= 0.0)
+        {
+            return Err(ConfigError::Invalid("record_fps must be positive"));
+        }
+        if self.channels.len() > CHANNEL_COUNT {
+            return Err(ConfigError::Invalid("there are only 4 channels"));
+        }
+        pass_order(&self.passes).map_err(ConfigError::Passes)?;
+        if self.capture_textures.is_empty() {
+            return Err(ConfigError::Invalid("capture_textures can't be empty"));
+        }
+        for name in &self.capture_textures {
+            if name != OUTPUT_TEXTURE && !self.passes.iter().any(|pass| pass.name == *name) {
+                return Err(ConfigError::Passes(format!(
+                    "there's no pass named `{name}` to capture"
+                )));
+            }
+        }
+        for (i, texture) in self.textures.iter().enumerate() {
+            let name = &texture.name;
+            check_binding_name(name).map_err(ConfigError::Textures)?;
+            if self.textures[..i].iter().any(|other| other.name == *name)
+                || self.passes.iter().any(|pass| pass.name == *name)
+            {
+                return Err(ConfigError::Textures(format!(
+                    "more than one texture or pass is named `{name}`"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The defs every compute pipeline is specialized with, for a shader whose `input` and
+    /// `output` are `format`. `STATE_FORMAT_INTEGER` is set when they hold integers. Defs can't
+    /// have a format as their value, so `#{STATE_FORMAT}` is substituted by
+    /// [`expand_state_format`] instead.
+    pub fn shader_defs(&self, format: StorageFormat) -> Vec<ShaderDefVal> {
+        let workgroup_size = self.dispatch_shape.workgroup_size(self.workgroup_size);
+        let mut shader_defs = vec![
+            ShaderDefVal::UInt("WORKGROUP_SIZE".into(), self.workgroup_size),
+            ShaderDefVal::UInt("WORKGROUP_SIZE_X".into(), workgroup_size.x),
+            ShaderDefVal::UInt("WORKGROUP_SIZE_Y".into(), workgroup_size.y),
+            ShaderDefVal::UInt("WORKGROUP_SIZE_Z".into(), workgroup_size.z),
+        ];
+        if format.is_integer() {
+            shader_defs.push(ShaderDefVal::Bool("STATE_FORMAT_INTEGER".into(), true));
+        }
+        shader_defs
+    }
+
+    /// File the `shader` asset is loaded from, for tools that read it without the asset server.
+    pub fn shader_path(&self) -> PathBuf {
+        Self::asset_path(&self.shader)
+    }
+
+    /// File an asset is loaded from.
+    pub fn asset_path(asset: &str) -> PathBuf {
+        FileAssetReader::get_base_path().join("assets").join(asset)
+    }
+
+    /// Size of the window that shows the state textures. [`Self::validate`] checks that it fits
+    /// in a `u32`.
+    pub fn window_size(&self) -> UVec2 {
+        self.size * self.display_factor
+    }
+}
+
+/// Replace `#{STATE_FORMAT}` in a shader's source with the WGSL name of `format`, the format
+/// of its `input` and `output`. This keeps the source on the same lines.
+pub fn expand_state_format(source: &str, format: StorageFormat) -> Cow<'_, str> {
+    const STATE_FORMAT: &str = "#{STATE_FORMAT}";
+    if source.contains(STATE_FORMAT) {
+        Cow::Owned(source.replace(STATE_FORMAT, format.wgsl_name()))
+    } else {
+        Cow::Borrowed(source)
+    }
+}
+
+/// Names the playground binds its own resources by.
+const RESERVED_NAMES: [&str; 6] = [
+    "input", "output", "globals", "keyboard", "dispatch", "params",
+];
+
+/// Check that a pass or texture can be bound by `name`: it must be a WGSL identifier that
+/// doesn't clash with the names the playground derives or binds itself.
+pub fn check_binding_name(name: &str) -> Result<(), String> {
+    let identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !identifier {
+        return Err(format!("`{name}` isn't a valid WGSL identifier"));
+    }
+    if RESERVED_NAMES.contains(&name)
+        || name.ends_with("_previous")
+        || name.ends_with("_sampler")
+        || name.starts_with("iChannel")
+    {
+        return Err(format!("the name `{name}` is reserved"));
+    }
+    Ok(())
+}
@@ -0,0 +1,700 @@
+//! A CPU backend that interprets the compute shader with naga, for machines without a GPU.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io, mem};
+
+use bevy::image::TextureFormatPixelInfo;
+use bevy::math::{UVec2, UVec3, Vec4};
+use bevy::render::render_resource::encase::internal::WriteInto;
+use bevy::render::render_resource::encase::{ShaderType, UniformBuffer};
+use bevy::render::render_resource::{BindingType, BufferBindingType, ShaderDefVal, TextureFormat};
+use bevy::render::settings::WgpuLimits;
+use clap::Args;
+use naga_oil::compose::{Composer, NagaModuleDescriptor, ShaderDefValue};
+
+use crate::bindings::{BindingSource, ShaderTarget, reflect};
+use crate::capture::CaptureError;
+use crate::config::{PlaygroundConfig, expand_state_format};
+use crate::dispatch::{DispatchError, DispatchOffset, DispatchPlan};
+use crate::export::save_texture;
+use crate::globals::ComputeShaderGlobals;
+use crate::headless::{FrameArgs, frame_file_name};
+use crate::input::KEY_COUNT;
+use crate::params::{ParamsLayout, reflect_params};
+use crate::presets::{PresetError, find_preset, presets_path};
+use crate::readback::TextureReadback;
+use crate::shader_error::ShaderDiagnostic;
+use crate::simulation::FixedTimestep;
+use crate::timeline::{TimelineError, load_timeline, timeline_path};
+use crate::{INIT_ENTRY_POINT, UPDATE_ENTRY_POINT};
+
+mod interpreter;
+
+#[derive(Args, Debug)]
+pub struct CpuArgs {
+    #[command(flatten)]
+    pub frames: FrameArgs,
+}
+
+#[derive(Debug)]
+pub enum CpuError {
+    Read(PathBuf, io::Error),
+    Shader(ShaderDiagnostic),
+    EntryPoint(String),
+    MissingBinding(u32),
+    Unsupported(String),
+    Invalid(&'static str),
+    Dispatch(DispatchError),
+    Preset(PresetError),
+    Timeline(TimelineError),
+    Save(PathBuf, CaptureError),
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::Read(path, err) => write!(f, "failed to read {}: {err}", path.display()),
+            CpuError::Shader(diagnostic) => diagnostic.fmt(f),
+            CpuError::EntryPoint(name) => write!(f, "no compute entry point named `{name}`"),
+            CpuError::MissingBinding(binding) => {
+                write!(f, "nothing is bound at @group(0) @binding({binding})")
+            }
+            CpuError::Unsupported(what) => write!(f, "{what} can't run on the CPU"),
+            CpuError::Invalid(reason) => write!(f, "invalid shader operation: {reason}"),
+            CpuError::Dispatch(err) => err.fmt(f),
+            CpuError::Preset(err) => err.fmt(f),
+            CpuError::Timeline(err) => err.fmt(f),
+            CpuError::Save(path, err) => write!(f, "failed to save {}: {err}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+/// Preprocess and validate a shader the way the pipeline cache does.
+pub fn compose_module(
+    source: &str,
+    file_path: &str,
+    shader_defs: &[ShaderDefVal],
+) -> Result<naga::Module, ShaderDiagnostic> {
+    let shader_defs = shader_defs
+        .iter()
+        .map(|def| match def {
+            ShaderDefVal::Bool(name, value) => (name.clone(), ShaderDefValue::Bool(*value)),
+            ShaderDefVal::Int(name, value) => (name.clone(), ShaderDefValue::Int(*value)),
+            ShaderDefVal::UInt(name, value) => (name.clone(), ShaderDefValue::UInt(*value)),
+        })
+        .collect();
+    Composer::default()
+        .make_naga_module(NagaModuleDescriptor {
+            source,
+            file_path,
+            shader_defs,
+            ..Default::default()
+        })
+        .map_err(|err| ShaderDiagnostic::from_composer_error(&err))
+}
+
+/// A texture in CPU memory, with tightly packed rows.
+#[derive(Clone, Debug)]
+pub struct CpuTexture {
+    pub size: UVec2,
+    pub format: TextureFormat,
+    pub data: Vec<u8>,
+}
+
+impl CpuTexture {
+    /// A texture with every texel set to `texel`, given as bytes in `format`.
+    pub fn new_fill(size: UVec2, format: TextureFormat, texel: &[u8]) -> Self {
+        Self {
+            size,
+            format,
+            data: texel.repeat(size.element_product() as usize),
+        }
+    }
+
+    pub fn readback(&self) -> TextureReadback {
+        TextureReadback {
+            size: self.size,
+            format: self.format,
+            data: self.data.clone(),
+        }
+    }
+}
+
+enum CpuResource {
+    Texture(CpuTexture),
+    Uniform(Vec<u8>),
+}
+
+/// The resources of bind group 0, which holds every binding of the compute pipeline.
+#[derive(Default)]
+pub struct CpuBindings {
+    resources: HashMap<u32, CpuResource>,
+}
+
+impl CpuBindings {
+    pub fn insert_texture(&mut self, binding: u32, texture: CpuTexture) {
+        self.resources
+            .insert(binding, CpuResource::Texture(texture));
+    }
+
+    /// Encode `value` with the same layout the GPU uniform buffer gets.
+    pub fn insert_uniform<T: ShaderType + WriteInto>(&mut self, binding: u32, value: &T) {
+        let mut buffer = UniformBuffer::new(Vec::new());
+        buffer.write(value).unwrap();
+        self.resources
+            .insert(binding, CpuResource::Uniform(buffer.into_inner()));
+    }
+
+    /// A uniform buffer of `size` zero bytes.
+    pub fn insert_zeroed_uniform(&mut self, binding: u32, size: u64) {
+        self.insert_uniform_bytes(binding, vec![0; size as usize]);
+    }
+
+    pub fn insert_uniform_bytes(&mut self, binding: u32, bytes: Vec<u8>) {
+        self.resources.insert(binding, CpuResource::Uniform(bytes));
+    }
+
+    pub fn texture(&self, binding: u32) -> Option<&CpuTexture> {
+        match self.resources.get(&binding) {
+            Some(CpuResource::Texture(texture)) => Some(texture),
+            _ => None,
+        }
+    }
+
+    fn texture_mut(&mut self, binding: u32) -> Option<&mut CpuTexture> {
+        match self.resources.get_mut(&binding) {
+            Some(CpuResource::Texture(texture)) => Some(texture),
+            _ => None,
+        }
+    }
+
+    fn uniform(&self, binding: &naga::ResourceBinding) -> Option<&[u8]> {
+        match self.resources.get(&binding.binding) {
+            Some(CpuResource::Uniform(bytes)) if binding.group == 0 => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn swap(&mut self, a: u32, b: u32) {
+        let resource_a = self.resources.remove(&a);
+        let resource_b = self.resources.remove(&b);
+        if let Some(resource) = resource_a {
+            self.resources.insert(b, resource);
+        }
+        if let Some(resource) = resource_b {
+            self.resources.insert(a, resource);
+        }
+    }
+}
+
+/// A compute shader that runs on the CPU, one invocation at a time.
+pub struct CpuShader {
+    module: naga::Module,
+    /// The parameters bound as `params`.
+    params: Option<ParamsLayout>,
+}
+
+impl CpuShader {
+    pub fn load(path: &Path, config: &PlaygroundConfig) -> Result<Self, CpuError> {
+        let source = fs::read_to_string(path).map_err(|err| CpuError::Read(path.into(), err))?;
+        let source = config.mode.expand(&source, &config.channels);
+        let source = expand_state_format(&source, config.format);
+        let path = path.to_string_lossy();
+        let module = compose_module(&source, &path, &config.shader_defs(config.format))
+            .map_err(CpuError::Shader)?;
+        let params = reflect_params(&module, &source).map_err(|problems| {
+            CpuError::Shader(ShaderDiagnostic {
+                path: path.into_owned(),
+                line: 0,
+                column: 0,
+                message: problems.join("\n"),
+            })
+        })?;
+        Ok(Self { module, params })
+    }
+
+    /// The CPU equivalent of `dispatch_workgroups` on a pipeline using `entry_point`.
+    pub fn dispatch(
+        &self,
+        entry_point: &str,
+        workgroups: UVec3,
+        bindings: &mut CpuBindings,
+    ) -> Result<(), CpuError> {
+        let entry_point = self
+            .module
+            .entry_points
+            .iter()
+            .find(|ep| ep.name == entry_point && ep.stage == naga::ShaderStage::Compute)
+            .ok_or_else(|| CpuError::EntryPoint(entry_point.to_string()))?;
+        interpreter::dispatch(&self.module, entry_point, workgroups, bindings)
+    }
+}
+
+/// Simulate the playground on the CPU: the same state textures, globals and dispatches as the
+/// `render` command, saving frames under the same names.
+pub fn run(config: &PlaygroundConfig, args: &CpuArgs) -> Result<(), CpuError> {
+    if !config.passes.is_empty() {
+        return Err(CpuError::Unsupported("passes".to_string()));
+    }
+    let path = config.shader_path();
+    let shader = CpuShader::load(&path, config)?;
+    let limits = WgpuLimits::default();
+    let layout =
+        reflect(&shader.module, config, ShaderTarget::Main, &limits).map_err(|problems| {
+            CpuError::Shader(ShaderDiagnostic {
+                path: path.to_string_lossy().into_owned(),
+                line: 0,
+                column: 0,
+                message: problems.join("\n"),
+            })
+        })?;
+    let find = |source| {
+        layout
+            .iter()
+            .find(|binding| binding.source == source)
+            .map(|binding| binding.binding)
+    };
+    let (input, output) = (find(BindingSource::Input), find(BindingSource::Output));
+    let (globals_binding, dispatch_binding) =
+        (find(BindingSource::Globals), find(BindingSource::Dispatch));
+
+    // The parameters start from their defaults or the configured preset, and the timeline
+    // animates them every frame.
+    let mut params = shader
+        .params
+        .as_ref()
+        .map(|layout| (layout, layout.defaults()));
+    if let (Some((layout, values)), Some(name)) = (&mut params, &config.preset) {
+        find_preset(&presets_path(config), name)
+            .map_err(CpuError::Preset)?
+            .apply(layout, values);
+    }
+    let timeline = match params {
+        Some(_) => load_timeline(&timeline_path(config)).map_err(CpuError::Timeline)?,
+        None => None,
+    };
+    let params_binding = layout
+        .iter()
+        .find(|binding| binding.source == BindingSource::Params);
+
+    let mut bindings = CpuBindings::default();
+    let state = CpuTexture::new_fill(
+        config.size,
+        config.format.texture_format(),
+        &config.format.texel(Vec4::new(1.0, 0.0, 0.0, 1.0)),
+    );
+    // The state texture that isn't bound when the shader only declares one of the two.
+    let mut spare = state.clone();
+    for binding in &layout {
+        match binding.source {
+            BindingSource::Input | BindingSource::Output => {
+                bindings.insert_texture(binding.binding, state.clone());
+            }
+            BindingSource::Keyboard => bindings.insert_texture(
+                binding.binding,
+                CpuTexture::new_fill(
+                    UVec2::new(KEY_COUNT as u32, 3),
+                    TextureFormat::R8Unorm,
+                    &[0],
+                ),
+            ),
+            // A `params` binding is a struct, so it has a layout.
+            BindingSource::Params => {
+                if let Some((layout, values)) = &params {
+                    bindings.insert_uniform_bytes(binding.binding, layout.bytes(values));
+                }
+            }
+            BindingSource::Globals
+            | BindingSource::Dispatch
+            | BindingSource::Pass { .. }
+            | BindingSource::Copy(_) => {}
+            BindingSource::Texture(_) | BindingSource::TextureSampler(_) => {
+                return Err(CpuError::Unsupported(format!(
+                    "the image texture `{}`",
+                    binding.name
+                )));
+            }
+            BindingSource::Allocated => match binding.ty {
+                BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    ..
+                } => bindings.insert_zeroed_uniform(binding.binding, binding.buffer_size),
+                BindingType::StorageTexture { .. } | BindingType::Texture { .. } => {
+                    let format = binding.texture_format().unwrap();
+                    let texel = vec![0; format.pixel_size()];
+                    bindings.insert_texture(
+                        binding.binding,
+                        CpuTexture::new_fill(binding.texture_size(config.size), format, &texel),
+                    );
+                }
+                // Samplers are only used by `textureSample*`, which the interpreter rejects.
+                BindingType::Sampler(_) => {}
+                _ => {
+                    return Err(CpuError::Unsupported(format!(
+                        "`{}` at @binding({})",
+                        binding.name, binding.binding
+                    )));
+                }
+            },
+        }
+    }
+
+    let workgroup_size = config.dispatch_shape.workgroup_size(config.workgroup_size);
+    let plan = DispatchPlan::new(
+        config.size.extend(1),
+        workgroup_size,
+        &WgpuLimits::default(),
+    )
+    .map_err(CpuError::Dispatch)?;
+
+    let frames = &args.frames;
+    let dump = frames.dump_frames();
+    let output_dir = frames
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("render-cpu"));
+    let timestep = frames.timestep();
+    let mut fixed = FixedTimestep::from_config(config);
+    let mut globals = ComputeShaderGlobals {
+        resolution: config.size,
+        ..Default::default()
+    };
+    let run = |entry_point, globals: &ComputeShaderGlobals, bindings: &mut CpuBindings| {
+        if let Some(binding) = globals_binding {
+            bindings.insert_uniform(binding, globals);
+        }
+        for call in &plan.calls {
+            if let Some(binding) = dispatch_binding {
+                bindings.insert_uniform(
+                    binding,
+                    &DispatchOffset {
+                        offset: call.offset,
+                    },
+                );
+            }
+            shader.dispatch(entry_point, call.workgroups, bindings)?;
+        }
+        Ok::<_, CpuError>(())
+    };
+
+    run(INIT_ENTRY_POINT, &globals, &mut bindings)?;
+    // Like the GPU render, frames whose time doesn't add up to a step at the tick rate run
+    // nothing and aren't counted.
+    while globals.frame < frames.frames {
+        let steps = match &mut fixed {
+            Some(fixed) => fixed.advance(timestep, 1),
+            None => 1,
+        };
+        if steps == 0 {
+            continue;
+        }
+        globals.frame += 1;
+        for step in 0..steps {
+            // Read the state the previous step wrote.
+            match (input, output) {
+                (Some(input), Some(output)) => bindings.swap(input, output),
+                (Some(binding), None) | (None, Some(binding)) => {
+                    mem::swap(bindings.texture_mut(binding).unwrap(), &mut spare);
+                }
+                (None, None) => {}
+            }
+            globals.tick += 1;
+            match &fixed {
+                Some(fixed) => {
+                    globals.delta_time = fixed.timestep() as f32;
+                    globals.time = (globals.tick as f64 * fixed.timestep()) as f32;
+                }
+                None => {
+                    globals.delta_time = timestep.as_secs_f32();
+                    globals.time += globals.delta_time;
+                }
+            }
+            // Like the uniform the GPU render uploads once a frame, every step of a frame sees
+            // the values at the time of its first step.
+            if let (0, Some(timeline), Some((layout, values)), Some(binding)) =
+                (step, &timeline, &params, params_binding)
+            {
+                let mut values = values.clone();
+                timeline.apply(layout, timeline.playhead(globals.time as f64), &mut values);
+                bindings.insert_uniform_bytes(binding.binding, layout.bytes(&values));
+            }
+            run(UPDATE_ENTRY_POINT, &globals, &mut bindings)?;
+        }
+
+        if dump.contains(&globals.frame) {
+            let path = output_dir.join(frame_file_name(globals.frame, config.capture_format));
+            let output = output
+                .and_then(|binding| bindings.texture(binding))
+                .unwrap_or(&spare)
+                .readback();
+            save_texture(&output, &path, config.capture_format)
+                .map_err(|err| CpuError::Save(path.clone(), err))?;
+            println!("Saved {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::*;
+    use crate::format::StorageFormat;
+
+    /// A shader from WGSL source, composed with the default config's defs.
+    fn shader(source: &str) -> CpuShader {
+        let config = PlaygroundConfig::default();
+        let module =
+            compose_module(source, "test.wgsl", &config.shader_defs(config.format)).unwrap();
+        CpuShader {
+            module,
+            params: None,
+        }
+    }
+
+    fn r32uint(values: &[u32]) -> CpuTexture {
+        CpuTexture {
+            size: UVec2::new(values.len() as u32, 1),
+            format: TextureFormat::R32Uint,
+            data: values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    fn texels(texture: &CpuTexture) -> Vec<u32> {
+        texture
+            .data
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    fn rgba8(texture: &CpuTexture, x: u32, y: u32) -> [u8; 4] {
+        let offset = 4 * (y * texture.size.x + x) as usize;
+        texture.data[offset..offset + 4].try_into().unwrap()
+    }
+
+    /// Run `main` of a one-invocation shader over an `r32uint` input and output.
+    fn run_single(source: &str, input: &[u32], output: &[u32]) -> Result<Vec<u32>, CpuError> {
+        let mut bindings = CpuBindings::default();
+        bindings.insert_texture(0, r32uint(input));
+        bindings.insert_texture(1, r32uint(output));
+        shader(source).dispatch("main", UVec3::ONE, &mut bindings)?;
+        Ok(texels(bindings.texture(1).unwrap()))
+    }
+
+    const R32UINT_BINDINGS: &str = "
+        @group(0) @binding(0) var input: texture_storage_2d<r32uint, read>;
+        @group(0) @binding(1) var output: texture_storage_2d<r32uint, write>;
+    ";
+
+    /// The bindings of the example shader, with zeroed state textures of the config's format.
+    fn example_bindings(config: &PlaygroundConfig, params: Vec<u8>) -> CpuBindings {
+        let format = config.format.texture_format();
+        let texel = vec![0; format.pixel_size()];
+        let state = CpuTexture::new_fill(config.size, format, &texel);
+        let mut bindings = CpuBindings::default();
+        bindings.insert_texture(0, state.clone());
+        bindings.insert_texture(1, state);
+        bindings.insert_uniform(
+            2,
+            &ComputeShaderGlobals {
+                resolution: config.size,
+                ..Default::default()
+            },
+        );
+        bindings.insert_texture(
+            3,
+            CpuTexture::new_fill(
+                UVec2::new(KEY_COUNT as u32, 3),
+                TextureFormat::R8Unorm,
+                &[0],
+            ),
+        );
+        bindings.insert_uniform(4, &DispatchOffset::default());
+        bindings.insert_uniform_bytes(5, params);
+        bindings
+    }
+
+    #[test]
+    fn example_shader_paints_with_the_mouse() {
+        let config = PlaygroundConfig {
+            size: UVec2::new(4, 3),
+            ..Default::default()
+        };
+        let shader = CpuShader::load(&config.shader_path(), &config).unwrap();
+        let layout = shader.params.clone().unwrap();
+        let mut values = layout.defaults();
+        let radius = layout
+            .fields
+            .iter()
+            .position(|field| field.name == "brush_radius")
+            .unwrap();
+        values[radius][0] = 1.0;
+
+        let mut bindings = example_bindings(&config, layout.bytes(&values));
+        let mut globals = ComputeShaderGlobals {
+            resolution: config.size,
+            ..Default::default()
+        };
+        // A single 8x8 workgroup covers the whole texture.
+        shader
+            .dispatch(INIT_ENTRY_POINT, UVec3::ONE, &mut bindings)
+            .unwrap();
+
+        // Red, with alpha going down the rows.
+        let output = bindings.texture(1).unwrap();
+        assert_eq!(rgba8(output, 0, 0), [255, 0, 0, 0]);
+        assert_eq!(rgba8(output, 3, 1), [255, 0, 0, 85]);
+        assert_eq!(rgba8(output, 2, 2), [255, 0, 0, 170]);
+
+        // Held left button at (1, 1) paints only that texel at a radius of 1.
+        globals.frame = 1;
+        globals.mouse = Vec2::new(1.0, 1.0);
+        globals.mouse_buttons = 1;
+        bindings.insert_uniform(2, &globals);
+        bindings.swap(0, 1);
+        shader
+            .dispatch(UPDATE_ENTRY_POINT, UVec3::ONE, &mut bindings)
+            .unwrap();
+        let output = bindings.texture(1).unwrap();
+        assert_eq!(rgba8(output, 1, 1), [255, 255, 255, 255]);
+        assert_eq!(rgba8(output, 0, 1), [255, 0, 0, 85]);
+        assert_eq!(rgba8(output, 3, 2), [255, 0, 0, 170]);
+
+        // Holding shift erases instead.
+        let keyboard = bindings.texture_mut(3).unwrap();
+        keyboard.data[16] = 255;
+        bindings.swap(0, 1);
+        shader
+            .dispatch(UPDATE_ENTRY_POINT, UVec3::ONE, &mut bindings)
+            .unwrap();
+        let output = bindings.texture(1).unwrap();
+        assert_eq!(rgba8(output, 1, 1), [0, 0, 0, 0]);
+        assert_eq!(rgba8(output, 2, 1), [255, 0, 0, 85]);
+    }
+
+    #[test]
+    fn example_shader_writes_colors_in_the_state_format() {
+        for (format, texel) in [
+            (
+                StorageFormat::Rgba32Float,
+                [1.0f32, 0.0, 0.0, 0.5].as_slice(),
+            ),
+            (StorageFormat::R32Float, &[1.0]),
+        ] {
+            let config = PlaygroundConfig {
+                size: UVec2::new(1, 2),
+                format,
+                ..Default::default()
+            };
+            let shader = CpuShader::load(&config.shader_path(), &config).unwrap();
+            let params = shader.params.as_ref().unwrap();
+            let mut bindings = example_bindings(&config, params.bytes(&params.defaults()));
+            shader
+                .dispatch(INIT_ENTRY_POINT, UVec3::ONE, &mut bindings)
+                .unwrap();
+            let output = bindings.texture(1).unwrap();
+            let bytes: Vec<u8> = texel.iter().flat_map(|value| value.to_le_bytes()).collect();
+            assert_eq!(output.data[bytes.len()..], bytes, "{format:?}");
+        }
+
+        // Integer formats count up to 255.
+        let config = PlaygroundConfig {
+            size: UVec2::new(1, 1),
+            format: StorageFormat::R32Uint,
+            ..Default::default()
+        };
+        let shader = CpuShader::load(&config.shader_path(), &config).unwrap();
+        let params = shader.params.as_ref().unwrap();
+        let mut bindings = example_bindings(&config, params.bytes(&params.defaults()));
+        shader
+            .dispatch(INIT_ENTRY_POINT, UVec3::ONE, &mut bindings)
+            .unwrap();
+        assert_eq!(texels(bindings.texture(1).unwrap()), [255]);
+    }
+
+    #[test]
+    fn integer_division_by_zero() {
+        let source = format!(
+            "{R32UINT_BINDINGS}
+            @compute @workgroup_size(1)
+            fn main() {{
+                // Loaded, so the division isn't folded when the shader is compiled.
+                let zero = textureLoad(input, vec2<u32>(0u, 0u)).r;
+                let min = i32(zero) - 2147483647 - 1;
+                textureStore(output, vec2<u32>(0u, 0u), vec4<u32>(7u / zero));
+                textureStore(output, vec2<u32>(1u, 0u), vec4<u32>(7u % zero));
+                textureStore(output, vec2<u32>(2u, 0u), vec4<u32>(bitcast<u32>(-7 / i32(zero))));
+                textureStore(output, vec2<u32>(3u, 0u), vec4<u32>(bitcast<u32>(-7 % i32(zero))));
+                textureStore(output, vec2<u32>(4u, 0u), vec4<u32>(bitcast<u32>(min / -1)));
+                textureStore(output, vec2<u32>(5u, 0u), vec4<u32>(bitcast<u32>(min % -1)));
+            }}"
+        );
+        // WGSL defines `x / 0` as `x` and `x % 0` as 0, and `i32::MIN / -1` as `i32::MIN`.
+        assert_eq!(
+            run_single(&source, &[0], &[1; 6]).unwrap(),
+            [7, 0, -7i32 as u32, 0, i32::MIN as u32, 0]
+        );
+    }
+
+    #[test]
+    fn out_of_bounds_texture_access() {
+        let source = format!(
+            "{R32UINT_BINDINGS}
+            @compute @workgroup_size(1)
+            fn main() {{
+                textureStore(output, vec2<u32>(0u, 0u), textureLoad(input, vec2<i32>(-1, 0)));
+                textureStore(output, vec2<u32>(1u, 0u), textureLoad(input, vec2<u32>(2u, 0u)));
+                textureStore(output, vec2<u32>(2u, 0u), textureLoad(input, vec2<u32>(1u, 0u)));
+                textureStore(output, vec2<u32>(4u, 0u), vec4<u32>(9u));
+                textureStore(output, vec2<i32>(-1, 0), vec4<u32>(9u));
+                textureStore(output, vec2<u32>(0u, 1u), vec4<u32>(9u));
+            }}"
+        );
+        // Loads outside the texture read 0 and stores outside it are dropped.
+        assert_eq!(run_single(&source, &[5, 6], &[1; 4]).unwrap(), [0, 0, 6, 1]);
+    }
+
+    #[test]
+    fn barriers_are_unsupported() {
+        let source = format!(
+            "{R32UINT_BINDINGS}
+            @compute @workgroup_size(1)
+            fn main() {{
+                workgroupBarrier();
+                textureStore(output, vec2<u32>(0u, 0u), vec4<u32>(1u));
+            }}"
+        );
+        let err = run_single(&source, &[0], &[0]).unwrap_err();
+        assert!(
+            matches!(&err, CpuError::Unsupported(what) if what == "barriers"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn atomics_are_unsupported() {
+        let source = format!(
+            "{R32UINT_BINDINGS}
+            var<workgroup> counter: atomic<u32>;
+
+            @compute @workgroup_size(1)
+            fn main() {{
+                let count = atomicAdd(&counter, 1u);
+                textureStore(output, vec2<u32>(0u, 0u), vec4<u32>(count));
+            }}"
+        );
+        let err = run_single(&source, &[0], &[0]).unwrap_err();
+        assert!(
+            matches!(&err, CpuError::Unsupported(what) if what == "atomics"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,1514 @@
+//! Evaluates naga IR one invocation at a time.
+
+use bevy::math::{IVec2, UVec3};
+use bevy::render::render_resource::TextureFormat;
+use naga::{
+    AddressSpace, ArraySize, BinaryOperator, Binding, Block, BuiltIn, EntryPoint, Expression,
+    Function, GlobalVariable, Handle, ImageDimension, ImageQuery, LocalVariable, MathFunction,
+    Module, RelationalFunction, Scalar, ScalarKind, Statement, Type, TypeInner, UnaryOperator,
+};
+
+use super::{CpuBindings, CpuError, CpuTexture};
+
+/// A runtime value. Vectors, matrices (as a list of columns), arrays and structs are all
+/// composites.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    F32(f32),
+    Composite(Vec<Value>),
+    Pointer(Pointer),
+    /// A texture or sampler.
+    Handle(Handle<GlobalVariable>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Pointer {
+    root: Root,
+    /// Indices into the composites below `root`.
+    path: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Root {
+    Local {
+        frame: usize,
+        variable: Handle<LocalVariable>,
+    },
+    Global(Handle<GlobalVariable>),
+}
+
+impl Value {
+    fn as_bool(&self) -> Result<bool, CpuError> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(CpuError::Invalid("expected a bool")),
+        }
+    }
+
+    fn as_f32(&self) -> Result<f32, CpuError> {
+        match self {
+            Value::F32(value) => Ok(*value),
+            _ => Err(CpuError::Invalid("expected an f32")),
+        }
+    }
+
+    fn as_i64(&self) -> Result<i64, CpuError> {
+        match self {
+            Value::I32(value) => Ok(*value as i64),
+            Value::U32(value) => Ok(*value as i64),
+            _ => Err(CpuError::Invalid("expected an integer")),
+        }
+    }
+
+    fn components(&self) -> Result<&[Value], CpuError> {
+        match self {
+            Value::Composite(components) => Ok(components),
+            _ => Err(CpuError::Invalid("expected a vector")),
+        }
+    }
+
+    fn into_pointer(self) -> Result<Pointer, CpuError> {
+        match self {
+            Value::Pointer(pointer) => Ok(pointer),
+            _ => Err(CpuError::Invalid("expected a pointer")),
+        }
+    }
+
+    fn texel_coordinate(&self) -> Result<IVec2, CpuError> {
+        let coordinate = match self {
+            Value::Composite(components) => components
+                .iter()
+                .map(Value::as_i64)
+                .collect::<Result<Vec<_>, _>>()?,
+            scalar => vec![scalar.as_i64()?],
+        };
+        let clamp = |value: i64| value.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        Ok(IVec2::new(
+            clamp(coordinate[0]),
+            coordinate.get(1).copied().map_or(0, clamp),
+        ))
+    }
+
+    fn is_matrix(&self) -> bool {
+        matches!(self, Value::Composite(columns) if matches!(columns.first(), Some(Value::Composite(_))))
+    }
+
+    fn is_vector(&self) -> bool {
+        matches!(self, Value::Composite(components) if !matches!(components.first(), Some(Value::Composite(_))))
+    }
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Option<Value>),
+}
+
+struct Frame<'a> {
+    function: &'a Function,
+    arguments: Vec<Value>,
+    locals: Vec<Value>,
+    /// Values of the expressions evaluated so far, by handle index.
+    expressions: Vec<Option<Value>>,
+}
+
+/// Run every invocation of `entry_point` over `workgroups`, one after another.
+pub fn dispatch(
+    module: &Module,
+    entry_point: &EntryPoint,
+    workgroups: UVec3,
+    bindings: &mut CpuBindings,
+) -> Result<(), CpuError> {
+    let globals = module
+        .global_variables
+        .iter()
+        .map(|(_, global)| initial_global(module, global, bindings))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut invocation = Invocation {
+        module,
+        bindings,
+        globals: Vec::new(),
+        frames: Vec::new(),
+    };
+
+    let size = UVec3::from(entry_point.workgroup_size);
+    for workgroup_id in grid(workgroups) {
+        for local_id in grid(size) {
+            let global_id = workgroup_id * size + local_id;
+            let local_index = (local_id.z * size.y + local_id.y) * size.x + local_id.x;
+            let builtin = |builtin| match builtin {
+                BuiltIn::GlobalInvocationId => Ok(uvec3(global_id)),
+                BuiltIn::LocalInvocationId => Ok(uvec3(local_id)),
+                BuiltIn::LocalInvocationIndex => Ok(Value::U32(local_index)),
+                BuiltIn::WorkGroupId => Ok(uvec3(workgroup_id)),
+                BuiltIn::NumWorkGroups => Ok(uvec3(workgroups)),
+                builtin => Err(CpuError::Unsupported(format!("@builtin({builtin:?})"))),
+            };
+
+            let arguments = entry_point
+                .function
+                .arguments
+                .iter()
+                .map(|argument| match &argument.binding {
+                    Some(Binding::BuiltIn(built_in)) => builtin(*built_in),
+                    Some(Binding::Location { .. }) => Err(CpuError::Invalid(
+                        "compute shaders have no @location inputs",
+                    )),
+                    None => match &module.types[argument.ty].inner {
+                        TypeInner::Struct { members, .. } => members
+                            .iter()
+                            .map(|member| match &member.binding {
+                                Some(Binding::BuiltIn(built_in)) => builtin(*built_in),
+                                _ => Err(CpuError::Invalid("entry point input without a builtin")),
+                            })
+                            .collect::<Result<_, _>>()
+                            .map(Value::Composite),
+                        _ => Err(CpuError::Invalid("entry point input without a builtin")),
+                    },
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // `var<private>` globals start over in every invocation.
+            invocation.globals.clone_from(&globals);
+            invocation.call(&entry_point.function, arguments)?;
+        }
+    }
+    Ok(())
+}
+
+fn grid(size: UVec3) -> impl Iterator<Item = UVec3> {
+    (0..size.z).flat_map(move |z| {
+        (0..size.y).flat_map(move |y| (0..size.x).map(move |x| UVec3::new(x, y, z)))
+    })
+}
+
+fn uvec3(value: UVec3) -> Value {
+    Value::Composite(value.to_array().map(Value::U32).to_vec())
+}
+
+/// The value a global holds when an invocation starts, or `None` for globals that can't be
+/// dereferenced: textures, samplers, and the address spaces the interpreter doesn't emulate.
+fn initial_global(
+    module: &Module,
+    global: &GlobalVariable,
+    bindings: &CpuBindings,
+) -> Result<Option<Value>, CpuError> {
+    match global.space {
+        AddressSpace::Private => match global.init {
+            Some(init) => const_value(module, init).map(Some),
+            None => zero_value(module, global.ty).map(Some),
+        },
+        AddressSpace::Uniform => {
+            let Some(bytes) = global
+                .binding
+                .as_ref()
+                .and_then(|binding| bindings.uniform(binding))
+            else {
+                return Ok(None);
+            };
+            read_value(module, global.ty, bytes, 0).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+struct Invocation<'a, 'b> {
+    module: &'a Module,
+    bindings: &'b mut CpuBindings,
+    globals: Vec<Option<Value>>,
+    frames: Vec<Frame<'a>>,
+}
+
+impl<'a> Invocation<'a, '_> {
+    fn frame(&self) -> &Frame<'a> {
+        self.frames.last().unwrap()
+    }
+
+    fn call(
+        &mut self,
+        function: &'a Function,
+        arguments: Vec<Value>,
+    ) -> Result<Option<Value>, CpuError> {
+        let locals = function
+            .local_variables
+            .iter()
+            .map(|(_, local)| zero_value(self.module, local.ty))
+            .collect::<Result<_, _>>()?;
+        self.frames.push(Frame {
+            function,
+            arguments,
+            locals,
+            expressions: vec![None; function.expressions.len()],
+        });
+        for (handle, local) in function.local_variables.iter() {
+            if let Some(init) = local.init {
+                let value = self.value(init)?;
+                self.frames.last_mut().unwrap().locals[handle.index()] = value;
+            }
+        }
+
+        let flow = self.block(&function.body);
+        self.frames.pop();
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    fn block(&mut self, block: &'a Block) -> Result<Flow, CpuError> {
+        for statement in block.iter() {
+            match self.statement(statement)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, statement: &'a Statement) -> Result<Flow, CpuError> {
+        match statement {
+            Statement::Emit(range) => {
+                for handle in range.clone() {
+                    let value = self.evaluate(handle)?;
+                    self.frames.last_mut().unwrap().expressions[handle.index()] = Some(value);
+                }
+            }
+            Statement::Block(block) => return self.block(block),
+            Statement::If {
+                condition,
+                accept,
+                reject,
+            } => {
+                return if self.value(*condition)?.as_bool()? {
+                    self.block(accept)
+                } else {
+                    self.block(reject)
+                };
+            }
+            Statement::Switch { selector, cases } => {
+                let selector = self.value(*selector)?.as_i64()?;
+                let start = cases
+                    .iter()
+                    .position(|case| match case.value {
+                        naga::SwitchValue::I32(value) => value as i64 == selector,
+                        naga::SwitchValue::U32(value) => value as i64 == selector,
+                        naga::SwitchValue::Default => false,
+                    })
+                    .or_else(|| {
+                        cases
+                            .iter()
+                            .position(|case| case.value == naga::SwitchValue::Default)
+                    });
+                let Some(start) = start else {
+                    return Ok(Flow::Next);
+                };
+                for case in &cases[start..] {
+                    match self.block(&case.body)? {
+                        Flow::Next if case.fall_through => continue,
+                        Flow::Next | Flow::Break => break,
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            Statement::Loop {
+                body,
+                continuing,
+                break_if,
+            } => loop {
+                match self.block(body)? {
+                    Flow::Break => break,
+                    Flow::Return(value) => return Ok(Flow::Return(value)),
+                    Flow::Next | Flow::Continue => {}
+                }
+                if let Flow::Return(value) = self.block(continuing)? {
+                    return Ok(Flow::Return(value));
+                }
+                if let Some(break_if) = break_if
+                    && self.value(*break_if)?.as_bool()?
+                {
+                    break;
+                }
+            },
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Return { value } => {
+                let value = value.map(|value| self.value(value)).transpose()?;
+                return Ok(Flow::Return(value));
+            }
+            Statement::Store { pointer, value } => {
+                let pointer = self.value(*pointer)?.into_pointer()?;
+                let value = self.value(*value)?;
+                *self.dereference(&pointer)? = value;
+            }
+            Statement::ImageStore {
+                image,
+                coordinate,
+                array_index,
+                value,
+            } => {
+                if array_index.is_some() {
+                    return Err(CpuError::Unsupported("texture arrays".into()));
+                }
+                let coordinate = self.value(*coordinate)?.texel_coordinate()?;
+                let value = self.value(*value)?;
+                let binding = self.texture_binding(*image)?;
+                let texture = self
+                    .bindings
+                    .texture_mut(binding)
+                    .ok_or(CpuError::MissingBinding(binding))?;
+                write_texel(texture, coordinate, &value)?;
+            }
+            Statement::Call {
+                function,
+                arguments,
+                result,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.value(*argument))
+                    .collect::<Result<_, _>>()?;
+                let value = self.call(&self.module.functions[*function], arguments)?;
+                if let Some(result) = result {
+                    let value = value.ok_or(CpuError::Invalid("function returned no value"))?;
+                    self.frames.last_mut().unwrap().expressions[result.index()] = Some(value);
+                }
+            }
+            Statement::Kill => return Err(CpuError::Unsupported("discard".into())),
+            Statement::Barrier(_) => {
+                // Invocations run one at a time, so nothing they share could be synchronized.
+                return Err(CpuError::Unsupported("barriers".into()));
+            }
+            Statement::Atomic { .. } | Statement::ImageAtomic { .. } => {
+                return Err(CpuError::Unsupported("atomics".into()));
+            }
+            Statement::WorkGroupUniformLoad { .. } => {
+                return Err(CpuError::Unsupported("workgroupUniformLoad".into()));
+            }
+            Statement::RayQuery { .. } => {
+                return Err(CpuError::Unsupported("ray queries".into()));
+            }
+            Statement::SubgroupBallot { .. }
+            | Statement::SubgroupGather { .. }
+            | Statement::SubgroupCollectiveOperation { .. } => {
+                return Err(CpuError::Unsupported("subgroup operations".into()));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// The value of an expression that was emitted earlier, or of one that never needs emitting.
+    fn value(&mut self, handle: Handle<Expression>) -> Result<Value, CpuError> {
+        match &self.frame().expressions[handle.index()] {
+            Some(value) => Ok(value.clone()),
+            None => self.evaluate(handle),
+        }
+    }
+
+    fn evaluate(&mut self, handle: Handle<Expression>) -> Result<Value, CpuError> {
+        let module = self.module;
+        let function = self.frame().function;
+        match &function.expressions[handle] {
+            Expression::Literal(literal) => literal_value(*literal),
+            Expression::Constant(constant) => const_value(module, module.constants[*constant].init),
+            Expression::ZeroValue(ty) => zero_value(module, *ty),
+            Expression::Compose { ty, components } => {
+                let components = components
+                    .iter()
+                    .map(|component| self.value(*component))
+                    .collect::<Result<_, _>>()?;
+                Ok(compose(module, *ty, components))
+            }
+            Expression::Access { base, index } => {
+                let index = self.value(*index)?.as_i64()?.max(0) as usize;
+                self.access(*base, index)
+            }
+            Expression::AccessIndex { base, index } => self.access(*base, *index as usize),
+            Expression::Splat { size, value } => {
+                Ok(Value::Composite(vec![self.value(*value)?; *size as usize]))
+            }
+            Expression::Swizzle {
+                size,
+                vector,
+                pattern,
+            } => {
+                let vector = self.value(*vector)?;
+                let components = vector.components()?;
+                Ok(Value::Composite(
+                    pattern[..*size as usize]
+                        .iter()
+                        .map(|component| components[*component as usize].clone())
+                        .collect(),
+                ))
+            }
+            Expression::FunctionArgument(index) => {
+                Ok(self.frame().arguments[*index as usize].clone())
+            }
+            Expression::GlobalVariable(global) => {
+                if module.global_variables[*global].space == AddressSpace::Handle {
+                    Ok(Value::Handle(*global))
+                } else {
+                    Ok(Value::Pointer(Pointer {
+                        root: Root::Global(*global),
+                        path: Vec::new(),
+                    }))
+                }
+            }
+            Expression::LocalVariable(variable) => Ok(Value::Pointer(Pointer {
+                root: Root::Local {
+                    frame: self.frames.len() - 1,
+                    variable: *variable,
+                },
+                path: Vec::new(),
+            })),
+            Expression::Load { pointer } => {
+                let pointer = self.value(*pointer)?.into_pointer()?;
+                self.dereference(&pointer).map(|value| value.clone())
+            }
+            Expression::ImageLoad {
+                image,
+                coordinate,
+                array_index,
+                sample,
+                level: _,
+            } => {
+                if array_index.is_some() {
+                    return Err(CpuError::Unsupported("texture arrays".into()));
+                }
+                if sample.is_some() {
+                    return Err(CpuError::Unsupported("multisampled textures".into()));
+                }
+                let coordinate = self.value(*coordinate)?.texel_coordinate()?;
+                let binding = self.texture_binding(*image)?;
+                let texture = self
+                    .bindings
+                    .texture(binding)
+                    .ok_or(CpuError::MissingBinding(binding))?;
+                read_texel(texture, coordinate)
+            }
+            Expression::ImageQuery { image, query } => {
+                let Value::Handle(global) = self.value(*image)? else {
+                    return Err(CpuError::Invalid("expected a texture"));
+                };
+                let binding = self.texture_binding(*image)?;
+                let texture = self
+                    .bindings
+                    .texture(binding)
+                    .ok_or(CpuError::MissingBinding(binding))?;
+                match query {
+                    ImageQuery::Size { .. } => {
+                        let dim = match &module.types[module.global_variables[global].ty].inner {
+                            TypeInner::Image { dim, .. } => *dim,
+                            _ => return Err(CpuError::Invalid("expected a texture")),
+                        };
+                        Ok(match dim {
+                            ImageDimension::D1 => Value::U32(texture.size.x),
+                            ImageDimension::D2 | ImageDimension::Cube => Value::Composite(vec![
+                                Value::U32(texture.size.x),
+                                Value::U32(texture.size.y),
+                            ]),
+                            ImageDimension::D3 => Value::Composite(vec![
+                                Value::U32(texture.size.x),
+                                Value::U32(texture.size.y),
+                                Value::U32(1),
+                            ]),
+                        })
+                    }
+                    ImageQuery::NumLevels | ImageQuery::NumLayers | ImageQuery::NumSamples => {
+                        Ok(Value::U32(1))
+                    }
+                }
+            }
+            Expression::Unary { op, expr } => {
+                let op = *op;
+                map(self.value(*expr)?, &|value| unary(op, value))
+            }
+            Expression::Binary { op, left, right } => {
+                let left = self.value(*left)?;
+                let right = self.value(*right)?;
+                binary(*op, left, right)
+            }
+            Expression::Select {
+                condition,
+                accept,
+                reject,
+            } => {
+                let condition = self.value(*condition)?;
+                let accept = self.value(*accept)?;
+                let reject = self.value(*reject)?;
+                match condition {
+                    Value::Bool(condition) => Ok(if condition { accept } else { reject }),
+                    condition => zip3(condition, accept, reject, &|condition, accept, reject| {
+                        Ok(if condition.as_bool()? { accept } else { reject })
+                    }),
+                }
+            }
+            Expression::Relational { fun, argument } => {
+                let argument = self.value(*argument)?;
+                relational(*fun, argument)
+            }
+            Expression::Math {
+                fun,
+                arg,
+                arg1,
+                arg2,
+                arg3,
+            } => {
+                let mut arguments = vec![self.value(*arg)?];
+                for arg in [arg1, arg2, arg3].into_iter().flatten() {
+                    arguments.push(self.value(*arg)?);
+                }
+                math(*fun, arguments)
+            }
+            Expression::As {
+                expr,
+                kind,
+                convert,
+            } => {
+                let kind = *kind;
+                let convert = convert.is_some();
+                map(self.value(*expr)?, &|value| cast(value, kind, convert))
+            }
+            Expression::CallResult(_) => Err(CpuError::Invalid("call result used before the call")),
+            Expression::Override(_) => Err(CpuError::Unsupported(
+                "pipeline-overridable constants".into(),
+            )),
+            Expression::ImageSample { .. } => Err(CpuError::Unsupported("textureSample".into())),
+            Expression::Derivative { .. } => Err(CpuError::Unsupported("derivatives".into())),
+            Expression::AtomicResult { .. } => Err(CpuError::Unsupported("atomics".into())),
+            Expression::WorkGroupUniformLoadResult { .. } => {
+                Err(CpuError::Unsupported("workgroupUniformLoad".into()))
+            }
+            Expression::ArrayLength(_) => Err(CpuError::Unsupported("arrayLength".into())),
+            Expression::RayQueryProceedResult | Expression::RayQueryGetIntersection { .. } => {
+                Err(CpuError::Unsupported("ray queries".into()))
+            }
+            Expression::SubgroupBallotResult | Expression::SubgroupOperationResult { .. } => {
+                Err(CpuError::Unsupported("subgroup operations".into()))
+            }
+        }
+    }
+
+    fn access(&mut self, base: Handle<Expression>, index: usize) -> Result<Value, CpuError> {
+        match self.value(base)? {
+            Value::Pointer(mut pointer) => {
+                pointer.path.push(index);
+                Ok(Value::Pointer(pointer))
+            }
+            Value::Composite(components) => {
+                // Out-of-bounds indices read the last element, as robust buffer access allows.
+                let index = index.min(components.len() - 1);
+                Ok(components[index].clone())
+            }
+            _ => Err(CpuError::Invalid("indexed a scalar")),
+        }
+    }
+
+    fn dereference(&mut self, pointer: &Pointer) -> Result<&mut Value, CpuError> {
+        let mut value =
+            match pointer.root {
+                Root::Local { frame, variable } => &mut self.frames[frame].locals[variable.index()],
+                Root::Global(global) => {
+                    let variable = &self.module.global_variables[global];
+                    self.globals[global.index()]
+                        .as_mut()
+                        .ok_or_else(|| match variable.space {
+                            AddressSpace::Uniform => variable.binding.as_ref().map_or(
+                                CpuError::Invalid("uniform without a binding"),
+                                |binding| CpuError::MissingBinding(binding.binding),
+                            ),
+                            space => CpuError::Unsupported(format!("{space:?} variables")),
+                        })?
+                }
+            };
+        for &index in &pointer.path {
+            let Value::Composite(components) = value else {
+                return Err(CpuError::Invalid("indexed a scalar"));
+            };
+            let index = index.min(components.len() - 1);
+            value = &mut components[index];
+        }
+        Ok(value)
+    }
+
+    fn texture_binding(&mut self, image: Handle<Expression>) -> Result<u32, CpuError> {
+        let Value::Handle(global) = self.value(image)? else {
+            return Err(CpuError::Invalid("expected a texture"));
+        };
+        match &self.module.global_variables[global].binding {
+            Some(binding) if binding.group == 0 => Ok(binding.binding),
+            Some(_) => Err(CpuError::Unsupported("bind groups other than 0".into())),
+            None => Err(CpuError::Invalid("texture without a binding")),
+        }
+    }
+}
+
+fn literal_value(literal: naga::Literal) -> Result<Value, CpuError> {
+    match literal {
+        naga::Literal::Bool(value) => Ok(Value::Bool(value)),
+        naga::Literal::I32(value) => Ok(Value::I32(value)),
+        naga::Literal::U32(value) => Ok(Value::U32(value)),
+        naga::Literal::F32(value) => Ok(Value::F32(value)),
+        // Concretization leaves no abstract literals behind, but they're trivial to handle.
+        naga::Literal::AbstractInt(value) => Ok(Value::I32(value as i32)),
+        naga::Literal::AbstractFloat(value) => Ok(Value::F32(value as f32)),
+        naga::Literal::F64(_) | naga::Literal::I64(_) | naga::Literal::U64(_) => {
+            Err(CpuError::Unsupported("64-bit scalars".into()))
+        }
+    }
+}
+
+/// Evaluate one of the module's constant expressions, which the front end has already folded.
+fn const_value(module: &Module, handle: Handle<Expression>) -> Result<Value, CpuError> {
+    match &module.global_expressions[handle] {
+        Expression::Literal(literal) => literal_value(*literal),
+        Expression::Constant(constant) => const_value(module, module.constants[*constant].init),
+        Expression::ZeroValue(ty) => zero_value(module, *ty),
+        Expression::Compose { ty, components } => {
+            let components = components
+                .iter()
+                .map(|component| const_value(module, *component))
+                .collect::<Result<_, _>>()?;
+            Ok(compose(module, *ty, components))
+        }
+        Expression::Splat { size, value } => Ok(Value::Composite(vec![
+            const_value(module, *value)?;
+            *size as usize
+        ])),
+        _ => Err(CpuError::Unsupported(
+            "pipeline-overridable constants".into(),
+        )),
+    }
+}
+
+fn zero_scalar(scalar: Scalar) -> Result<Value, CpuError> {
+    if scalar.width > 4 {
+        return Err(CpuError::Unsupported("64-bit scalars".into()));
+    }
+    Ok(match scalar.kind {
+        ScalarKind::Bool => Value::Bool(false),
+        ScalarKind::Sint | ScalarKind::AbstractInt => Value::I32(0),
+        ScalarKind::Uint => Value::U32(0),
+        ScalarKind::Float | ScalarKind::AbstractFloat => Value::F32(0.0),
+    })
+}
+
+fn zero_value(module: &Module, ty: Handle<Type>) -> Result<Value, CpuError> {
+    match &module.types[ty].inner {
+        TypeInner::Scalar(scalar) | TypeInner::Atomic(scalar) => zero_scalar(*scalar),
+        TypeInner::Vector { size, scalar } => Ok(Value::Composite(vec![
+            zero_scalar(*scalar)?;
+            *size as usize
+        ])),
+        TypeInner::Matrix {
+            columns,
+            rows,
+            scalar,
+        } => Ok(Value::Composite(vec![
+            Value::Composite(vec![
+                zero_scalar(
+                    *scalar
+                )?;
+                *rows as usize
+            ]);
+            *columns as usize
+        ])),
+        TypeInner::Array {
+            base,
+            size: ArraySize::Constant(size),
+            ..
+        } => Ok(Value::Composite(vec![
+            zero_value(module, *base)?;
+            size.get() as usize
+        ])),
+        TypeInner::Struct { members, .. } => members
+            .iter()
+            .map(|member| zero_value(module, member.ty))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        _ => Err(CpuError::Unsupported(format!(
+            "variables of type {:?}",
+            module.types[ty].inner
+        ))),
+    }
+}
+
+/// Build a composite, flattening vector components of vectors such as `vec4(v.xy, 0.0, 1.0)`.
+fn compose(module: &Module, ty: Handle<Type>, components: Vec<Value>) -> Value {
+    if let TypeInner::Vector { .. } = module.types[ty].inner {
+        let mut flat = Vec::new();
+        for component in components {
+            match component {
+                Value::Composite(inner) => flat.extend(inner),
+                scalar => flat.push(scalar),
+            }
+        }
+        Value::Composite(flat)
+    } else {
+        Value::Composite(components)
+    }
+}
+
+/// Decode a value of type `ty` from a uniform buffer laid out by the WGSL rules.
+fn read_value(
+    module: &Module,
+    ty: Handle<Type>,
+    bytes: &[u8],
+    offset: usize,
+) -> Result<Value, CpuError> {
+    let read_scalar = |scalar: Scalar, offset: usize| -> Result<Value, CpuError> {
+        let word = bytes
+            .get(offset..offset + 4)
+            .ok_or(CpuError::Invalid("uniform buffer is too small"))?;
+        let word = u32::from_le_bytes(word.try_into().unwrap());
+        Ok(match scalar.kind {
+            ScalarKind::Sint => Value::I32(word as i32),
+            ScalarKind::Uint => Value::U32(word),
+            ScalarKind::Float => Value::F32(f32::from_bits(word)),
+            _ => return Err(CpuError::Unsupported(format!("{scalar:?} uniforms"))),
+        })
+    };
+    let read_vector = |size: usize, scalar: Scalar, offset: usize| -> Result<Value, CpuError> {
+        (0..size)
+            .map(|index| read_scalar(scalar, offset + index * 4))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite)
+    };
+
+    match &module.types[ty].inner {
+        TypeInner::Scalar(scalar) if scalar.width == 4 => read_scalar(*scalar, offset),
+        TypeInner::Vector { size, scalar } if scalar.width == 4 => {
+            read_vector(*size as usize, *scalar, offset)
+        }
+        TypeInner::Matrix {
+            columns,
+            rows,
+            scalar,
+        } if scalar.width == 4 => {
+            // Columns are aligned like vectors: 8 bytes for vec2, 16 for vec3 and vec4.
+            let stride = if *rows as usize == 2 { 8 } else { 16 };
+            (0..*columns as usize)
+                .map(|column| read_vector(*rows as usize, *scalar, offset + column * stride))
+                .collect::<Result<_, _>>()
+                .map(Value::Composite)
+        }
+        TypeInner::Array {
+            base,
+            size: ArraySize::Constant(size),
+            stride,
+        } => (0..size.get() as usize)
+            .map(|index| read_value(module, *base, bytes, offset + index * *stride as usize))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        TypeInner::Struct { members, .. } => members
+            .iter()
+            .map(|member| read_value(module, member.ty, bytes, offset + member.offset as usize))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        inner => Err(CpuError::Unsupported(format!("uniforms of type {inner:?}"))),
+    }
+}
+
+/// How the channels of a texel are stored.
+#[derive(Clone, Copy)]
+enum Channel {
+    Unorm8,
+    Snorm8,
+    Uint8,
+    Sint8,
+    Float16,
+    Float32,
+    Uint32,
+    Sint32,
+}
+
+impl Channel {
+    fn size(self) -> usize {
+        match self {
+            Channel::Unorm8 | Channel::Snorm8 | Channel::Uint8 | Channel::Sint8 => 1,
+            Channel::Float16 => 2,
+            Channel::Float32 | Channel::Uint32 | Channel::Sint32 => 4,
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> Value {
+        let word = || u32::from_le_bytes(bytes[..4].try_into().unwrap());
+        match self {
+            Channel::Unorm8 => Value::F32(bytes[0] as f32 / 255.0),
+            Channel::Snorm8 => Value::F32((bytes[0] as i8 as f32 / 127.0).max(-1.0)),
+            Channel::Uint8 => Value::U32(bytes[0] as u32),
+            Channel::Sint8 => Value::I32(bytes[0] as i8 as i32),
+            Channel::Float16 => Value::F32(half::f16::from_le_bytes([bytes[0], bytes[1]]).to_f32()),
+            Channel::Float32 => Value::F32(f32::from_bits(word())),
+            Channel::Uint32 => Value::U32(word()),
+            Channel::Sint32 => Value::I32(word() as i32),
+        }
+    }
+
+    fn write(self, value: &Value, bytes: &mut [u8]) -> Result<(), CpuError> {
+        match (self, value) {
+            (Channel::Unorm8, Value::F32(value)) => {
+                bytes[0] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+            (Channel::Snorm8, Value::F32(value)) => {
+                bytes[0] = (value.clamp(-1.0, 1.0) * 127.0).round() as i8 as u8;
+            }
+            (Channel::Uint8, Value::U32(value)) => bytes[0] = *value as u8,
+            (Channel::Sint8, Value::I32(value)) => bytes[0] = *value as i8 as u8,
+            (Channel::Float16, Value::F32(value)) => {
+                bytes.copy_from_slice(&half::f16::from_f32(*value).to_le_bytes());
+            }
+            (Channel::Float32, Value::F32(value)) => bytes.copy_from_slice(&value.to_le_bytes()),
+            (Channel::Uint32, Value::U32(value)) => bytes.copy_from_slice(&value.to_le_bytes()),
+            (Channel::Sint32, Value::I32(value)) => bytes.copy_from_slice(&value.to_le_bytes()),
+            _ => return Err(CpuError::Invalid("texel value doesn't match the format")),
+        }
+        Ok(())
+    }
+
+    /// What missing channels read as: 0 for color and 1 for alpha.
+    fn default(self, channel: usize) -> Value {
+        let one = channel == 3;
+        match self {
+            Channel::Unorm8 | Channel::Snorm8 | Channel::Float16 | Channel::Float32 => {
+                Value::F32(if one { 1.0 } else { 0.0 })
+            }
+            Channel::Uint8 | Channel::Uint32 => Value::U32(one as u32),
+            Channel::Sint8 | Channel::Sint32 => Value::I32(one as i32),
+        }
+    }
+}
+
+fn texel_layout(format: TextureFormat) -> Result<(Channel, usize), CpuError> {
+    Ok(match format {
+        TextureFormat::R8Unorm => (Channel::Unorm8, 1),
+        TextureFormat::Rg8Unorm => (Channel::Unorm8, 2),
+        TextureFormat::Rgba8Unorm => (Channel::Unorm8, 4),
+        TextureFormat::Rgba8Snorm => (Channel::Snorm8, 4),
+        TextureFormat::Rgba8Uint => (Channel::Uint8, 4),
+        TextureFormat::Rgba8Sint => (Channel::Sint8, 4),
+        TextureFormat::Rgba16Float => (Channel::Float16, 4),
+        TextureFormat::R32Float => (Channel::Float32, 1),
+        TextureFormat::Rg32Float => (Channel::Float32, 2),
+        TextureFormat::Rgba32Float => (Channel::Float32, 4),
+        TextureFormat::R32Uint => (Channel::Uint32, 1),
+        TextureFormat::Rg32Uint => (Channel::Uint32, 2),
+        TextureFormat::Rgba32Uint => (Channel::Uint32, 4),
+        TextureFormat::R32Sint => (Channel::Sint32, 1),
+        TextureFormat::Rg32Sint => (Channel::Sint32, 2),
+        TextureFormat::Rgba32Sint => (Channel::Sint32, 4),
+        format => return Err(CpuError::Unsupported(format!("{format:?} textures"))),
+    })
+}
+
+/// Byte offset of a texel, or `None` outside the texture.
+fn texel_offset(texture: &CpuTexture, coordinate: IVec2, texel_size: usize) -> Option<usize> {
+    let size = texture.size.as_ivec2();
+    if coordinate.cmplt(IVec2::ZERO).any() || coordinate.cmpge(size).any() {
+        return None;
+    }
+    Some((coordinate.y as usize * size.x as usize + coordinate.x as usize) * texel_size)
+}
+
+/// `textureLoad`. Loads outside the texture return zero, which robust access allows.
+fn read_texel(texture: &CpuTexture, coordinate: IVec2) -> Result<Value, CpuError> {
+    let (channel, channels) = texel_layout(texture.format)?;
+    let Some(offset) = texel_offset(texture, coordinate, channel.size() * channels) else {
+        return Ok(Value::Composite(vec![channel.default(0); 4]));
+    };
+    Ok(Value::Composite(
+        (0..4)
+            .map(|index| {
+                if index < channels {
+                    let start = offset + index * channel.size();
+                    channel.read(&texture.data[start..start + channel.size()])
+                } else {
+                    channel.default(index)
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// `textureStore`. Stores outside the texture are discarded.
+fn write_texel(texture: &mut CpuTexture, coordinate: IVec2, value: &Value) -> Result<(), CpuError> {
+    let (channel, channels) = texel_layout(texture.format)?;
+    let Some(offset) = texel_offset(texture, coordinate, channel.size() * channels) else {
+        return Ok(());
+    };
+    for (index, component) in value.components()?.iter().take(channels).enumerate() {
+        let start = offset + index * channel.size();
+        channel.write(component, &mut texture.data[start..start + channel.size()])?;
+    }
+    Ok(())
+}
+
+/// Apply `f` to every scalar of a value.
+fn map(value: Value, f: &dyn Fn(Value) -> Result<Value, CpuError>) -> Result<Value, CpuError> {
+    match value {
+        Value::Composite(components) => components
+            .into_iter()
+            .map(|component| map(component, f))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        scalar => f(scalar),
+    }
+}
+
+/// Apply `f` to matching scalars of two values, splatting a scalar against a composite.
+fn zip(
+    a: Value,
+    b: Value,
+    f: &dyn Fn(Value, Value) -> Result<Value, CpuError>,
+) -> Result<Value, CpuError> {
+    match (a, b) {
+        (Value::Composite(a), Value::Composite(b)) => a
+            .into_iter()
+            .zip(b)
+            .map(|(a, b)| zip(a, b, f))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        (Value::Composite(a), b) => a
+            .into_iter()
+            .map(|a| zip(a, b.clone(), f))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        (a, Value::Composite(b)) => b
+            .into_iter()
+            .map(|b| zip(a.clone(), b, f))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        (a, b) => f(a, b),
+    }
+}
+
+fn zip3(
+    a: Value,
+    b: Value,
+    c: Value,
+    f: &dyn Fn(Value, Value, Value) -> Result<Value, CpuError>,
+) -> Result<Value, CpuError> {
+    let composite = |value: &Value, index: usize| match value {
+        Value::Composite(components) => components[index].clone(),
+        scalar => scalar.clone(),
+    };
+    let len = [&a, &b, &c].into_iter().find_map(|value| match value {
+        Value::Composite(components) => Some(components.len()),
+        _ => None,
+    });
+    match len {
+        Some(len) => (0..len)
+            .map(|index| {
+                zip3(
+                    composite(&a, index),
+                    composite(&b, index),
+                    composite(&c, index),
+                    f,
+                )
+            })
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        None => f(a, b, c),
+    }
+}
+
+fn unary(op: UnaryOperator, value: Value) -> Result<Value, CpuError> {
+    Ok(match (op, value) {
+        (UnaryOperator::Negate, Value::F32(value)) => Value::F32(-value),
+        (UnaryOperator::Negate, Value::I32(value)) => Value::I32(value.wrapping_neg()),
+        (UnaryOperator::LogicalNot, Value::Bool(value)) => Value::Bool(!value),
+        (UnaryOperator::BitwiseNot, Value::I32(value)) => Value::I32(!value),
+        (UnaryOperator::BitwiseNot, Value::U32(value)) => Value::U32(!value),
+        _ => return Err(CpuError::Invalid("unary operator on the wrong type")),
+    })
+}
+
+fn binary(op: BinaryOperator, left: Value, right: Value) -> Result<Value, CpuError> {
+    if op == BinaryOperator::Multiply && (left.is_matrix() || right.is_matrix()) {
+        return multiply_matrix(left, right);
+    }
+    zip(left, right, &|left, right| binary_scalar(op, left, right))
+}
+
+macro_rules! integer_op {
+    ($op:expr, $x:expr, $y:expr, $variant:path) => {{
+        let (x, y) = ($x, $y);
+        match $op {
+            BinaryOperator::Add => $variant(x.wrapping_add(y)),
+            BinaryOperator::Subtract => $variant(x.wrapping_sub(y)),
+            BinaryOperator::Multiply => $variant(x.wrapping_mul(y)),
+            // WGSL defines division by zero to return the dividend, and remainder by zero zero.
+            BinaryOperator::Divide => $variant(if y == 0 { x } else { x.wrapping_div(y) }),
+            BinaryOperator::Modulo => $variant(if y == 0 { 0 } else { x.wrapping_rem(y) }),
+            BinaryOperator::Equal => Value::Bool(x == y),
+            BinaryOperator::NotEqual => Value::Bool(x != y),
+            BinaryOperator::Less => Value::Bool(x < y),
+            BinaryOperator::LessEqual => Value::Bool(x <= y),
+            BinaryOperator::Greater => Value::Bool(x > y),
+            BinaryOperator::GreaterEqual => Value::Bool(x >= y),
+            BinaryOperator::And => $variant(x & y),
+            BinaryOperator::ExclusiveOr => $variant(x ^ y),
+            BinaryOperator::InclusiveOr => $variant(x | y),
+            _ => return Err(CpuError::Invalid("binary operator on the wrong types")),
+        }
+    }};
+}
+
+fn binary_scalar(op: BinaryOperator, left: Value, right: Value) -> Result<Value, CpuError> {
+    Ok(match (left, right) {
+        (Value::F32(x), Value::F32(y)) => match op {
+            BinaryOperator::Add => Value::F32(x + y),
+            BinaryOperator::Subtract => Value::F32(x - y),
+            BinaryOperator::Multiply => Value::F32(x * y),
+            BinaryOperator::Divide => Value::F32(x / y),
+            // Truncating remainder, like WGSL's `%`.
+            BinaryOperator::Modulo => Value::F32(x % y),
+            BinaryOperator::Equal => Value::Bool(x == y),
+            BinaryOperator::NotEqual => Value::Bool(x != y),
+            BinaryOperator::Less => Value::Bool(x < y),
+            BinaryOperator::LessEqual => Value::Bool(x <= y),
+            BinaryOperator::Greater => Value::Bool(x > y),
+            BinaryOperator::GreaterEqual => Value::Bool(x >= y),
+            _ => return Err(CpuError::Invalid("binary operator on the wrong types")),
+        },
+        // Shift amounts are taken modulo the bit width.
+        (Value::I32(x), Value::U32(y)) if op == BinaryOperator::ShiftLeft => {
+            Value::I32(x.wrapping_shl(y))
+        }
+        (Value::I32(x), Value::U32(y)) if op == BinaryOperator::ShiftRight => {
+            Value::I32(x.wrapping_shr(y))
+        }
+        (Value::U32(x), Value::U32(y)) if op == BinaryOperator::ShiftLeft => {
+            Value::U32(x.wrapping_shl(y))
+        }
+        (Value::U32(x), Value::U32(y)) if op == BinaryOperator::ShiftRight => {
+            Value::U32(x.wrapping_shr(y))
+        }
+        (Value::I32(x), Value::I32(y)) => integer_op!(op, x, y, Value::I32),
+        (Value::U32(x), Value::U32(y)) => integer_op!(op, x, y, Value::U32),
+        (Value::Bool(x), Value::Bool(y)) => match op {
+            BinaryOperator::Equal => Value::Bool(x == y),
+            BinaryOperator::NotEqual => Value::Bool(x != y),
+            BinaryOperator::LogicalAnd | BinaryOperator::And => Value::Bool(x && y),
+            BinaryOperator::LogicalOr | BinaryOperator::InclusiveOr => Value::Bool(x || y),
+            _ => return Err(CpuError::Invalid("binary operator on the wrong types")),
+        },
+        _ => return Err(CpuError::Invalid("binary operator on the wrong types")),
+    })
+}
+
+fn multiply_matrix(left: Value, right: Value) -> Result<Value, CpuError> {
+    let add = |a: Value, b: Value| binary(BinaryOperator::Add, a, b);
+    let scale = |vector: &Value, scalar: &Value| {
+        binary(BinaryOperator::Multiply, vector.clone(), scalar.clone())
+    };
+    // Sum of the columns of `matrix` weighted by the components of `vector`.
+    let matrix_vector = |matrix: &Value, vector: &Value| -> Result<Value, CpuError> {
+        let mut sum: Option<Value> = None;
+        for (column, weight) in matrix.components()?.iter().zip(vector.components()?) {
+            let term = scale(column, weight)?;
+            sum = Some(match sum {
+                Some(sum) => add(sum, term)?,
+                None => term,
+            });
+        }
+        sum.ok_or(CpuError::Invalid("empty matrix"))
+    };
+
+    match (left.is_matrix(), right.is_matrix()) {
+        (true, true) => right
+            .components()?
+            .iter()
+            .map(|column| matrix_vector(&left, column))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        (true, false) if right.is_vector() => matrix_vector(&left, &right),
+        (false, true) if left.is_vector() => right
+            .components()?
+            .iter()
+            .map(|column| dot(left.clone(), column.clone()))
+            .collect::<Result<_, _>>()
+            .map(Value::Composite),
+        // Matrix times scalar.
+        _ => zip(left, right, &|left, right| {
+            binary_scalar(BinaryOperator::Multiply, left, right)
+        }),
+    }
+}
+
+fn relational(fun: RelationalFunction, argument: Value) -> Result<Value, CpuError> {
+    match fun {
+        RelationalFunction::All | RelationalFunction::Any => {
+            let components = match &argument {
+                Value::Composite(components) => components.clone(),
+                scalar => vec![scalar.clone()],
+            };
+            let values = components
+                .iter()
+                .map(Value::as_bool)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::Bool(if fun == RelationalFunction::All {
+                values.iter().all(|value| *value)
+            } else {
+                values.iter().any(|value| *value)
+            }))
+        }
+        RelationalFunction::IsNan => {
+            map(argument, &|value| Ok(Value::Bool(value.as_f32()?.is_nan())))
+        }
+        RelationalFunction::IsInf => map(argument, &|value| {
+            Ok(Value::Bool(value.as_f32()?.is_infinite()))
+        }),
+    }
+}
+
+/// Apply a float function to every component.
+fn float1(value: Value, f: fn(f32) -> f32) -> Result<Value, CpuError> {
+    map(value, &|value| Ok(Value::F32(f(value.as_f32()?))))
+}
+
+fn float2(a: Value, b: Value, f: fn(f32, f32) -> f32) -> Result<Value, CpuError> {
+    zip(a, b, &|a, b| Ok(Value::F32(f(a.as_f32()?, b.as_f32()?))))
+}
+
+fn float3(a: Value, b: Value, c: Value, f: fn(f32, f32, f32) -> f32) -> Result<Value, CpuError> {
+    zip3(a, b, c, &|a, b, c| {
+        Ok(Value::F32(f(a.as_f32()?, b.as_f32()?, c.as_f32()?)))
+    })
+}
+
+fn dot(a: Value, b: Value) -> Result<Value, CpuError> {
+    let products = binary(BinaryOperator::Multiply, a, b)?;
+    let mut components = products.components()?.iter().cloned();
+    let first = components.next().ok_or(CpuError::Invalid("empty vector"))?;
+    components.try_fold(first, |sum, component| {
+        binary_scalar(BinaryOperator::Add, sum, component)
+    })
+}
+
+fn length(value: Value) -> Result<f32, CpuError> {
+    match value {
+        Value::F32(value) => Ok(value.abs()),
+        vector => Ok(dot(vector.clone(), vector)?.as_f32()?.sqrt()),
+    }
+}
+
+fn determinant(columns: &[Vec<f32>]) -> f32 {
+    if columns.len() == 1 {
+        return columns[0][0];
+    }
+    // Laplace expansion along the first row.
+    (0..columns.len())
+        .map(|skip| {
+            let minor: Vec<Vec<f32>> = columns
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != skip)
+                .map(|(_, column)| column[1..].to_vec())
+                .collect();
+            let sign = if skip % 2 == 0 { 1.0 } else { -1.0 };
+            sign * columns[skip][0] * determinant(&minor)
+        })
+        .sum()
+}
+
+fn bits(value: Value, f: fn(u32) -> u32) -> Result<Value, CpuError> {
+    map(value, &|value| match value {
+        Value::U32(value) => Ok(Value::U32(f(value))),
+        Value::I32(value) => Ok(Value::I32(f(value as u32) as i32)),
+        _ => Err(CpuError::Invalid("expected an integer")),
+    })
+}
+
+fn pack(value: &Value, scale: f32, min: f32, bits: u32) -> Result<Value, CpuError> {
+    let mut packed = 0;
+    for (index, component) in value.components()?.iter().enumerate() {
+        let scaled = (component.as_f32()?.clamp(min, 1.0) * scale).round() as i32 as u32;
+        packed |= (scaled & ((1 << bits) - 1)) << (index as u32 * bits);
+    }
+    Ok(Value::U32(packed))
+}
+
+fn unpack(
+    value: &Value,
+    count: u32,
+    bits: u32,
+    f: &dyn Fn(u32) -> Value,
+) -> Result<Value, CpuError> {
+    let Value::U32(packed) = value else {
+        return Err(CpuError::Invalid("expected a u32"));
+    };
+    Ok(Value::Composite(
+        (0..count)
+            .map(|index| f((packed >> (index * bits)) & ((1 << bits) - 1)))
+            .collect(),
+    ))
+}
+
+fn math(fun: MathFunction, arguments: Vec<Value>) -> Result<Value, CpuError> {
+    let mut arguments = arguments.into_iter();
+    let mut next = || {
+        arguments
+            .next()
+            .ok_or(CpuError::Invalid("missing function argument"))
+    };
+    let a = next()?;
+    match fun {
+        MathFunction::Abs => map(a, &|value| match value {
+            Value::F32(value) => Ok(Value::F32(value.abs())),
+            Value::I32(value) => Ok(Value::I32(value.wrapping_abs())),
+            Value::U32(value) => Ok(Value::U32(value)),
+            _ => Err(CpuError::Invalid("abs of a bool")),
+        }),
+        MathFunction::Min | MathFunction::Max => {
+            let max = fun == MathFunction::Max;
+            zip(a, next()?, &|a, b| {
+                let less = binary_scalar(BinaryOperator::Less, a.clone(), b.clone())?.as_bool()?;
+                Ok(if less == max { b } else { a })
+            })
+        }
+        MathFunction::Clamp => {
+            let (low, high) = (next()?, next()?);
+            let max = math(MathFunction::Max, vec![a, low])?;
+            math(MathFunction::Min, vec![max, high])
+        }
+        MathFunction::Saturate => float1(a, |x| x.clamp(0.0, 1.0)),
+        MathFunction::Cos => float1(a, f32::cos),
+        MathFunction::Cosh => float1(a, f32::cosh),
+        MathFunction::Sin => float1(a, f32::sin),
+        MathFunction::Sinh => float1(a, f32::sinh),
+        MathFunction::Tan => float1(a, f32::tan),
+        MathFunction::Tanh => float1(a, f32::tanh),
+        MathFunction::Acos => float1(a, f32::acos),
+        MathFunction::Asin => float1(a, f32::asin),
+        MathFunction::Atan => float1(a, f32::atan),
+        MathFunction::Atan2 => float2(a, next()?, f32::atan2),
+        MathFunction::Asinh => float1(a, f32::asinh),
+        MathFunction::Acosh => float1(a, f32::acosh),
+        MathFunction::Atanh => float1(a, f32::atanh),
+        MathFunction::Radians => float1(a, f32::to_radians),
+        MathFunction::Degrees => float1(a, f32::to_degrees),
+        MathFunction::Ceil => float1(a, f32::ceil),
+        MathFunction::Floor => float1(a, f32::floor),
+        MathFunction::Round => float1(a, f32::round_ties_even),
+        MathFunction::Fract => float1(a, |x| x - x.floor()),
+        MathFunction::Trunc => float1(a, f32::trunc),
+        MathFunction::Ldexp => zip(a, next()?, &|a, b| {
+            Ok(Value::F32(a.as_f32()? * 2f32.powi(b.as_i64()? as i32)))
+        }),
+        MathFunction::Exp => float1(a, f32::exp),
+        MathFunction::Exp2 => float1(a, f32::exp2),
+        MathFunction::Log => float1(a, f32::ln),
+        MathFunction::Log2 => float1(a, f32::log2),
+        MathFunction::Pow => float2(a, next()?, f32::powf),
+        MathFunction::Dot => dot(a, next()?),
+        MathFunction::Cross => {
+            let b = next()?;
+            let (a, b) = (a.components()?, b.components()?);
+            let component = |i: usize, j: usize| -> Result<f32, CpuError> {
+                Ok(a[i].as_f32()? * b[j].as_f32()? - a[j].as_f32()? * b[i].as_f32()?)
+            };
+            Ok(Value::Composite(vec![
+                Value::F32(component(1, 2)?),
+                Value::F32(component(2, 0)?),
+                Value::F32(component(0, 1)?),
+            ]))
+        }
+        MathFunction::Distance => {
+            let difference = binary(BinaryOperator::Subtract, a, next()?)?;
+            Ok(Value::F32(length(difference)?))
+        }
+        MathFunction::Length => Ok(Value::F32(length(a)?)),
+        MathFunction::Normalize => {
+            let length = Value::F32(length(a.clone())?);
+            binary(BinaryOperator::Divide, a, length)
+        }
+        MathFunction::FaceForward => {
+            let (b, c) = (next()?, next()?);
+            if dot(b, c)?.as_f32()? < 0.0 {
+                Ok(a)
+            } else {
+                map(a, &|value| unary(UnaryOperator::Negate, value))
+            }
+        }
+        MathFunction::Reflect => {
+            let normal = next()?;
+            let scale = Value::F32(2.0 * dot(normal.clone(), a.clone())?.as_f32()?);
+            let offset = binary(BinaryOperator::Multiply, normal, scale)?;
+            binary(BinaryOperator::Subtract, a, offset)
+        }
+        MathFunction::Refract => {
+            let (normal, eta) = (next()?, next()?.as_f32()?);
+            let cos = dot(normal.clone(), a.clone())?.as_f32()?;
+            let k = 1.0 - eta * eta * (1.0 - cos * cos);
+            if k < 0.0 {
+                return map(a, &|_| Ok(Value::F32(0.0)));
+            }
+            let incident = binary(BinaryOperator::Multiply, a, Value::F32(eta))?;
+            let offset = binary(
+                BinaryOperator::Multiply,
+                normal,
+                Value::F32(eta * cos + k.sqrt()),
+            )?;
+            binary(BinaryOperator::Subtract, incident, offset)
+        }
+        MathFunction::Sign => map(a, &|value| match value {
+            Value::F32(value) => Ok(Value::F32(if value == 0.0 { 0.0 } else { value.signum() })),
+            Value::I32(value) => Ok(Value::I32(value.signum())),
+            _ => Err(CpuError::Invalid("sign of an unsigned value")),
+        }),
+        MathFunction::Fma => float3(a, next()?, next()?, f32::mul_add),
+        MathFunction::Mix => float3(a, next()?, next()?, |a, b, t| a * (1.0 - t) + b * t),
+        MathFunction::Step => float2(a, next()?, |edge, x| if x >= edge { 1.0 } else { 0.0 }),
+        MathFunction::SmoothStep => float3(a, next()?, next()?, |low, high, x| {
+            let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        }),
+        MathFunction::Sqrt => float1(a, f32::sqrt),
+        MathFunction::InverseSqrt => float1(a, |x| 1.0 / x.sqrt()),
+        MathFunction::Transpose => {
+            let columns = a.components()?;
+            let rows = columns
+                .first()
+                .ok_or(CpuError::Invalid("empty matrix"))?
+                .components()?
+                .len();
+            (0..rows)
+                .map(|row| {
+                    columns
+                        .iter()
+                        .map(|column| Ok(column.components()?[row].clone()))
+                        .collect::<Result<_, _>>()
+                        .map(Value::Composite)
+                })
+                .collect::<Result<_, _>>()
+                .map(Value::Composite)
+        }
+        MathFunction::Determinant => {
+            let columns = a
+                .components()?
+                .iter()
+                .map(|column| {
+                    column
+                        .components()?
+                        .iter()
+                        .map(Value::as_f32)
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::F32(determinant(&columns)))
+        }
+        MathFunction::CountTrailingZeros => bits(a, u32::trailing_zeros),
+        MathFunction::CountLeadingZeros => bits(a, u32::leading_zeros),
+        MathFunction::CountOneBits => bits(a, u32::count_ones),
+        MathFunction::ReverseBits => bits(a, u32::reverse_bits),
+        MathFunction::FirstTrailingBit => {
+            bits(a, |x| if x == 0 { u32::MAX } else { x.trailing_zeros() })
+        }
+        MathFunction::FirstLeadingBit => map(a, &|value| match value {
+            Value::U32(x) => Ok(Value::U32(if x == 0 {
+                u32::MAX
+            } else {
+                31 - x.leading_zeros()
+            })),
+            // For negative values, the most significant bit that differs from the sign bit.
+            Value::I32(x) => Ok(Value::I32(if x == 0 || x == -1 {
+                -1
+            } else {
+                let magnitude = if x < 0 { !x } else { x };
+                31 - magnitude.leading_zeros() as i32
+            })),
+            _ => Err(CpuError::Invalid("expected an integer")),
+        }),
+        MathFunction::ExtractBits => {
+            let offset = next()?.as_i64()?.min(32) as u32;
+            let count = (next()?.as_i64()? as u32).min(32 - offset);
+            map(a, &|value| {
+                Ok(match value {
+                    _ if count == 0 => match value {
+                        Value::I32(_) => Value::I32(0),
+                        _ => Value::U32(0),
+                    },
+                    Value::U32(x) => Value::U32((x >> offset) & (u32::MAX >> (32 - count))),
+                    // Shift the field to the top, then sign-extend it back down.
+                    Value::I32(x) => Value::I32((x << (32 - offset - count)) >> (32 - count)),
+                    _ => return Err(CpuError::Invalid("expected an integer")),
+                })
+            })
+        }
+        MathFunction::InsertBits => {
+            let inserted = next()?;
+            let offset = next()?.as_i64()?.min(32) as u32;
+            let count = (next()?.as_i64()? as u32).min(32 - offset);
+            let mask = if count == 0 {
+                0
+            } else {
+                (u32::MAX >> (32 - count)) << offset
+            };
+            zip(a, inserted, &|value, inserted| {
+                Ok(match (value, inserted) {
+                    (Value::U32(x), Value::U32(y)) => {
+                        Value::U32((x & !mask) | (y.wrapping_shl(offset) & mask))
+                    }
+                    (Value::I32(x), Value::I32(y)) => Value::I32(
+                        ((x as u32 & !mask) | ((y as u32).wrapping_shl(offset) & mask)) as i32,
+                    ),
+                    _ => return Err(CpuError::Invalid("expected integers")),
+                })
+            })
+        }
+        MathFunction::Pack4x8unorm => pack(&a, 255.0, 0.0, 8),
+        MathFunction::Pack4x8snorm => pack(&a, 127.0, -1.0, 8),
+        MathFunction::Pack2x16unorm => pack(&a, 65535.0, 0.0, 16),
+        MathFunction::Pack2x16snorm => pack(&a, 32767.0, -1.0, 16),
+        MathFunction::Pack4xI8 | MathFunction::Pack4xU8 => {
+            let mut packed = 0;
+            for (index, component) in a.components()?.iter().enumerate() {
+                packed |= (component.as_i64()? as u32 & 0xff) << (index * 8);
+            }
+            Ok(Value::U32(packed))
+        }
+        MathFunction::Unpack4x8unorm => unpack(&a, 4, 8, &|x| Value::F32(x as f32 / 255.0)),
+        MathFunction::Unpack4x8snorm => unpack(&a, 4, 8, &|x| {
+            Value::F32((x as u8 as i8 as f32 / 127.0).max(-1.0))
+        }),
+        MathFunction::Unpack2x16unorm => unpack(&a, 2, 16, &|x| Value::F32(x as f32 / 65535.0)),
+        MathFunction::Unpack2x16snorm => unpack(&a, 2, 16, &|x| {
+            Value::F32((x as u16 as i16 as f32 / 32767.0).max(-1.0))
+        }),
+        MathFunction::Unpack4xI8 => unpack(&a, 4, 8, &|x| Value::I32(x as u8 as i8 as i32)),
+        MathFunction::Unpack4xU8 => unpack(&a, 4, 8, &Value::U32),
+        fun => Err(CpuError::Unsupported(format!("{fun:?}"))),
+    }
+}
+
+fn cast(value: Value, kind: ScalarKind, convert: bool) -> Result<Value, CpuError> {
+    Ok(match (value, kind, convert) {
+        (Value::F32(x), ScalarKind::Float, _) => Value::F32(x),
+        (Value::I32(x), ScalarKind::Sint, _) => Value::I32(x),
+        (Value::U32(x), ScalarKind::Uint, _) => Value::U32(x),
+        (Value::Bool(x), ScalarKind::Bool, _) => Value::Bool(x),
+        // Conversions. Float to integer saturates, like `as`.
+        (Value::F32(x), ScalarKind::Sint, true) => Value::I32(x as i32),
+        (Value::F32(x), ScalarKind::Uint, true) => Value::U32(x as u32),
+        (Value::F32(x), ScalarKind::Bool, true) => Value::Bool(x != 0.0),
+        (Value::I32(x), ScalarKind::Float, true) => Value::F32(x as f32),
+        (Value::I32(x), ScalarKind::Uint, _) => Value::U32(x as u32),
+        (Value::I32(x), ScalarKind::Bool, true) => Value::Bool(x != 0),
+        (Value::U32(x), ScalarKind::Float, true) => Value::F32(x as f32),
+        (Value::U32(x), ScalarKind::Sint, _) => Value::I32(x as i32),
+        (Value::U32(x), ScalarKind::Bool, true) => Value::Bool(x != 0),
+        (Value::Bool(x), ScalarKind::Float, true) => Value::F32(x as u32 as f32),
+        (Value::Bool(x), ScalarKind::Sint, true) => Value::I32(x as i32),
+        (Value::Bool(x), ScalarKind::Uint, true) => Value::U32(x as u32),
+        // Bitcasts.
+        (Value::F32(x), ScalarKind::Uint, false) => Value::U32(x.to_bits()),
+        (Value::F32(x), ScalarKind::Sint, false) => Value::I32(x.to_bits() as i32),
+        (Value::U32(x), ScalarKind::Float, false) => Value::F32(f32::from_bits(x)),
+        (Value::I32(x), ScalarKind::Float, false) => Value::F32(f32::from_bits(x as u32)),
+        _ => return Err(CpuError::Invalid("invalid conversion")),
+    })
+}
@@ -0,0 +1,400 @@
+use std::{fmt, slice};
+
+use bevy::app::{App, Plugin};
+use bevy::log::error_once;
+use bevy::math::UVec3;
+use bevy::prelude::{IntoScheduleConfigs, Res, ResMut, Resource};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{BindGroup, ComputePass, DynamicUniformBuffer};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::settings::WgpuLimits;
+use bevy::render::texture::GpuImage;
+use bevy::render::{Render, RenderApp, RenderSet};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::ComputeShaderImage;
+use crate::bindings::{ComputeShaderLayout, has_dynamic_offset};
+use crate::config::PlaygroundConfig;
+
+/// How many dimensions of a workgroup span more than one invocation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum DispatchShape {
+    /// `(size, 1, 1)` workgroups, one row of texels per group row.
+    #[serde(rename = "1d")]
+    #[value(name = "1d")]
+    D1,
+    /// `(size, size, 1)` workgroups.
+    #[default]
+    #[serde(rename = "2d")]
+    #[value(name = "2d")]
+    D2,
+    /// `(size, size, size)` workgroups, for 3D textures.
+    #[serde(rename = "3d")]
+    #[value(name = "3d")]
+    D3,
+}
+
+impl DispatchShape {
+    pub fn workgroup_size(self, size: u32) -> UVec3 {
+        match self {
+            DispatchShape::D1 => UVec3::new(size, 1, 1),
+            DispatchShape::D2 => UVec3::new(size, size, 1),
+            DispatchShape::D3 => UVec3::splat(size),
+        }
+    }
+}
+
+/// One `dispatch_workgroups` call of a [`DispatchPlan`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DispatchCall {
+    pub workgroups: UVec3,
+    /// Added to `global_invocation_id` by the shader, through `dispatch.offset`.
+    pub offset: UVec3,
+}
+
+/// The dispatches that cover every texel of a texture exactly once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DispatchPlan {
+    pub calls: Vec<DispatchCall>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DispatchError {
+    WorkgroupTooLarge {
+        size: UVec3,
+        max: UVec3,
+    },
+    TooManyInvocations {
+        invocations: u32,
+        max: u32,
+    },
+    /// The grid is split over several calls, but the shader has no `dispatch` binding to offset
+    /// them by, so every call would cover the first part again.
+    MissingOffset {
+        calls: usize,
+    },
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchError::WorkgroupTooLarge { size, max } => {
+                write!(f, "workgroup size {size} exceeds the device limit of {max}")
+            }
+            DispatchError::TooManyInvocations { invocations, max } => write!(
+                f,
+                "{invocations} invocations per workgroup exceed the device limit of {max}"
+            ),
+            DispatchError::MissingOffset { calls } => write!(
+                f,
+                "covering the texture takes {calls} dispatches, so the shader must declare \
+                 `dispatch` and add `dispatch.offset` to `global_invocation_id`"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
+
+impl DispatchPlan {
+    /// Cover `extent` texels with workgroups of `workgroup_size`, rounding up so partial
+    /// workgroups reach the last rows and columns, and splitting any dimension that needs more
+    /// than the device's `max_compute_workgroups_per_dimension` into several calls.
+    pub fn new(
+        extent: UVec3,
+        workgroup_size: UVec3,
+        limits: &WgpuLimits,
+    ) -> Result<Self, DispatchError> {
+        let max_size = UVec3::new(
+            limits.max_compute_workgroup_size_x,
+            limits.max_compute_workgroup_size_y,
+            limits.max_compute_workgroup_size_z,
+        );
+        if workgroup_size.cmpgt(max_size).any() {
+            return Err(DispatchError::WorkgroupTooLarge {
+                size: workgroup_size,
+                max: max_size,
+            });
+        }
+        let invocations = workgroup_size.element_product();
+        if invocations > limits.max_compute_invocations_per_workgroup {
+            return Err(DispatchError::TooManyInvocations {
+                invocations,
+                max: limits.max_compute_invocations_per_workgroup,
+            });
+        }
+
+        let extent = extent.max(UVec3::ONE);
+        let groups = UVec3::new(
+            extent.x.div_ceil(workgroup_size.x),
+            extent.y.div_ceil(workgroup_size.y),
+            extent.z.div_ceil(workgroup_size.z),
+        );
+        let max_groups = limits.max_compute_workgroups_per_dimension;
+        let chunks = |count: u32| {
+            (0..count.div_ceil(max_groups)).map(move |chunk| {
+                (
+                    chunk * max_groups,
+                    (count - chunk * max_groups).min(max_groups),
+                )
+            })
+        };
+
+        let mut calls = Vec::new();
+        for (z, depth) in chunks(groups.z) {
+            for (y, height) in chunks(groups.y) {
+                for (x, width) in chunks(groups.x) {
+                    calls.push(DispatchCall {
+                        workgroups: UVec3::new(width, height, depth),
+                        offset: UVec3::new(x, y, z) * workgroup_size,
+                    });
+                }
+            }
+        }
+        Ok(Self { calls })
+    }
+
+    /// Check that a shader can run the plan: past the first call, only a shader with the
+    /// `dispatch` binding knows where its invocations are.
+    pub fn check_offset(&self, dynamic_offset: bool) -> Result<(), DispatchError> {
+        if self.calls.len() > 1 && !dynamic_offset {
+            return Err(DispatchError::MissingOffset {
+                calls: self.calls.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub use offset::DispatchOffset;
+
+// Holds just the struct for its derive's unused check functions, like `globals::uniform`.
+#[allow(dead_code)]
+mod offset {
+    use bevy::math::UVec3;
+    use bevy::render::render_resource::ShaderType;
+
+    /// Bound to the compute shader variable named `dispatch`, with one dynamic offset per call.
+    #[derive(Clone, Default, ShaderType)]
+    pub struct DispatchOffset {
+        pub offset: UVec3,
+    }
+}
+
+/// The dispatch calls for this frame, each paired with its dynamic offset into `offsets`.
+#[derive(Resource, Default)]
+pub struct ComputeShaderDispatch {
+    pub calls: Vec<(DispatchCall, u32)>,
+    pub offsets: DynamicUniformBuffer<DispatchOffset>,
+}
+
+pub struct DispatchPlugin;
+
+impl Plugin for DispatchPlugin {
+    fn build(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<ComputeShaderDispatch>()
+            .add_systems(Render, prepare_dispatch.in_set(RenderSet::PrepareResources));
+    }
+}
+
+impl ComputeShaderDispatch {
+    /// Plan the calls that cover `extent` texels and upload their offsets. `dynamic_offset` is
+    /// whether the shader has the `dispatch` binding, without which there are no calls if the
+    /// grid has to be split.
+    pub fn prepare(
+        &mut self,
+        extent: UVec3,
+        config: &PlaygroundConfig,
+        dynamic_offset: bool,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+    ) -> Result<(), DispatchError> {
+        self.calls.clear();
+        self.offsets.clear();
+        let workgroup_size = config.dispatch_shape.workgroup_size(config.workgroup_size);
+        let plan = DispatchPlan::new(extent, workgroup_size, &render_device.limits())
+            .and_then(|plan| plan.check_offset(dynamic_offset).map(|()| plan));
+        if let Ok(plan) = &plan {
+            for call in &plan.calls {
+                let dynamic_offset = self.offsets.push(&DispatchOffset {
+                    offset: call.offset,
+                });
+                self.calls.push((*call, dynamic_offset));
+            }
+        }
+        self.offsets.write_buffer(render_device, render_queue);
+        plan.map(|_| ())
+    }
+
+    /// Record the calls into `pass`. The bind group is set with each call's offset if its
+    /// layout has the dynamic `dispatch` binding.
+    pub fn record(&self, pass: &mut ComputePass, bind_group: &BindGroup, dynamic_offset: bool) {
+        for (call, offset) in &self.calls {
+            let offsets = if dynamic_offset {
+                slice::from_ref(offset)
+            } else {
+                &[]
+            };
+            pass.set_bind_group(0, bind_group, offsets);
+            pass.dispatch_workgroups(call.workgroups.x, call.workgroups.y, call.workgroups.z);
+        }
+    }
+}
+
+fn prepare_dispatch(
+    mut dispatch: ResMut<ComputeShaderDispatch>,
+    config: Res<PlaygroundConfig>,
+    compute_shader_image: Res<ComputeShaderImage>,
+    layout: Res<ComputeShaderLayout>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(output) = gpu_images.get(&compute_shader_image.output) else {
+        dispatch.calls.clear();
+        return;
+    };
+    let extent = UVec3::new(
+        output.size.width,
+        output.size.height,
+        output.size.depth_or_array_layers,
+    );
+    let dynamic_offset = has_dynamic_offset(&layout.main.bindings);
+    if let Err(err) = dispatch.prepare(
+        extent,
+        &config,
+        dynamic_offset,
+        &render_device,
+        &render_queue,
+    ) {
+        error_once!("Not dispatching the compute shader: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(extent: UVec3, workgroup_size: UVec3) -> Vec<DispatchCall> {
+        DispatchPlan::new(extent, workgroup_size, &WgpuLimits::default())
+            .unwrap()
+            .calls
+    }
+
+    #[test]
+    fn rounds_up_to_cover_partial_workgroups() {
+        assert_eq!(
+            plan(UVec3::new(320, 180, 1), DispatchShape::D2.workgroup_size(8)),
+            [DispatchCall {
+                workgroups: UVec3::new(40, 23, 1),
+                offset: UVec3::ZERO,
+            }]
+        );
+    }
+
+    #[test]
+    fn one_dimensional_workgroups() {
+        assert_eq!(
+            plan(UVec3::new(100, 3, 1), DispatchShape::D1.workgroup_size(64)),
+            [DispatchCall {
+                workgroups: UVec3::new(2, 3, 1),
+                offset: UVec3::ZERO,
+            }]
+        );
+    }
+
+    #[test]
+    fn three_dimensional_workgroups() {
+        assert_eq!(
+            plan(UVec3::new(10, 8, 5), DispatchShape::D3.workgroup_size(4)),
+            [DispatchCall {
+                workgroups: UVec3::new(3, 2, 2),
+                offset: UVec3::ZERO,
+            }]
+        );
+    }
+
+    #[test]
+    fn empty_extent_still_dispatches() {
+        assert_eq!(
+            plan(UVec3::ZERO, DispatchShape::D2.workgroup_size(8)),
+            [DispatchCall {
+                workgroups: UVec3::ONE,
+                offset: UVec3::ZERO,
+            }]
+        );
+    }
+
+    #[test]
+    fn splits_dimensions_over_the_limit() {
+        let limits = WgpuLimits {
+            max_compute_workgroups_per_dimension: 16,
+            ..WgpuLimits::default()
+        };
+        // 40 x 20 groups: three calls across, two down.
+        let calls = DispatchPlan::new(UVec3::new(320, 160, 1), UVec3::new(8, 8, 1), &limits)
+            .unwrap()
+            .calls;
+        let call = |workgroups: [u32; 2], offset: [u32; 2]| DispatchCall {
+            workgroups: UVec3::new(workgroups[0], workgroups[1], 1),
+            offset: UVec3::new(offset[0], offset[1], 0),
+        };
+        assert_eq!(
+            calls,
+            [
+                call([16, 16], [0, 0]),
+                call([16, 16], [128, 0]),
+                call([8, 16], [256, 0]),
+                call([16, 4], [0, 128]),
+                call([16, 4], [128, 128]),
+                call([8, 4], [256, 128]),
+            ]
+        );
+        let covered: u32 = calls
+            .iter()
+            .map(|call| call.workgroups.element_product())
+            .sum();
+        assert_eq!(covered, 40 * 20);
+    }
+
+    #[test]
+    fn split_grids_need_the_dispatch_offset() {
+        let limits = WgpuLimits {
+            max_compute_workgroups_per_dimension: 16,
+            ..WgpuLimits::default()
+        };
+        let split =
+            DispatchPlan::new(UVec3::new(320, 160, 1), UVec3::new(8, 8, 1), &limits).unwrap();
+        assert_eq!(split.check_offset(true), Ok(()));
+        assert_eq!(
+            split.check_offset(false),
+            Err(DispatchError::MissingOffset { calls: 6 })
+        );
+
+        // A single call doesn't need it.
+        let single =
+            DispatchPlan::new(UVec3::new(128, 128, 1), UVec3::new(8, 8, 1), &limits).unwrap();
+        assert_eq!(single.check_offset(false), Ok(()));
+    }
+
+    #[test]
+    fn rejects_workgroups_over_the_limits() {
+        let limits = WgpuLimits::default();
+        assert_eq!(
+            DispatchPlan::new(UVec3::ONE, UVec3::new(512, 1, 1), &limits),
+            Err(DispatchError::WorkgroupTooLarge {
+                size: UVec3::new(512, 1, 1),
+                max: UVec3::new(256, 256, 64),
+            })
+        );
+        assert_eq!(
+            DispatchPlan::new(UVec3::ONE, UVec3::new(32, 32, 1), &limits),
+            Err(DispatchError::TooManyInvocations {
+                invocations: 1024,
+                max: 256,
+            })
+        );
+    }
+}
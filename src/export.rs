@@ -0,0 +1,339 @@
+//! Saving state textures in formats that keep their values, for looking at a simulation's fields
+//! in other tools.
+//!
+//! PNG saves a texture the way it's displayed, which clamps floats and rounds them to 8 bits.
+//! The other formats keep the values:
+//!
+//! - `exr` saves an OpenEXR image of half floats for `rgba8unorm` and `rgba16float`, 32-bit
+//!   floats for the `*32float` formats and 32-bit integers for `r32uint`. The channels are named
+//!   R, G, B and A, or Y when there's one.
+//! - `hdr` saves a Radiance HDR image of the displayed colors without clamping. Alpha is
+//!   dropped and negative values are saved as 0.
+//! - `raw` saves the texels as they are in memory, little-endian with the top row first, and
+//!   describes them in a JSON file of the same name next to it.
+//! - `npy` saves the same texels as a NumPy array of shape `(height, width, channels)`.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use bevy::math::{UVec2, Vec3};
+use clap::ValueEnum;
+use exr::prelude::{AnyChannel, AnyChannels, FlatSamples, Image, WritableImage, f16};
+use serde::{Deserialize, Serialize};
+
+use crate::capture::{CaptureError, save_png};
+use crate::format::{StorageFormat, to_rgba32f};
+use crate::readback::TextureReadback;
+
+/// What captures and saved frames are written as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// An 8-bit PNG of what's displayed.
+    #[default]
+    Png,
+    /// An OpenEXR image at the precision of the texture.
+    Exr,
+    /// A Radiance HDR image.
+    Hdr,
+    /// Raw little-endian texels with a JSON description.
+    Raw,
+    /// A NumPy array.
+    Npy,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Exr => "exr",
+            ExportFormat::Hdr => "hdr",
+            ExportFormat::Raw => "bin",
+            ExportFormat::Npy => "npy",
+        }
+    }
+}
+
+/// How the channels of a format are stored.
+#[derive(Clone, Copy)]
+enum Scalar {
+    U8,
+    F16,
+    F32,
+    U32,
+}
+
+impl Scalar {
+    fn of(format: StorageFormat) -> Self {
+        match format {
+            StorageFormat::Rgba8Unorm => Scalar::U8,
+            StorageFormat::Rgba16Float => Scalar::F16,
+            StorageFormat::Rgba32Float | StorageFormat::R32Float | StorageFormat::Rg32Float => {
+                Scalar::F32
+            }
+            StorageFormat::R32Uint => Scalar::U32,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::U8 => 1,
+            Scalar::F16 => 2,
+            Scalar::F32 | Scalar::U32 => 4,
+        }
+    }
+
+    /// The NumPy name of the type.
+    fn name(self) -> &'static str {
+        match self {
+            Scalar::U8 => "uint8",
+            Scalar::F16 => "float16",
+            Scalar::F32 => "float32",
+            Scalar::U32 => "uint32",
+        }
+    }
+
+    /// The NumPy array-protocol type string, little-endian.
+    fn descr(self) -> &'static str {
+        match self {
+            Scalar::U8 => "|u1",
+            Scalar::F16 => "<f2",
+            Scalar::F32 => "<f4",
+            Scalar::U32 => "<u4",
+        }
+    }
+}
+
+/// Save the texels of a readback to `path` in `format`.
+pub fn save_texture(
+    readback: &TextureReadback,
+    path: &Path,
+    format: ExportFormat,
+) -> Result<(), CaptureError> {
+    if format == ExportFormat::Png {
+        return save_png(readback, path);
+    }
+    let storage = StorageFormat::from_texture_format(readback.format)
+        .ok_or(CaptureError::UnsupportedFormat(readback.format))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(CaptureError::Io)?;
+    }
+    match format {
+        ExportFormat::Png => unreachable!(),
+        ExportFormat::Exr => save_exr(readback, storage, path),
+        ExportFormat::Hdr => {
+            let colors = to_rgba32f(readback.format, &readback.data)
+                .ok_or(CaptureError::UnsupportedFormat(readback.format))?;
+            let rgb: Vec<Vec3> = colors.into_iter().map(|color| color.truncate()).collect();
+            write_hdr(path, readback.size, &rgb).map_err(CaptureError::Io)
+        }
+        ExportFormat::Raw => write_raw(readback, storage, path).map_err(CaptureError::Io),
+        ExportFormat::Npy => write_npy(readback, storage, path).map_err(CaptureError::Io),
+    }
+}
+
+fn save_exr(
+    readback: &TextureReadback,
+    format: StorageFormat,
+    path: &Path,
+) -> Result<(), CaptureError> {
+    let names: &[&str] = match format.channels() {
+        1 => &["Y"],
+        2 => &["R", "G"],
+        _ => &["R", "G", "B", "A"],
+    };
+    let scalar = Scalar::of(format);
+    let texel_size = format.channels() * scalar.size();
+    let samples = |channel: usize| {
+        let texels = readback.data.chunks_exact(texel_size);
+        let bytes = move |texel: &[u8]| {
+            let start = channel * scalar.size();
+            texel[start..start + scalar.size()].to_vec()
+        };
+        match scalar {
+            // Unorms are saved as the floats they're read as.
+            Scalar::U8 => FlatSamples::F16(
+                texels
+                    .map(|texel| f16::from_f32(texel[channel] as f32 / 255.0))
+                    .collect(),
+            ),
+            Scalar::F16 => FlatSamples::F16(
+                texels
+                    .map(|texel| f16::from_le_bytes(bytes(texel).try_into().unwrap()))
+                    .collect(),
+            ),
+            Scalar::F32 => FlatSamples::F32(
+                texels
+                    .map(|texel| f32::from_le_bytes(bytes(texel).try_into().unwrap()))
+                    .collect(),
+            ),
+            Scalar::U32 => FlatSamples::U32(
+                texels
+                    .map(|texel| u32::from_le_bytes(bytes(texel).try_into().unwrap()))
+                    .collect(),
+            ),
+        }
+    };
+    let channels = names
+        .iter()
+        .enumerate()
+        .map(|(channel, name)| AnyChannel::new(*name, samples(channel)))
+        .collect();
+    let size = (readback.size.x as usize, readback.size.y as usize);
+    Image::from_channels(size, AnyChannels::sort(channels))
+        .write()
+        .to_file(path)
+        .map_err(CaptureError::Exr)
+}
+
+/// Write flat, not run-length encoded, scanlines of RGBE texels.
+fn write_hdr(path: &Path, size: UVec2, colors: &[Vec3]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        size.y, size.x
+    )?;
+    for color in colors {
+        writer.write_all(&rgbe(*color))?;
+    }
+    writer.flush()
+}
+
+/// A color as three 8-bit mantissas sharing an exponent.
+fn rgbe(color: Vec3) -> [u8; 4] {
+    // `max` also turns NaN into 0.
+    let color = color.max(Vec3::ZERO);
+    let largest = color.max_element();
+    if largest < f32::MIN_POSITIVE {
+        return [0; 4];
+    }
+    // The exponent that puts the largest channel in [0.5, 1), read from its bits since `log2`
+    // rounds values just below a power of two up to it.
+    let exponent = ((largest.to_bits() >> 23) as i32 - 126).clamp(-127, 127);
+    let mantissa = color * 256.0 / 2f32.powi(exponent);
+    [
+        mantissa.x as u8,
+        mantissa.y as u8,
+        mantissa.z as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+fn write_raw(readback: &TextureReadback, format: StorageFormat, path: &Path) -> io::Result<()> {
+    fs::write(path, &readback.data)?;
+    let (width, height, channels) = (readback.size.x, readback.size.y, format.channels());
+    let description = format!(
+        "{{\n  \"width\": {width},\n  \"height\": {height},\n  \"channels\": {channels},\n  \
+         \"shape\": [{height}, {width}, {channels}],\n  \"dtype\": \"{}\",\n  \
+         \"byte_order\": \"little\",\n  \"format\": \"{}\"\n}}\n",
+        Scalar::of(format).name(),
+        format.wgsl_name(),
+    );
+    fs::write(path.with_extension("json"), description)
+}
+
+fn write_npy(readback: &TextureReadback, format: StorageFormat, path: &Path) -> io::Result<()> {
+    let header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+        Scalar::of(format).descr(),
+        readback.size.y,
+        readback.size.x,
+        format.channels(),
+    );
+    // Version 1.0: the magic string, the version, the header's length as a u16 and the header,
+    // padded with spaces and a newline so the data starts at a multiple of 64 bytes.
+    let unpadded = 10 + header.len() + 1;
+    let header = header + &" ".repeat(unpadded.next_multiple_of(64) - unpadded) + "\n";
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(&readback.data)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec4;
+
+    use super::*;
+
+    /// The NumPy type and the JSON dtype of each format's channels.
+    fn types(format: StorageFormat) -> (&'static str, &'static str) {
+        match format {
+            StorageFormat::Rgba8Unorm => ("|u1", "uint8"),
+            StorageFormat::Rgba16Float => ("<f2", "float16"),
+            StorageFormat::Rgba32Float | StorageFormat::R32Float | StorageFormat::Rg32Float => {
+                ("<f4", "float32")
+            }
+            StorageFormat::R32Uint => ("<u4", "uint32"),
+        }
+    }
+
+    fn readback(format: StorageFormat) -> TextureReadback {
+        TextureReadback {
+            size: UVec2::new(3, 2),
+            format: format.texture_format(),
+            data: format.texel(Vec4::new(0.25, 0.5, 0.75, 1.0)).repeat(6),
+        }
+    }
+
+    #[test]
+    fn npy_and_json_describe_the_texels() {
+        let dir = std::env::temp_dir().join(format!("export-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for &format in StorageFormat::value_variants() {
+            let readback = readback(format);
+            let (descr, dtype) = types(format);
+            let channels = format.channels();
+
+            let path = dir.join(format!("{}.npy", format.wgsl_name()));
+            write_npy(&readback, format, &path).unwrap();
+            let bytes = fs::read(&path).unwrap();
+            assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+            let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+            assert_eq!((10 + header_len) % 64, 0, "{format:?}");
+            let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+            assert!(header.ends_with('\n'));
+            assert!(header.contains(&format!("'descr': '{descr}'")), "{header}");
+            assert!(
+                header.contains(&format!("'shape': (2, 3, {channels})")),
+                "{header}"
+            );
+            assert_eq!(&bytes[10 + header_len..], readback.data);
+
+            let path = dir.join(format!("{}.raw", format.wgsl_name()));
+            write_raw(&readback, format, &path).unwrap();
+            assert_eq!(fs::read(&path).unwrap(), readback.data);
+            let json = fs::read_to_string(path.with_extension("json")).unwrap();
+            assert!(
+                json.contains(&format!("\"shape\": [2, 3, {channels}]")),
+                "{json}"
+            );
+            assert!(json.contains(&format!("\"dtype\": \"{dtype}\"")), "{json}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rgbe_keeps_powers_of_two_exact() {
+        let decode = |[r, g, b, e]: [u8; 4]| {
+            let scale = 2f32.powi(e as i32 - 128) / 256.0;
+            Vec3::new(r as f32, g as f32, b as f32) * scale
+        };
+        assert_eq!(rgbe(Vec3::ONE), [128, 128, 128, 129]);
+        assert_eq!(rgbe(Vec3::splat(0.5)), [128, 128, 128, 128]);
+        for exponent in -20..20 {
+            let value = 2f32.powi(exponent);
+            let color = Vec3::new(value, value / 2.0, 0.0);
+            assert_eq!(decode(rgbe(color)), color, "2^{exponent}");
+            // Just below a power of two the mantissa is as large as it gets.
+            let below = f32::from_bits(value.to_bits() - 1);
+            assert_eq!(rgbe(Vec3::splat(below))[0], 255, "2^{exponent}");
+        }
+        assert_eq!(rgbe(Vec3::new(-1.0, f32::NAN, 0.0)), [0; 4]);
+    }
+}
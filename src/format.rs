@@ -0,0 +1,422 @@
+//! Formats of the state and pass textures, and the conversion that displays the formats a sprite
+//! can't show.
+
+use bevy::app::{App, Plugin};
+use bevy::asset::{Assets, Handle};
+use bevy::math::Vec4;
+use bevy::prelude::{Commands, FromWorld, IntoScheduleConfigs, Res, Resource, World};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{
+    self, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel,
+};
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource, BindingType,
+    CachedComputePipelineId, ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache,
+    Shader, ShaderDefVal, ShaderStages, StorageTextureAccess, TextureFormat, TextureViewDimension,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::texture::GpuImage;
+use bevy::render::{Render, RenderApp, RenderSet};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PlaygroundConfig;
+use crate::{ComputeShaderImage, ComputeShaderLabel, ComputeShaderReady};
+
+/// Texture formats that can be written from a compute shader on every adapter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+#[value(rename_all = "lowercase")]
+pub enum StorageFormat {
+    #[default]
+    Rgba8Unorm,
+    Rgba16Float,
+    Rgba32Float,
+    R32Float,
+    R32Uint,
+    Rg32Float,
+}
+
+impl StorageFormat {
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            StorageFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+            StorageFormat::Rgba16Float => TextureFormat::Rgba16Float,
+            StorageFormat::Rgba32Float => TextureFormat::Rgba32Float,
+            StorageFormat::R32Float => TextureFormat::R32Float,
+            StorageFormat::R32Uint => TextureFormat::R32Uint,
+            StorageFormat::Rg32Float => TextureFormat::Rg32Float,
+        }
+    }
+
+    /// The storage format of textures of `format`, if it is one.
+    pub fn from_texture_format(format: TextureFormat) -> Option<Self> {
+        [
+            StorageFormat::Rgba8Unorm,
+            StorageFormat::Rgba16Float,
+            StorageFormat::Rgba32Float,
+            StorageFormat::R32Float,
+            StorageFormat::R32Uint,
+            StorageFormat::Rg32Float,
+        ]
+        .into_iter()
+        .find(|storage| storage.texture_format() == format)
+    }
+
+    /// Number of channels of a texel.
+    pub fn channels(self) -> usize {
+        match self {
+            StorageFormat::Rgba8Unorm | StorageFormat::Rgba16Float | StorageFormat::Rgba32Float => {
+                4
+            }
+            StorageFormat::Rg32Float => 2,
+            StorageFormat::R32Float | StorageFormat::R32Uint => 1,
+        }
+    }
+
+    /// The format's name in WGSL.
+    pub fn wgsl_name(self) -> &'static str {
+        match self {
+            StorageFormat::Rgba8Unorm => "rgba8unorm",
+            StorageFormat::Rgba16Float => "rgba16float",
+            StorageFormat::Rgba32Float => "rgba32float",
+            StorageFormat::R32Float => "r32float",
+            StorageFormat::R32Uint => "r32uint",
+            StorageFormat::Rg32Float => "rg32float",
+        }
+    }
+
+    /// Whether the format stores integers rather than floats.
+    pub fn is_integer(self) -> bool {
+        self == StorageFormat::R32Uint
+    }
+
+    /// The bytes of a texel, dropping the channels the format doesn't have. Integers count up to
+    /// 255, like they're displayed.
+    pub fn texel(self, value: Vec4) -> Vec<u8> {
+        let floats = |channels: usize| {
+            value.to_array()[..channels]
+                .iter()
+                .flat_map(|channel| channel.to_le_bytes())
+                .collect()
+        };
+        match self {
+            StorageFormat::Rgba8Unorm => value
+                .to_array()
+                .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .to_vec(),
+            StorageFormat::Rgba16Float => value
+                .to_array()
+                .iter()
+                .flat_map(|channel| half::f16::from_f32(*channel).to_le_bytes())
+                .collect(),
+            StorageFormat::Rgba32Float => floats(4),
+            StorageFormat::R32Float => floats(1),
+            StorageFormat::Rg32Float => floats(2),
+            StorageFormat::R32Uint => ((value.x.clamp(0.0, 1.0) * 255.0).round() as u32)
+                .to_le_bytes()
+                .to_vec(),
+        }
+    }
+
+    /// Whether a sprite can show textures of the format as they are.
+    pub fn is_displayable(self) -> bool {
+        matches!(self, StorageFormat::Rgba8Unorm | StorageFormat::Rgba16Float)
+    }
+}
+
+/// Texels of the given format as 8-bit RGBA, converted the way they're displayed: single
+/// channels show as gray, missing channels as 0 and alpha as 1, and integers count up to 255.
+/// `None` for formats that aren't [`StorageFormat`]s.
+pub fn to_rgba8(format: TextureFormat, data: &[u8]) -> Option<Vec<u8>> {
+    if matches!(
+        format,
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+    ) {
+        return Some(data.to_vec());
+    }
+    Some(
+        to_rgba32f(format, data)?
+            .into_iter()
+            .flat_map(|color| StorageFormat::Rgba8Unorm.texel(color))
+            .collect(),
+    )
+}
+
+/// Texels of the given format as float RGBA, converted like [`to_rgba8`] but without clamping
+/// to what 8 bits can hold.
+pub fn to_rgba32f(format: TextureFormat, data: &[u8]) -> Option<Vec<Vec4>> {
+    let texel_size = match format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {
+            return Some(
+                data.chunks_exact(4)
+                    .map(|texel| Vec4::from_array(std::array::from_fn(|i| texel[i] as f32 / 255.0)))
+                    .collect(),
+            );
+        }
+        TextureFormat::Rgba16Float | TextureFormat::Rg32Float => 8,
+        TextureFormat::Rgba32Float => 16,
+        TextureFormat::R32Float | TextureFormat::R32Uint => 4,
+        _ => return None,
+    };
+    Some(
+        data.chunks_exact(texel_size)
+            .map(|texel| display_color(format, texel))
+            .collect(),
+    )
+}
+
+/// The color a texel of one of the formats [`to_rgba8`] converts is displayed as.
+fn display_color(format: TextureFormat, texel: &[u8]) -> Vec4 {
+    let f32s: Vec<f32> = texel
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+    match format {
+        TextureFormat::Rgba16Float => Vec4::from_array(std::array::from_fn(|i| {
+            half::f16::from_le_bytes([texel[2 * i], texel[2 * i + 1]]).to_f32()
+        })),
+        TextureFormat::Rgba32Float => Vec4::from_slice(&f32s),
+        TextureFormat::R32Float => gray(f32s[0]),
+        TextureFormat::Rg32Float => Vec4::new(f32s[0], f32s[1], 0.0, 1.0),
+        _ => gray(u32::from_le_bytes(texel.try_into().unwrap()) as f32 / 255.0),
+    }
+}
+
+fn gray(value: f32) -> Vec4 {
+    Vec4::new(value, value, value, 1.0)
+}
+
+/// Converts the state textures for display. It's the same conversion as [`to_rgba8`], into
+/// `display`.
+const DISPLAY_SHADER: &str = r"
+#ifdef INTEGER
+@group(0) @binding(0) var state: texture_2d<u32>;
+#else
+@group(0) @binding(0) var state: texture_2d<f32>;
+#endif
+@group(0) @binding(1) var display: texture_storage_2d<rgba16float, write>;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(display);
+    if (id.x >= size.x || id.y >= size.y) { return; }
+    let texel = textureLoad(state, id.xy, 0);
+#ifdef INTEGER
+    let color = vec4<f32>(vec3<f32>(f32(texel.r) / 255.0), 1.0);
+#else
+#ifdef SINGLE_CHANNEL
+    let color = vec4<f32>(texel.rrr, 1.0);
+#else
+    let color = texel;
+#endif
+#endif
+    textureStore(display, id.xy, color);
+}
+";
+
+/// Format of the texture the state is converted into.
+pub const DISPLAY_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+const DISPLAY_WORKGROUP_SIZE: u32 = 8;
+
+pub struct DisplayPlugin;
+
+impl Plugin for DisplayPlugin {
+    fn build(&self, app: &mut App) {
+        if app
+            .world()
+            .resource::<PlaygroundConfig>()
+            .format
+            .is_displayable()
+        {
+            return;
+        }
+        let shader = app
+            .world_mut()
+            .resource_mut::<Assets<Shader>>()
+            .add(Shader::from_wgsl(DISPLAY_SHADER, file!()));
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .insert_resource(DisplayShader(shader))
+            .add_systems(
+                Render,
+                prepare_display_bind_group.in_set(RenderSet::PrepareBindGroups),
+            );
+        let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        render_graph.add_node(DisplayLabel, DisplayNode);
+        render_graph.add_node_edge(ComputeShaderLabel, DisplayLabel);
+        render_graph.add_node_edge(DisplayLabel, bevy::render::graph::CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        if render_app.world().contains_resource::<DisplayShader>() {
+            render_app.init_resource::<DisplayPipeline>();
+        }
+    }
+}
+
+#[derive(Resource)]
+struct DisplayShader(Handle<Shader>);
+
+#[derive(Resource)]
+struct DisplayPipeline {
+    layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for DisplayPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let format = world.resource::<PlaygroundConfig>().format;
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "display",
+            &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: format.texture_format().sample_type(None, None).unwrap(),
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: DISPLAY_FORMAT,
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let mut shader_defs = Vec::new();
+        if format.is_integer() {
+            shader_defs.push(ShaderDefVal::Bool("INTEGER".into(), true));
+        }
+        if format == StorageFormat::R32Float {
+            shader_defs.push(ShaderDefVal::Bool("SINGLE_CHANNEL".into(), true));
+        }
+        let shader = world.resource::<DisplayShader>().0.clone();
+        let pipeline =
+            world
+                .resource::<PipelineCache>()
+                .queue_compute_pipeline(ComputePipelineDescriptor {
+                    label: Some("display".into()),
+                    layout: vec![layout.clone()],
+                    push_constant_ranges: Vec::new(),
+                    shader,
+                    shader_defs,
+                    entry_point: "main".into(),
+                    zero_initialize_workgroup_memory: false,
+                });
+        Self { layout, pipeline }
+    }
+}
+
+#[derive(Resource)]
+struct DisplayBindGroup(BindGroup);
+
+fn prepare_display_bind_group(
+    mut commands: Commands,
+    pipeline: Res<DisplayPipeline>,
+    image: Res<ComputeShaderImage>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+) {
+    let (Some(output), Some(display)) = (
+        gpu_images.get(&image.output),
+        image
+            .display
+            .as_ref()
+            .and_then(|display| gpu_images.get(display)),
+    ) else {
+        return;
+    };
+    let bind_group = render_device.create_bind_group(
+        None,
+        &pipeline.layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&output.texture_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(&display.texture_view),
+            },
+        ],
+    );
+    commands.insert_resource(DisplayBindGroup(bind_group));
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct DisplayLabel;
+
+/// Converts the output of the compute shader into the displayed texture.
+struct DisplayNode;
+
+impl render_graph::Node for DisplayNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        if !world.resource::<ComputeShaderReady>().get() {
+            return Ok(());
+        }
+        let Some(bind_group) = world.get_resource::<DisplayBindGroup>() else {
+            return Ok(());
+        };
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let Some(pipeline) =
+            pipeline_cache.get_compute_pipeline(world.resource::<DisplayPipeline>().pipeline)
+        else {
+            return Ok(());
+        };
+
+        let size = world.resource::<PlaygroundConfig>().size;
+        let mut pass = render_context
+            .command_encoder()
+            .begin_compute_pass(&ComputePassDescriptor::default());
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group.0, &[]);
+        pass.dispatch_workgroups(
+            size.x.div_ceil(DISPLAY_WORKGROUP_SIZE),
+            size.y.div_ceil(DISPLAY_WORKGROUP_SIZE),
+            1,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texels_display_as_the_color_they_were_made_from() {
+        for &format in StorageFormat::value_variants() {
+            for (color, rgba, gray) in [
+                (Vec4::new(1.0, 0.0, 0.0, 1.0), [255, 0, 0, 255], 255),
+                (Vec4::new(0.5, 0.25, 0.0, 1.0), [128, 64, 0, 255], 128),
+            ] {
+                let displayed = to_rgba8(format.texture_format(), &format.texel(color)).unwrap();
+                let expected = match format.channels() {
+                    1 => [gray, gray, gray, 255],
+                    2 => [rgba[0], rgba[1], 0, 255],
+                    _ => rgba,
+                };
+                assert_eq!(displayed, expected, "{format:?} {color}");
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! A small GIF89a encoder for recordings, see [`crate::recording`].
+//!
+//! Every frame is mapped to the same fixed palette of 6 red, 7 green and 6 blue levels, so
+//! frames can be written as they come without looking at the whole recording first. Alpha is
+//! dropped.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use bevy::math::UVec2;
+
+const RED_LEVELS: u32 = 6;
+const GREEN_LEVELS: u32 = 7;
+const BLUE_LEVELS: u32 = 6;
+
+/// Bits per palette index, which is also the minimum LZW code size.
+const INDEX_BITS: u32 = 8;
+/// The largest code GIF's LZW allows, after which the table starts over.
+const MAX_CODE: u16 = 4095;
+
+pub struct GifEncoder<W: Write> {
+    writer: W,
+    size: UVec2,
+}
+
+impl<W: Write> GifEncoder<W> {
+    /// Write the header of a looping animation of `size` frames.
+    pub fn new(mut writer: W, size: UVec2) -> io::Result<Self> {
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&(size.x as u16).to_le_bytes())?;
+        writer.write_all(&(size.y as u16).to_le_bytes())?;
+        // A global palette of 2^8 colors, 8 bits per channel.
+        writer.write_all(&[0xf7, 0, 0])?;
+        writer.write_all(&palette())?;
+        // The NETSCAPE2.0 extension, looping forever.
+        writer.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(Self { writer, size })
+    }
+
+    /// Append a frame of tightly packed RGBA8 texels, shown for `delay` hundredths of a second.
+    pub fn write_frame(&mut self, rgba: &[u8], delay: u16) -> io::Result<()> {
+        let writer = &mut self.writer;
+        // Graphic control extension: no disposal, no transparency.
+        writer.write_all(&[0x21, 0xf9, 0x04, 0x00])?;
+        writer.write_all(&delay.to_le_bytes())?;
+        writer.write_all(&[0x00, 0x00])?;
+        // Image descriptor covering the whole canvas, with the global palette.
+        writer.write_all(&[0x2c, 0, 0, 0, 0])?;
+        writer.write_all(&(self.size.x as u16).to_le_bytes())?;
+        writer.write_all(&(self.size.y as u16).to_le_bytes())?;
+        writer.write_all(&[0x00])?;
+
+        let indices: Vec<u8> = rgba
+            .chunks_exact(4)
+            .map(|texel| palette_index(texel[0], texel[1], texel[2]))
+            .collect();
+        writer.write_all(&[INDEX_BITS as u8])?;
+        for block in lzw(&indices).chunks(255) {
+            writer.write_all(&[block.len() as u8])?;
+            writer.write_all(block)?;
+        }
+        writer.write_all(&[0x00])
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0x3b])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn level(value: u8, levels: u32) -> u32 {
+    (value as u32 * (levels - 1) + 127) / 255
+}
+
+fn palette_index(r: u8, g: u8, b: u8) -> u8 {
+    let (r, g, b) = (
+        level(r, RED_LEVELS),
+        level(g, GREEN_LEVELS),
+        level(b, BLUE_LEVELS),
+    );
+    ((r * GREEN_LEVELS + g) * BLUE_LEVELS + b) as u8
+}
+
+/// The 252 colors of the levels, padded to 256 with black.
+fn palette() -> [u8; 768] {
+    let mut palette = [0; 768];
+    let scale = |level: u32, levels: u32| (level * 255 / (levels - 1)) as u8;
+    for r in 0..RED_LEVELS {
+        for g in 0..GREEN_LEVELS {
+            for b in 0..BLUE_LEVELS {
+                let index = palette_index(
+                    scale(r, RED_LEVELS),
+                    scale(g, GREEN_LEVELS),
+                    scale(b, BLUE_LEVELS),
+                ) as usize;
+                palette[3 * index..3 * index + 3].copy_from_slice(&[
+                    scale(r, RED_LEVELS),
+                    scale(g, GREEN_LEVELS),
+                    scale(b, BLUE_LEVELS),
+                ]);
+            }
+        }
+    }
+    palette
+}
+
+/// Codes written least significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u32) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Compress palette indices with GIF's variable-length LZW.
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let clear = 1 << INDEX_BITS;
+    let end = clear + 1;
+    let mut out = BitWriter::default();
+    let mut table = HashMap::new();
+    let mut size = INDEX_BITS + 1;
+    let mut last = end;
+
+    out.write(clear, size);
+    let Some((&first, rest)) = indices.split_first() else {
+        out.write(end, size);
+        return out.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        out.write(prefix, size);
+        last += 1;
+        table.insert((prefix, index), last);
+        if last >= 1 << size {
+            size += 1;
+        }
+        if last == MAX_CODE {
+            out.write(clear, size);
+            table.clear();
+            size = INDEX_BITS + 1;
+            last = end;
+        }
+        prefix = index as u16;
+    }
+    out.write(prefix, size);
+    out.write(end, size);
+    out.finish()
+}
@@ -0,0 +1,191 @@
+use bevy::app::{App, Plugin, Update};
+use bevy::prelude::{IntoScheduleConfigs, Res, ResMut, Resource, World};
+use bevy::render::extract_resource::ExtractResourcePlugin;
+use bevy::render::render_graph::{self, NodeRunError, RenderGraphContext, RenderLabel};
+use bevy::render::render_resource::{
+    BufferUsages, CommandEncoder, DynamicUniformBuffer, ShaderType, UniformBuffer,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::{Render, RenderApp, RenderSet};
+
+use crate::config::PlaygroundConfig;
+use crate::input::{ShaderMouse, update_mouse};
+use crate::simulation::{FrameSteps, SimulationControl, advance_simulation};
+
+pub use uniform::ComputeShaderGlobals;
+
+// The `ShaderType` derive emits const check functions that are never called. They're items of
+// the module rather than of the struct, so this module holds just the struct to keep the allow
+// from hiding anything else.
+#[allow(dead_code)]
+mod uniform {
+    use bevy::math::{UVec2, Vec2};
+    use bevy::prelude::Resource;
+    use bevy::render::extract_resource::ExtractResource;
+    use bevy::render::render_resource::ShaderType;
+
+    /// Per-step values bound to the compute shader variable named `globals`.
+    ///
+    /// The field order and types must match the `Globals` struct in `shader.wgsl`.
+    #[derive(Resource, Clone, Default, ExtractResource, ShaderType)]
+    pub struct ComputeShaderGlobals {
+        /// Seconds since `init` ran.
+        pub time: f32,
+        /// Seconds the simulation advances by each step.
+        pub delta_time: f32,
+        /// Number of frames that ran `update` so far, counting this one; 0 on the `init` frame.
+        /// Every step of a frame sees the same `frame`.
+        pub frame: u32,
+        /// Size of the state textures in texels.
+        pub resolution: UVec2,
+        /// Last known cursor position in texel space, with the origin in the top-left corner.
+        pub mouse: Vec2,
+        /// Cursor position of the most recent left button press in texel space.
+        pub mouse_click: Vec2,
+        /// Held mouse buttons as a bit mask: left = 1, right = 2, middle = 4.
+        pub mouse_buttons: u32,
+        /// Cursor position the last time the left button was held, in texel space.
+        pub mouse_drag: Vec2,
+        /// Mouse buttons pressed this frame, as a bit mask like `mouse_buttons`.
+        pub mouse_pressed: u32,
+        /// Number of `update` steps run so far, counting this one; 0 on the `init` frame. With a
+        /// fixed timestep, `time` is always `tick` steps long, so a run can be reproduced from it.
+        pub tick: u32,
+    }
+}
+
+/// The `globals` uniform, and the values of every step of the frame. Each step copies its
+/// values into the uniform before it's dispatched.
+#[derive(Resource)]
+pub struct ComputeShaderGlobalsBuffer {
+    pub uniform: UniformBuffer<ComputeShaderGlobals>,
+    steps: DynamicUniformBuffer<ComputeShaderGlobals>,
+    offsets: Vec<u32>,
+}
+
+impl Default for ComputeShaderGlobalsBuffer {
+    fn default() -> Self {
+        let mut steps = DynamicUniformBuffer::default();
+        steps.add_usages(BufferUsages::COPY_SRC);
+        Self {
+            uniform: UniformBuffer::default(),
+            steps,
+            offsets: Vec::new(),
+        }
+    }
+}
+
+impl ComputeShaderGlobalsBuffer {
+    /// Copy the values of `step` into the uniform.
+    pub fn record_step(&self, encoder: &mut CommandEncoder, step: u32) {
+        let (Some(steps), Some(uniform), Some(&offset)) = (
+            self.steps.buffer(),
+            self.uniform.buffer(),
+            self.offsets.get(step as usize),
+        ) else {
+            return;
+        };
+        encoder.copy_buffer_to_buffer(
+            steps,
+            offset as u64,
+            uniform,
+            0,
+            ComputeShaderGlobals::min_size().get(),
+        );
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct GlobalsStepLabel;
+
+/// Copies the globals of the step being recorded into the uniform, before anything of the step
+/// runs.
+pub struct GlobalsStepNode;
+
+impl render_graph::Node for GlobalsStepNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let step = world.resource::<FrameSteps>().current();
+        world
+            .resource::<ComputeShaderGlobalsBuffer>()
+            .record_step(render_context.command_encoder(), step);
+        Ok(())
+    }
+}
+
+pub struct GlobalsPlugin;
+
+impl Plugin for GlobalsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ComputeShaderGlobals>()
+            .add_plugins(ExtractResourcePlugin::<ComputeShaderGlobals>::default())
+            .add_systems(
+                Update,
+                update_globals.after(update_mouse).after(advance_simulation),
+            );
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<ComputeShaderGlobalsBuffer>()
+            .add_systems(
+                Render,
+                prepare_globals_buffer.in_set(RenderSet::PrepareResources),
+            );
+    }
+}
+
+/// Set the globals of this frame's first step.
+pub fn update_globals(
+    control: Res<SimulationControl>,
+    mouse: Res<ShaderMouse>,
+    config: Res<PlaygroundConfig>,
+    mut globals: ResMut<ComputeShaderGlobals>,
+) {
+    let globals = &mut *globals;
+    (
+        globals.frame,
+        globals.tick,
+        globals.time,
+        globals.delta_time,
+    ) = control.clock(0);
+    globals.resolution = config.size;
+
+    if let Some(position) = mouse.position {
+        globals.mouse = position;
+    }
+    globals.mouse_click = mouse.click;
+    globals.mouse_buttons = mouse.buttons;
+    globals.mouse_drag = mouse.drag;
+    globals.mouse_pressed = mouse.pressed;
+}
+
+/// Upload the globals of every step this frame runs; later steps count on from the first.
+fn prepare_globals_buffer(
+    globals: Res<ComputeShaderGlobals>,
+    control: Res<SimulationControl>,
+    mut buffer: ResMut<ComputeShaderGlobalsBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let buffer = &mut *buffer;
+    buffer.uniform.set(globals.clone());
+    buffer.uniform.write_buffer(&render_device, &render_queue);
+
+    buffer.steps.clear();
+    buffer.offsets.clear();
+    for step in 0..control.steps().max(1) {
+        let mut globals = globals.clone();
+        (
+            globals.frame,
+            globals.tick,
+            globals.time,
+            globals.delta_time,
+        ) = control.clock(step);
+        buffer.offsets.push(buffer.steps.push(&globals));
+    }
+    buffer.steps.write_buffer(&render_device, &render_queue);
+}
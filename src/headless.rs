@@ -0,0 +1,265 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bevy::DefaultPlugins;
+use bevy::app::{
+    App, AppExit, Plugin, PluginGroup, PluginGroupBuilder, PostUpdate, ScheduleRunnerPlugin, Update,
+};
+use bevy::audio::AudioPlugin;
+use bevy::log::{error, info};
+use bevy::prelude::{
+    EventReader, EventWriter, IntoScheduleConfigs, Res, ResMut, Resource, WindowPlugin, default,
+};
+use bevy::render::RenderPlugin;
+use bevy::render::pipelined_rendering::PipelinedRenderingPlugin;
+use bevy::render::renderer::{RenderInstance, WgpuWrapper, initialize_renderer};
+use bevy::render::settings::{Backends, RenderCreation, WgpuSettings};
+use bevy::tasks::block_on;
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+use clap::Args;
+
+use crate::capture::{CaptureFrame, FrameSaved, PendingCaptures};
+use crate::config::{ConfigError, PlaygroundConfig};
+use crate::export::ExportFormat;
+use crate::globals::{ComputeShaderGlobals, update_globals};
+use crate::shader_error::ShaderErrors;
+use crate::{ComputeShaderReady, swap_textures};
+
+/// How many frames to simulate and which of them to save.
+#[derive(Args, Debug)]
+pub struct FrameArgs {
+    /// Number of frames that run `update` to render.
+    #[arg(long, default_value_t = 60)]
+    pub frames: u32,
+    /// Simulated frames per second. Sets `globals.delta_time`, or with a `tick_rate` how many
+    /// steps come due each frame.
+    #[arg(long, default_value_t = 60.0)]
+    pub fps: f64,
+    /// Comma-separated frames to save, counting the first `update` as frame 1. Defaults to the
+    /// last frame.
+    #[arg(long, value_delimiter = ',')]
+    pub dump: Vec<u32>,
+    /// Directory the saved frames are written to.
+    #[arg(long)]
+    pub output_dir: Option<PathBuf>,
+}
+
+impl FrameArgs {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.frames == 0 {
+            return Err(ConfigError::Invalid("--frames must be at least 1"));
+        }
+        if !self.fps.is_finite() || self.fps <= 0.0 {
+            return Err(ConfigError::Invalid("--fps must be positive"));
+        }
+        if self
+            .dump
+            .iter()
+            .any(|&frame| frame == 0 || frame > self.frames)
+        {
+            return Err(ConfigError::Invalid(
+                "--dump frames must be between 1 and --frames",
+            ));
+        }
+        Ok(())
+    }
+
+    /// How long each simulated frame lasts.
+    pub fn timestep(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.fps)
+    }
+
+    pub fn dump_frames(&self) -> BTreeSet<u32> {
+        if self.dump.is_empty() {
+            BTreeSet::from([self.frames])
+        } else {
+            self.dump.iter().copied().collect()
+        }
+    }
+}
+
+/// Name of a saved frame, shared by the GPU and CPU renders so their output can be compared.
+pub fn frame_file_name(frame: u32, format: ExportFormat) -> String {
+    format!("frame-{frame:06}.{}", format.extension())
+}
+
+#[derive(Args, Debug)]
+pub struct HeadlessArgs {
+    #[command(flatten)]
+    pub frames: FrameArgs,
+    /// Seconds to wait for the shader to load and compile before giving up.
+    #[arg(long, default_value_t = 30.0)]
+    pub timeout: f64,
+    /// Render on a software Vulkan adapter such as lavapipe instead of a GPU.
+    #[arg(long)]
+    pub software: bool,
+}
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    /// `--software` was given, but there's no fallback adapter to render on.
+    NoSoftwareAdapter,
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadlessError::NoSoftwareAdapter => write!(
+                f,
+                "there's no software Vulkan adapter; install lavapipe or render without --software"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeadlessError {}
+
+/// The default plugins without a window or event loop. Without winit nothing would run the
+/// app more than once, so frames are run back to back by the schedule runner. Rendering isn't
+/// pipelined, so every frame the main world sends is computed before the next one starts.
+pub fn plugins(args: &HeadlessArgs) -> Result<PluginGroupBuilder, HeadlessError> {
+    let render_creation = if args.software {
+        software_renderer()?
+    } else {
+        WgpuSettings::default().into()
+    };
+    Ok(DefaultPlugins
+        .set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .set(RenderPlugin {
+            render_creation,
+            synchronous_pipeline_compilation: true,
+            ..default()
+        })
+        .disable::<WinitPlugin>()
+        .disable::<PipelinedRenderingPlugin>()
+        .disable::<AudioPlugin>()
+        .add(ScheduleRunnerPlugin::run_loop(Duration::ZERO)))
+}
+
+/// Create the renderer on the Vulkan fallback adapter, which is the CPU implementation when one
+/// is installed.
+fn software_renderer() -> Result<RenderCreation, HeadlessError> {
+    let settings = WgpuSettings {
+        backends: Some(Backends::VULKAN),
+        ..default()
+    };
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: Backends::VULKAN,
+        flags: settings.instance_flags,
+        ..default()
+    });
+    let options = wgpu::RequestAdapterOptions {
+        force_fallback_adapter: true,
+        ..default()
+    };
+    // `initialize_renderer` panics when it finds no adapter.
+    if block_on(instance.request_adapter(&options)).is_none() {
+        return Err(HeadlessError::NoSoftwareAdapter);
+    }
+    let (device, queue, adapter_info, adapter) =
+        block_on(initialize_renderer(&instance, &settings, &options));
+    Ok(RenderCreation::manual(
+        device,
+        queue,
+        adapter_info,
+        adapter,
+        RenderInstance(Arc::new(WgpuWrapper::new(instance))),
+    ))
+}
+
+/// Steps the simulation at a fixed timestep, saves the requested frames and exits.
+pub struct HeadlessPlugin(pub HeadlessArgs);
+
+#[derive(Resource)]
+struct HeadlessRender {
+    frames: u32,
+    dump: BTreeSet<u32>,
+    output_dir: PathBuf,
+    timeout: Duration,
+    started: Instant,
+    failed: bool,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let args = &self.0;
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(args.frames.timestep()))
+            .insert_resource(HeadlessRender {
+                frames: args.frames.frames,
+                dump: args.frames.dump_frames(),
+                output_dir: args
+                    .frames
+                    .output_dir
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from("render")),
+                timeout: Duration::from_secs_f64(args.timeout),
+                started: Instant::now(),
+                failed: false,
+            })
+            .add_systems(
+                Update,
+                step_render.after(update_globals).before(swap_textures),
+            )
+            .add_systems(PostUpdate, finish_render);
+    }
+}
+
+fn step_render(
+    mut render: ResMut<HeadlessRender>,
+    ready: Res<ComputeShaderReady>,
+    errors: Res<ShaderErrors>,
+    config: Res<PlaygroundConfig>,
+    globals: Res<ComputeShaderGlobals>,
+    mut captures: EventWriter<CaptureFrame>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if let Some(diagnostic) = errors.get() {
+        error!("{diagnostic}");
+        exit.write(AppExit::error());
+        return;
+    }
+    if !ready.get() {
+        if render.started.elapsed() > render.timeout {
+            error!("The compute shader wasn't ready after {:?}", render.timeout);
+            exit.write(AppExit::error());
+        }
+        return;
+    }
+
+    if render.dump.remove(&globals.frame) {
+        let path = render
+            .output_dir
+            .join(frame_file_name(globals.frame, config.capture_format));
+        captures.write(CaptureFrame { path: Some(path) });
+    }
+}
+
+/// Exit once the last frame has been rendered and every requested frame is on disk.
+fn finish_render(
+    mut render: ResMut<HeadlessRender>,
+    globals: Res<ComputeShaderGlobals>,
+    pending: Res<PendingCaptures>,
+    mut saved: EventReader<FrameSaved>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for frame in saved.read() {
+        render.failed |= frame.result.is_err();
+    }
+    if globals.frame >= render.frames && render.dump.is_empty() && pending.is_empty() {
+        if render.failed {
+            exit.write(AppExit::error());
+        } else {
+            info!("Rendered {} frames", render.frames);
+            exit.write(AppExit::Success);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::asset::{Assets, Handle, RenderAssetUsages};
+use bevy::image::Image;
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::input::mouse::MouseButton;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    Camera, Camera2d, Commands, GlobalTransform, Interaction, Local, Query, Res, ResMut, Resource,
+    Single, Window, With,
+};
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::window::PrimaryWindow;
+
+use crate::ComputeShaderSprite;
+use crate::config::PlaygroundConfig;
+
+/// Number of key codes in the keyboard texture, one column per JavaScript key code.
+pub const KEY_COUNT: usize = 256;
+
+/// Mouse state in the texel space of the compute textures, with the origin in the top-left
+/// corner.
+#[derive(Resource, Default)]
+pub struct ShaderMouse {
+    /// Cursor position, `None` while the cursor is outside the window.
+    pub position: Option<Vec2>,
+    /// Position of the most recent left button press.
+    pub click: Vec2,
+    /// Cursor position the last time the left button was held.
+    pub drag: Vec2,
+    /// Held buttons as a bit mask: left = 1, right = 2, middle = 4.
+    pub buttons: u32,
+    /// Buttons pressed this frame, as a bit mask like `buttons`.
+    pub pressed: u32,
+}
+
+/// Shadertoy-style keyboard state, bound to the compute shader variable named `keyboard`.
+///
+/// The texture is 256x3 `R8Unorm`, indexed by JavaScript key code. Row 0 holds the keys that
+/// are down, row 1 the keys pressed this frame and row 2 flips on every press.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct KeyboardTexture {
+    pub texture: Handle<Image>,
+}
+
+pub struct ShaderInputPlugin;
+
+impl Plugin for ShaderInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShaderMouse>()
+            .add_plugins(ExtractResourcePlugin::<KeyboardTexture>::default())
+            .add_systems(Startup, setup_keyboard_texture)
+            .add_systems(Update, (update_mouse, update_keyboard_texture));
+    }
+}
+
+fn setup_keyboard_texture(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let image = Image::new(
+        Extent3d {
+            width: KEY_COUNT as u32,
+            height: 3,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        vec![0; KEY_COUNT * 3],
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::default(),
+    );
+
+    commands.insert_resource(KeyboardTexture {
+        texture: images.add(image),
+    });
+}
+
+/// Map the cursor through the camera and the sprite transform into texel coordinates.
+pub fn update_mouse(
+    mut mouse: ResMut<ShaderMouse>,
+    config: Res<PlaygroundConfig>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<Camera2d>>,
+    sprite: Single<&GlobalTransform, With<ComputeShaderSprite>>,
+    interactions: Query<&Interaction>,
+) {
+    let (camera, camera_transform) = *camera;
+    mouse.position = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor).ok())
+        .map(|world_position| {
+            let local = sprite
+                .affine()
+                .inverse()
+                .transform_point3(world_position.extend(0.0));
+            // The sprite is centered on its transform and the world's y axis points up, while
+            // texel rows are counted from the top.
+            let half_size = config.size.as_vec2() / 2.0;
+            Vec2::new(local.x + half_size.x, half_size.y - local.y)
+        });
+
+    // Clicks on the UI, like dragging a parameter slider, are not for the shader.
+    if interactions.iter().any(|i| *i != Interaction::None) {
+        mouse.buttons = 0;
+        mouse.pressed = 0;
+        return;
+    }
+    if let Some(position) = mouse.position {
+        if buttons.just_pressed(MouseButton::Left) {
+            mouse.click = position;
+        }
+        if buttons.pressed(MouseButton::Left) {
+            mouse.drag = position;
+        }
+    }
+
+    let mask = |held: fn(&ButtonInput<MouseButton>, MouseButton) -> bool| {
+        [MouseButton::Left, MouseButton::Right, MouseButton::Middle]
+            .into_iter()
+            .enumerate()
+            .filter(|(_, button)| held(&buttons, *button))
+            .fold(0, |mask, (bit, _)| mask | 1 << bit)
+    };
+    mouse.buttons = mask(ButtonInput::pressed);
+    mouse.pressed = mask(ButtonInput::just_pressed);
+}
+
+fn update_keyboard_texture(
+    keys: Res<ButtonInput<KeyCode>>,
+    keyboard: Res<KeyboardTexture>,
+    mut images: ResMut<Assets<Image>>,
+    mut toggled: Local<Vec<bool>>,
+) {
+    toggled.resize(KEY_COUNT, false);
+
+    let mut data = vec![0; KEY_COUNT * 3];
+    for key in keys.get_pressed() {
+        if let Some(code) = js_key_code(*key) {
+            data[code] = 255;
+        }
+    }
+    for key in keys.get_just_pressed() {
+        if let Some(code) = js_key_code(*key) {
+            data[KEY_COUNT + code] = 255;
+            toggled[code] = !toggled[code];
+        }
+    }
+    for (code, _) in toggled.iter().enumerate().filter(|(_, toggled)| **toggled) {
+        data[2 * KEY_COUNT + code] = 255;
+    }
+
+    // Only touch the asset when something changed, since that re-uploads the texture.
+    let unchanged = images
+        .get(&keyboard.texture)
+        .is_some_and(|image| image.data.as_deref() == Some(&data[..]));
+    if !unchanged && let Some(image) = images.get_mut(&keyboard.texture) {
+        image.data = Some(data);
+    }
+}
+
+/// The JavaScript `keyCode` Shadertoy shaders use to index the keyboard texture.
+fn js_key_code(key: KeyCode) -> Option<usize> {
+    let code = match key {
+        KeyCode::Backspace => 8,
+        KeyCode::Tab => 9,
+        KeyCode::Enter | KeyCode::NumpadEnter => 13,
+        KeyCode::ShiftLeft | KeyCode::ShiftRight => 16,
+        KeyCode::ControlLeft | KeyCode::ControlRight => 17,
+        KeyCode::AltLeft | KeyCode::AltRight => 18,
+        KeyCode::Pause => 19,
+        KeyCode::CapsLock => 20,
+        KeyCode::Escape => 27,
+        KeyCode::Space => 32,
+        KeyCode::PageUp => 33,
+        KeyCode::PageDown => 34,
+        KeyCode::End => 35,
+        KeyCode::Home => 36,
+        KeyCode::ArrowLeft => 37,
+        KeyCode::ArrowUp => 38,
+        KeyCode::ArrowRight => 39,
+        KeyCode::ArrowDown => 40,
+        KeyCode::Insert => 45,
+        KeyCode::Delete => 46,
+        KeyCode::Digit0 => 48,
+        KeyCode::Digit1 => 49,
+        KeyCode::Digit2 => 50,
+        KeyCode::Digit3 => 51,
+        KeyCode::Digit4 => 52,
+        KeyCode::Digit5 => 53,
+        KeyCode::Digit6 => 54,
+        KeyCode::Digit7 => 55,
+        KeyCode::Digit8 => 56,
+        KeyCode::Digit9 => 57,
+        KeyCode::KeyA => 65,
+        KeyCode::KeyB => 66,
+        KeyCode::KeyC => 67,
+        KeyCode::KeyD => 68,
+        KeyCode::KeyE => 69,
+        KeyCode::KeyF => 70,
+        KeyCode::KeyG => 71,
+        KeyCode::KeyH => 72,
+        KeyCode::KeyI => 73,
+        KeyCode::KeyJ => 74,
+        KeyCode::KeyK => 75,
+        KeyCode::KeyL => 76,
+        KeyCode::KeyM => 77,
+        KeyCode::KeyN => 78,
+        KeyCode::KeyO => 79,
+        KeyCode::KeyP => 80,
+        KeyCode::KeyQ => 81,
+        KeyCode::KeyR => 82,
+        KeyCode::KeyS => 83,
+        KeyCode::KeyT => 84,
+        KeyCode::KeyU => 85,
+        KeyCode::KeyV => 86,
+        KeyCode::KeyW => 87,
+        KeyCode::KeyX => 88,
+        KeyCode::KeyY => 89,
+        KeyCode::KeyZ => 90,
+        KeyCode::Numpad0 => 96,
+        KeyCode::Numpad1 => 97,
+        KeyCode::Numpad2 => 98,
+        KeyCode::Numpad3 => 99,
+        KeyCode::Numpad4 => 100,
+        KeyCode::Numpad5 => 101,
+        KeyCode::Numpad6 => 102,
+        KeyCode::Numpad7 => 103,
+        KeyCode::Numpad8 => 104,
+        KeyCode::Numpad9 => 105,
+        KeyCode::NumpadMultiply => 106,
+        KeyCode::NumpadAdd => 107,
+        KeyCode::NumpadSubtract => 109,
+        KeyCode::NumpadDecimal => 110,
+        KeyCode::NumpadDivide => 111,
+        KeyCode::F1 => 112,
+        KeyCode::F2 => 113,
+        KeyCode::F3 => 114,
+        KeyCode::F4 => 115,
+        KeyCode::F5 => 116,
+        KeyCode::F6 => 117,
+        KeyCode::F7 => 118,
+        KeyCode::F8 => 119,
+        KeyCode::F9 => 120,
+        KeyCode::F10 => 121,
+        KeyCode::F11 => 122,
+        KeyCode::F12 => 123,
+        KeyCode::Semicolon => 186,
+        KeyCode::Equal => 187,
+        KeyCode::Comma => 188,
+        KeyCode::Minus => 189,
+        KeyCode::Period => 190,
+        KeyCode::Slash => 191,
+        KeyCode::Backquote => 192,
+        KeyCode::BracketLeft => 219,
+        KeyCode::Backslash => 220,
+        KeyCode::BracketRight => 221,
+        KeyCode::Quote => 222,
+        _ => return None,
+    };
+    Some(code)
+}
@@ -0,0 +1,230 @@
+//! An overlay with the exact values of the texels around the cursor, read back from `output`.
+//!
+//! Toggled with F3. One region is read back at a time, so the values trail the display by the
+//! few frames a readback takes.
+
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::color::Color;
+use bevy::image::TextureFormatPixelInfo;
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::log::info;
+use bevy::math::{URect, UVec2, Vec2};
+use bevy::prelude::{
+    BackgroundColor, Commands, Component, DetectChangesMut, EventReader, IntoScheduleConfigs, Node,
+    PositionType, Query, Res, ResMut, Resource, Text, TextColor, TextFont, UiRect, Val, Visibility,
+    With, default,
+};
+
+use crate::config::PlaygroundConfig;
+use crate::format::StorageFormat;
+use crate::input::{ShaderMouse, update_mouse};
+use crate::readback::{
+    ReadbackId, ReadbackRequest, TextureReadback, TextureReadbackComplete, TextureReadbacks,
+};
+use crate::{ComputeShaderImage, swap_textures};
+
+/// Key that shows or hides the inspector.
+const INSPECT_KEY: KeyCode = KeyCode::F3;
+
+/// Texels read on each side of the one under the cursor.
+const INSPECT_RADIUS: u32 = 1;
+
+#[derive(Resource, Default)]
+struct Inspector {
+    enabled: bool,
+    /// The readback in flight, and the texel under the cursor when it was requested.
+    pending: Option<(ReadbackId, UVec2)>,
+    /// Text of the last region read.
+    text: String,
+}
+
+#[derive(Component)]
+struct InspectorOverlay;
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inspector>()
+            .add_systems(Startup, setup_overlay)
+            .add_systems(
+                Update,
+                (
+                    inspector_hotkey,
+                    request_inspection.after(update_mouse).after(swap_textures),
+                    read_inspection,
+                    update_overlay,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn setup_overlay(mut commands: Commands) {
+    commands.spawn((
+        InspectorOverlay,
+        Text::default(),
+        TextFont {
+            font_size: 12.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.0),
+            left: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+}
+
+fn inspector_hotkey(keys: Res<ButtonInput<KeyCode>>, mut inspector: ResMut<Inspector>) {
+    if keys.just_pressed(INSPECT_KEY) {
+        inspector.enabled = !inspector.enabled;
+        info!("Inspector {}", if inspector.enabled { "on" } else { "off" });
+    }
+}
+
+/// Read back the texels around the cursor once the previous readback has arrived.
+fn request_inspection(
+    mut inspector: ResMut<Inspector>,
+    mouse: Res<ShaderMouse>,
+    config: Res<PlaygroundConfig>,
+    image: Res<ComputeShaderImage>,
+    mut readbacks: ResMut<TextureReadbacks>,
+) {
+    if !inspector.enabled || inspector.pending.is_some() {
+        return;
+    }
+    // The mouse position is already mapped through the sprite transform, which scales the
+    // texels by `display_factor`.
+    let Some(texel) = mouse
+        .position
+        .filter(|position| position.cmpge(Vec2::ZERO).all())
+        .map(|position| position.floor().as_uvec2())
+        .filter(|texel| texel.cmplt(config.size).all())
+    else {
+        inspector.text = "Cursor outside the texture".to_string();
+        return;
+    };
+    let region = URect::from_corners(
+        texel.saturating_sub(UVec2::splat(INSPECT_RADIUS)),
+        texel + UVec2::splat(INSPECT_RADIUS + 1),
+    );
+    let id = readbacks.request(ReadbackRequest {
+        image: image.output.clone(),
+        region: Some(region),
+    });
+    inspector.pending = Some((id, texel));
+}
+
+fn read_inspection(
+    mut inspector: ResMut<Inspector>,
+    mut readbacks: EventReader<TextureReadbackComplete>,
+    config: Res<PlaygroundConfig>,
+) {
+    for complete in readbacks.read() {
+        if let Some((id, texel)) = inspector.pending
+            && complete.id == id
+        {
+            inspector.text = describe_region(&complete.readback, texel, config.format);
+            inspector.pending = None;
+        }
+    }
+}
+
+fn update_overlay(
+    inspector: Res<Inspector>,
+    mut overlay: Query<(&mut Text, &mut Visibility), With<InspectorOverlay>>,
+) {
+    for (mut text, mut visibility) in &mut overlay {
+        if text.0 != inspector.text {
+            text.0.clone_from(&inspector.text);
+        }
+        visibility.set_if_neq(if inspector.enabled {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+/// A table of the texels of a region around `texel`, one row of the texture per line, with
+/// `texel` in brackets.
+fn describe_region(readback: &TextureReadback, texel: UVec2, format: StorageFormat) -> String {
+    // The region starts at the texel unless that's closer than the radius to the edge.
+    let origin = texel.saturating_sub(UVec2::splat(INSPECT_RADIUS));
+    let texel_size = readback.format.pixel_size();
+    let cells: Vec<Vec<String>> = readback
+        .data
+        .chunks_exact(texel_size * readback.size.x as usize)
+        .enumerate()
+        .map(|(y, row)| {
+            row.chunks_exact(texel_size)
+                .enumerate()
+                .map(|(x, bytes)| {
+                    let values = channel_values(format, bytes).join(" ");
+                    if origin + UVec2::new(x as u32, y as u32) == texel {
+                        format!("[{values}]")
+                    } else {
+                        format!(" {values} ")
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let width = cells.iter().flatten().map(String::len).max().unwrap_or(0);
+    let label_width = format!("y={}", origin.y + readback.size.y - 1).len();
+
+    let mut text = format!(
+        "Texel ({}, {}) of {}, channels {}\n{:label_width$}",
+        texel.x,
+        texel.y,
+        format.wgsl_name(),
+        channel_names(format),
+        ""
+    );
+    for x in 0..readback.size.x {
+        text += &format!(" {:^width$}", format!("x={}", origin.x + x));
+    }
+    for (y, row) in cells.iter().enumerate() {
+        text += &format!("\n{:<label_width$}", format!("y={}", origin.y + y as u32));
+        for cell in row {
+            text += &format!(" {cell:<width$}");
+        }
+    }
+    text
+}
+
+fn channel_names(format: StorageFormat) -> &'static str {
+    match format {
+        StorageFormat::Rgba8Unorm | StorageFormat::Rgba16Float | StorageFormat::Rgba32Float => {
+            "r g b a"
+        }
+        StorageFormat::Rg32Float => "r g",
+        StorageFormat::R32Float | StorageFormat::R32Uint => "r",
+    }
+}
+
+/// The channels of a texel as they're stored: unorm and integer channels as the raw integers,
+/// float channels in full precision.
+fn channel_values(format: StorageFormat, texel: &[u8]) -> Vec<String> {
+    let words = || texel.chunks_exact(4).map(|bytes| bytes.try_into().unwrap());
+    match format {
+        StorageFormat::Rgba8Unorm => texel.iter().map(u8::to_string).collect(),
+        StorageFormat::Rgba16Float => texel
+            .chunks_exact(2)
+            .map(|bytes| half::f16::from_le_bytes([bytes[0], bytes[1]]).to_string())
+            .collect(),
+        StorageFormat::Rgba32Float | StorageFormat::R32Float | StorageFormat::Rg32Float => words()
+            .map(|bytes| f32::from_le_bytes(bytes).to_string())
+            .collect(),
+        StorageFormat::R32Uint => words()
+            .map(|bytes| u32::from_le_bytes(bytes).to_string())
+            .collect(),
+    }
+}
@@ -1,184 +1,677 @@
 use bevy::DefaultPlugins;
-use bevy::app::{App, Plugin, Startup};
-use bevy::asset::{Assets, DirectAssetAccessExt, Handle, RenderAssetUsages};
+use bevy::app::{App, AppExit, Plugin, PluginGroup, Startup};
+use bevy::asset::{AssetId, Assets, Handle, RenderAssetUsages};
 use bevy::image::Image;
-use bevy::math::{Vec2, Vec3};
+use bevy::log::info;
+use bevy::math::{Vec3, Vec4};
 use bevy::prelude::{
-    Camera2d, Commands, FromWorld, IntoScheduleConfigs, Res, ResMut, Resource, Sprite, Transform,
-    World, default,
+    Camera2d, Commands, Component, IntoScheduleConfigs, Res, ResMut, Resource, Single, Sprite,
+    Transform, Update, Window, WindowPlugin, With, World, default,
 };
 use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
 use bevy::render::render_asset::RenderAssets;
 use bevy::render::render_graph::{NodeRunError, RenderGraph, RenderGraphContext, RenderLabel};
-use bevy::render::render_resource::binding_types::texture_storage_2d;
 use bevy::render::render_resource::{
-    BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, CachedComputePipelineId,
-    ComputePassDescriptor, ComputePipelineDescriptor, Extent3d, PipelineCache, ShaderStages,
-    StorageTextureAccess, TextureDimension, TextureFormat, TextureUsages,
+    BindGroupLayout, CachedComputePipelineId, CachedPipelineState, ComputePipeline,
+    ComputePipelineDescriptor, Extent3d, Pipeline, PipelineCache, Shader, TextureDimension,
+    TextureUsages,
 };
 use bevy::render::renderer::{RenderContext, RenderDevice};
 use bevy::render::texture::GpuImage;
 use bevy::render::{Render, RenderApp, RenderSet, render_graph};
+use clap::{Parser, Subcommand};
 use std::borrow::Cow;
-use bevy::log::info;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod bindings;
+mod capture;
+mod config;
+mod cpu;
+mod dispatch;
+mod export;
+mod format;
+mod gif;
+mod globals;
+mod headless;
+mod input;
+mod inspector;
+mod params;
+mod passes;
+mod presets;
+mod profiling;
+mod readback;
+mod recording;
+mod shader_error;
+mod shadertoy;
+mod simulation;
+mod storage;
+mod textures;
+mod timeline;
+mod validate;
+
+use bindings::{
+    AllocatedBindings, BoundResources, ComputeShaderLayout, ShaderBinding, ShaderBindingsPlugin,
+    StepBindGroup, has_dynamic_offset,
+};
+use capture::CapturePlugin;
+use config::{ConfigArgs, PlaygroundConfig};
+use cpu::CpuArgs;
+use dispatch::{ComputeShaderDispatch, DispatchPlugin};
+use format::{DISPLAY_FORMAT, DisplayPlugin};
+use globals::{ComputeShaderGlobalsBuffer, GlobalsPlugin, GlobalsStepLabel, GlobalsStepNode};
+use headless::{HeadlessArgs, HeadlessPlugin};
+use input::{KeyboardTexture, ShaderInputPlugin};
+use inspector::InspectorPlugin;
+use params::{ParamsBuffer, ParamsPlugin};
+use passes::{PassImages, PassPipelines, PassesPlugin};
+use presets::PresetsPlugin;
+use profiling::{PassProfiler, ProfilingPlugin};
+use readback::ReadbackPlugin;
+use recording::RecordingPlugin;
+use shader_error::{ShaderDiagnostic, ShaderErrorPlugin, ShaderErrors};
+use simulation::{
+    FrameSteps, SimulationControl, SimulationPlugin, SimulationStepGraph, advance_simulation,
+};
+use storage::StoragePlugin;
+use textures::{InputTextureSamplers, InputTextures, TextureInput, TexturesPlugin};
+use timeline::TimelinePlugin;
+use validate::ValidateArgs;
 
-const SHADER_PATH: &str = "shader.wgsl";
-const DISPLAY_FACTOR: u32 = 4;
+#[derive(Parser)]
+#[command(about = "Run a WGSL compute shader and display its output")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    config: ConfigArgs,
+}
 
-const SIZE: (u32, u32) = (1280 / DISPLAY_FACTOR, 720 / DISPLAY_FACTOR);
+#[derive(Subcommand)]
+enum Command {
+    /// Render a fixed number of frames without a window, save some of them and exit.
+    Render(HeadlessArgs),
+    /// Run the compute shader on the CPU instead, save some frames and exit.
+    Cpu(CpuArgs),
+    /// Check the shader against the compute pipeline without running it.
+    Validate(ValidateArgs),
+}
 
-const WORKGROUP_SIZE: u32 = 8;
+fn main() -> AppExit {
+    let cli = Cli::parse();
+    let mut config = match PlaygroundConfig::load(&cli.config) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            return AppExit::from_code(2);
+        }
+    };
 
-fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugins(ComputeShaderPlugin)
+    let mut app = App::new();
+    match cli.command {
+        None => {
+            let window_size = config.window_size();
+            app.add_plugins(DefaultPlugins.set(WindowPlugin {
+                primary_window: Some(Window {
+                    resolution: (window_size.x as f32, window_size.y as f32).into(),
+                    ..default()
+                }),
+                ..default()
+            }));
+        }
+        Some(Command::Render(args)) => {
+            if let Err(err) = args.frames.validate() {
+                eprintln!("{err}");
+                return AppExit::from_code(2);
+            }
+            let plugins = match headless::plugins(&args) {
+                Ok(plugins) => plugins,
+                Err(err) => {
+                    eprintln!("{err}");
+                    return AppExit::error();
+                }
+            };
+            app.add_plugins(plugins).add_plugins(HeadlessPlugin(args));
+        }
+        Some(Command::Cpu(args)) => {
+            if let Err(err) = args.frames.validate() {
+                eprintln!("{err}");
+                return AppExit::from_code(2);
+            }
+            return match cpu::run(&config, &args) {
+                Ok(()) => AppExit::Success,
+                Err(err) => {
+                    eprintln!("{err}");
+                    AppExit::error()
+                }
+            };
+        }
+        Some(Command::Validate(args)) => {
+            return match validate::validate(&config, &args) {
+                Ok(paths) => {
+                    for path in paths {
+                        println!("{}: ok", path.display());
+                    }
+                    AppExit::Success
+                }
+                Err(diagnostics) => {
+                    for diagnostic in diagnostics {
+                        eprintln!("{diagnostic}");
+                    }
+                    AppExit::error()
+                }
+            };
+        }
+    }
+    // The configured textures go through the plugin's builder, like an embedding app's would.
+    let plugin = mem::take(&mut config.textures).into_iter().fold(
+        ComputeShaderPlugin::default(),
+        ComputeShaderPlugin::with_texture,
+    );
+    app.insert_resource(config)
+        .add_plugins(plugin)
         .add_systems(Startup, setup)
-        .run();
+        .add_systems(Update, swap_textures.after(advance_simulation))
+        .run()
 }
-fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+
+fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>, config: Res<PlaygroundConfig>) {
+    let size = Extent3d {
+        width: config.size.x,
+        height: config.size.y,
+        depth_or_array_layers: 1,
+    };
     let mut image = Image::new_fill(
-        Extent3d {
-            width: SIZE.0,
-            height: SIZE.1,
-            depth_or_array_layers: 1,
-        },
+        size,
         TextureDimension::D2,
-        &[255, 0, 0, 255],
-        TextureFormat::Rgba8Unorm,
+        &config.format.texel(Vec4::new(1.0, 0.0, 0.0, 1.0)),
+        config.format.texture_format(),
         RenderAssetUsages::RENDER_WORLD,
     );
 
-    image.texture_descriptor.usage =
-        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    image.texture_descriptor.usage = TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING;
+
+    // Formats the sprite can't show are converted into a texture it can.
+    let display = (!config.format.is_displayable()).then(|| {
+        let mut display = Image::new_fill(
+            size,
+            TextureDimension::D2,
+            &[0; 8],
+            DISPLAY_FORMAT,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        display.texture_descriptor.usage = TextureUsages::STORAGE_BINDING
+            | TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_SRC;
+        images.add(display)
+    });
+    let input = images.add(image.clone());
+    let output = images.add(image);
 
-    let image_handle = images.add(image.clone());
-    
     commands.spawn((
+        ComputeShaderSprite,
         Sprite {
-            image: image_handle.clone(),
-            custom_size: Some(Vec2::new(SIZE.0 as f32, SIZE.1 as f32)),
+            image: display.clone().unwrap_or_else(|| output.clone()),
+            custom_size: Some(config.size.as_vec2()),
             ..default()
         },
-        Transform::from_scale(Vec3::splat(DISPLAY_FACTOR as f32)),
+        Transform::from_scale(Vec3::splat(config.display_factor as f32)),
     ));
 
     commands.spawn(Camera2d);
     commands.insert_resource(ComputeShaderImage {
-        texture: image_handle,
+        input,
+        output,
+        display,
     });
 }
 
+/// Marks the sprite that displays the compute output.
+#[derive(Component)]
+struct ComputeShaderSprite;
+
+/// The two state textures of the simulation. They swap roles every step: `update` reads the
+/// state written by the previous step from `input` and writes the next one into `output`. After
+/// a frame, `output` holds the latest state.
 #[derive(Resource, Clone, ExtractResource)]
 struct ComputeShaderImage {
-    texture: Handle<Image>,
+    input: Handle<Image>,
+    output: Handle<Image>,
+    /// What the sprite shows instead of `output` when it can't show the state's format. The
+    /// output is converted into it every frame.
+    display: Option<Handle<Image>>,
 }
 
+/// Swap the state textures before the frame is extracted if it runs an odd number of steps, so
+/// that its last step writes `output`, and show `output`.
+fn swap_textures(
+    mut image: ResMut<ComputeShaderImage>,
+    control: Res<SimulationControl>,
+    mut sprite: Single<&mut Sprite, With<ComputeShaderSprite>>,
+) {
+    let image = &mut *image;
+    if control.swaps() {
+        mem::swap(&mut image.input, &mut image.output);
+    }
+    if image.display.is_none() {
+        sprite.image = image.output.clone();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn prepare_bind_group(
     mut commands: Commands,
-    pipeline: Res<ComputeShaderPipeline>,
+    pipeline: Option<Res<ComputeShaderPipeline>>,
     gpu_image: Res<RenderAssets<GpuImage>>,
     compute_shader_image: Res<ComputeShaderImage>,
+    globals_buffer: Res<ComputeShaderGlobalsBuffer>,
+    keyboard_texture: Res<KeyboardTexture>,
+    dispatch: Res<ComputeShaderDispatch>,
+    params: Res<ParamsBuffer>,
+    allocated: Res<AllocatedBindings>,
+    pass_images: Res<PassImages>,
+    input_textures: Res<InputTextures>,
+    texture_samplers: Res<InputTextureSamplers>,
     render_device: Res<RenderDevice>,
 ) {
-    let view = gpu_image.get(&compute_shader_image.texture).unwrap();
-    let bind_group = render_device.create_bind_group(
-        None,
-        &pipeline.bind_group_layout,
-        &BindGroupEntries::sequential((&view.texture_view,)),
-    );
-    commands.insert_resource(ComputeShaderBindGroup(bind_group))
+    // No offsets are written when the dispatch can't be planned, and then there's nothing to run.
+    let (Some(pipeline), Some(dispatch_offsets)) = (pipeline, dispatch.offsets.binding()) else {
+        return;
+    };
+    let (Some(passes), Some(textures)) = (
+        pass_images.gpu_images(&gpu_image),
+        input_textures.views(&gpu_image),
+    ) else {
+        return;
+    };
+    let input = gpu_image.get(&compute_shader_image.input).unwrap();
+    let output = gpu_image.get(&compute_shader_image.output).unwrap();
+    let keyboard = gpu_image.get(&keyboard_texture.texture).unwrap();
+    let bind_groups = [false, true].map(|swapped| {
+        let (input, output, passes) = match swapped {
+            false => (input, output, passes.clone()),
+            true => (
+                output,
+                input,
+                passes
+                    .iter()
+                    .map(|&[current, previous]| [previous, current])
+                    .collect(),
+            ),
+        };
+        let resources = BoundResources {
+            input,
+            output,
+            globals: globals_buffer.uniform.binding().unwrap(),
+            keyboard: &keyboard.texture_view,
+            dispatch: dispatch_offsets.clone(),
+            params: params.buffer.as_entire_binding(),
+            allocated: &allocated,
+            passes: &passes,
+            textures: &textures,
+            texture_samplers: &texture_samplers.0,
+        };
+        resources.bind_group(
+            &pipeline.bind_group_layout,
+            &pipeline.bindings,
+            &render_device,
+        )
+    });
+    commands.insert_resource(ComputeShaderBindGroup(bind_groups))
+}
+
+/// Set by the render world once `init` has run. Until then the simulation clock stands still,
+/// so `globals.time` and `globals.frame` count from the first frame that was actually computed.
+#[derive(Resource, Clone, Default)]
+struct ComputeShaderReady(Arc<AtomicBool>);
+
+impl ComputeShaderReady {
+    fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
 }
 
-struct ComputeShaderPlugin;
+/// Runs the configured compute shaders. Needs a [`PlaygroundConfig`] resource.
+#[derive(Default)]
+struct ComputeShaderPlugin {
+    textures: Vec<TextureInput>,
+}
+
+impl ComputeShaderPlugin {
+    /// Bind an image file in addition to the configured textures.
+    fn with_texture(mut self, texture: TextureInput) -> Self {
+        self.textures.push(texture);
+        self
+    }
+}
 
 impl Plugin for ComputeShaderPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(ExtractResourcePlugin::<ComputeShaderImage>::default());
+        let mut config = app.world_mut().resource_mut::<PlaygroundConfig>();
+        config.textures.extend(self.textures.iter().cloned());
+        if let Err(err) = config.validate() {
+            panic!("{err}");
+        }
+        let config = config.clone();
+        let ready = ComputeShaderReady::default();
+        app.insert_resource(ready.clone()).add_plugins((
+            ExtractResourcePlugin::<ComputeShaderImage>::default(),
+            GlobalsPlugin,
+            ShaderInputPlugin,
+            ShaderErrorPlugin,
+            DispatchPlugin,
+            ShaderBindingsPlugin,
+            SimulationPlugin,
+            StoragePlugin,
+            TexturesPlugin,
+        ));
 
         let render_app = app.sub_app_mut(RenderApp);
-        render_app.add_systems(
-            Render,
-            prepare_bind_group.in_set(RenderSet::PrepareBindGroups),
-        );
+        render_app
+            .insert_resource(config)
+            .insert_resource(ready)
+            .init_resource::<AllocatedBindings>()
+            .init_resource::<StepPipeline>()
+            .add_systems(
+                Render,
+                (
+                    prepare_pipeline.in_set(RenderSet::PrepareResources),
+                    prepare_bind_group.in_set(RenderSet::PrepareBindGroups),
+                ),
+            );
 
         let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
         render_graph.add_node(ComputeShaderLabel, ComputeShaderNode::default());
         render_graph.add_node_edge(ComputeShaderLabel, bevy::render::graph::CameraDriverLabel);
-    }
+        let mut step_graph = RenderGraph::default();
+        step_graph.add_node(GlobalsStepLabel, GlobalsStepNode);
+        step_graph.add_node(ComputeShaderStepLabel, ComputeShaderStepNode);
+        step_graph.add_node_edge(GlobalsStepLabel, ComputeShaderStepLabel);
+        render_graph.add_sub_graph(SimulationStepGraph, step_graph);
 
-    fn finish(&self, app: &mut App) {
-        let render_app = app.sub_app_mut(RenderApp);
-        render_app.init_resource::<ComputeShaderPipeline>();
+        // These hook into the render graph and the step graph before and after the compute
+        // shader.
+        app.add_plugins((
+            PassesPlugin,
+            DisplayPlugin,
+            ReadbackPlugin,
+            CapturePlugin,
+            InspectorPlugin,
+            ProfilingPlugin,
+            ParamsPlugin,
+            PresetsPlugin,
+            TimelinePlugin,
+            RecordingPlugin,
+        ));
     }
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
 struct ComputeShaderLabel;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct ComputeShaderStepLabel;
+
+/// The pipelines for the latest reflected version of the shader.
 #[derive(Resource)]
 struct ComputeShaderPipeline {
+    /// The reflected copy of the shader these pipelines compile.
+    shader: AssetId<Shader>,
+    bindings: Vec<ShaderBinding>,
     bind_group_layout: BindGroupLayout,
-    pipeline: CachedComputePipelineId,
+    init_pipeline: CachedComputePipelineId,
+    update_pipeline: CachedComputePipelineId,
 }
 
-impl FromWorld for ComputeShaderPipeline {
-    fn from_world(world: &mut World) -> Self {
-        let render_device = world.resource::<RenderDevice>();
-        let bind_group_layout = render_device.create_bind_group_layout(
-            "Image",
-            &BindGroupLayoutEntries::single(
-                ShaderStages::COMPUTE,
-                texture_storage_2d(TextureFormat::Rgba8Unorm, StorageTextureAccess::ReadWrite),
-            ),
-        );
+/// Entry point that runs once, on the first frame the pipelines are ready.
+const INIT_ENTRY_POINT: &str = "init";
+/// Entry point that runs on every frame after that.
+const UPDATE_ENTRY_POINT: &str = "update";
 
-        let shader = world.load_asset(SHADER_PATH);
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-            label: None,
-            layout: vec![bind_group_layout.clone()],
-            push_constant_ranges: Vec::new(),
-            shader: shader.clone(),
-            shader_defs: vec![],
-            entry_point: Cow::from("init"),
-            zero_initialize_workgroup_memory: false,
-        });
-        ComputeShaderPipeline {
-            bind_group_layout,
-            pipeline,
-        }
+/// Queue new pipelines, with a layout built from the shader's bindings, whenever the shader has
+/// been reflected again.
+fn prepare_pipeline(
+    mut commands: Commands,
+    layout: Res<ComputeShaderLayout>,
+    pipeline: Option<Res<ComputeShaderPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    config: Res<PlaygroundConfig>,
+    mut allocated: ResMut<AllocatedBindings>,
+    render_device: Res<RenderDevice>,
+) {
+    let layout = &layout.main;
+    let Some(shader) = &layout.shader else {
+        return;
+    };
+    if pipeline.is_some_and(|pipeline| pipeline.shader == shader.id()) {
+        return;
     }
+
+    allocated.allocate(&layout.bindings, config.size, &render_device);
+    let bind_group_layout = render_device
+        .create_bind_group_layout("Image", &bindings::layout_entries(&layout.bindings));
+    let shader_defs = config.shader_defs(config.format);
+    let init_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+        label: None,
+        layout: vec![bind_group_layout.clone()],
+        push_constant_ranges: Vec::new(),
+        shader: shader.clone(),
+        shader_defs: shader_defs.clone(),
+        entry_point: Cow::from(INIT_ENTRY_POINT),
+        zero_initialize_workgroup_memory: false,
+    });
+    let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+        label: None,
+        layout: vec![bind_group_layout.clone()],
+        push_constant_ranges: Vec::new(),
+        shader: shader.clone(),
+        shader_defs,
+        entry_point: Cow::from(UPDATE_ENTRY_POINT),
+        zero_initialize_workgroup_memory: false,
+    });
+    commands.insert_resource(ComputeShaderPipeline {
+        shader: shader.id(),
+        bindings: layout.bindings.clone(),
+        bind_group_layout,
+        init_pipeline,
+        update_pipeline,
+    });
 }
 
+/// The bind groups of the steps that read `input`, and of those that read `output`.
 #[derive(Resource)]
-struct ComputeShaderBindGroup(BindGroup);
+struct ComputeShaderBindGroup([StepBindGroup; 2]);
 
-struct ComputeShaderNode;
+/// The entry point the steps of this frame dispatch and its pipeline, set by
+/// [`ComputeShaderNode`] for [`ComputeShaderStepNode`].
+#[derive(Resource, Default)]
+struct StepPipeline(Option<(&'static str, ComputePipeline)>);
+
+enum ComputeShaderState {
+    Loading,
+    Init,
+    Update,
+}
+
+/// Runs [`SimulationStepGraph`] for every step of the frame. The node holds on to the last
+/// pipelines that compiled, so a broken edit to the shader keeps the previous version running
+/// until the fix compiles.
+struct ComputeShaderNode {
+    state: ComputeShaderState,
+    /// The shader copy the pipelines below were compiled from.
+    shader: Option<AssetId<Shader>>,
+    init_pipeline: Option<ComputePipeline>,
+    update_pipeline: Option<ComputePipeline>,
+}
 
 impl Default for ComputeShaderNode {
     fn default() -> Self {
-        Self
+        Self {
+            state: ComputeShaderState::Loading,
+            shader: None,
+            init_pipeline: None,
+            update_pipeline: None,
+        }
     }
 }
+
 impl render_graph::Node for ComputeShaderNode {
+    fn update(&mut self, world: &mut World) {
+        let layout_error = world.resource::<ComputeShaderLayout>().error();
+        let passes = world.resource::<PassPipelines>();
+        let (passes_ready, passes_error) = (passes.ready(), passes.error());
+        let Some(pipeline) = world.get_resource::<ComputeShaderPipeline>() else {
+            world.resource::<ShaderErrors>().set(layout_error);
+            return;
+        };
+        // Pipelines of an earlier version don't fit the bind group of the new layout.
+        if self.shader != Some(pipeline.shader) {
+            self.shader = Some(pipeline.shader);
+            self.init_pipeline = None;
+            self.update_pipeline = None;
+        }
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let mut error = None;
+        let mut ready = true;
+        for (id, last_good) in [
+            (pipeline.init_pipeline, &mut self.init_pipeline),
+            (pipeline.update_pipeline, &mut self.update_pipeline),
+        ] {
+            match pipeline_cache.get_compute_pipeline_state(id) {
+                CachedPipelineState::Ok(Pipeline::ComputePipeline(compiled)) => {
+                    *last_good = Some(compiled.clone());
+                }
+                CachedPipelineState::Err(err) => {
+                    error = error.or_else(|| ShaderDiagnostic::from_pipeline_error(err));
+                    ready = false;
+                }
+                _ => ready = false,
+            }
+        }
+
+        // Keep showing the error while the fixed shader is still compiling.
+        let error = layout_error.or(error).or(passes_error);
+        let errors = world.resource::<ShaderErrors>();
+        if error.is_some() || (ready && passes_ready) {
+            errors.set(error);
+        }
+
+        // `init` runs on the first frame both entry points and every pass are ready, and again
+        // whenever the simulation is reset.
+        match self.state {
+            ComputeShaderState::Loading => {
+                // Image files may still be loading, and then there's no bind group yet.
+                let bound = world.contains_resource::<ComputeShaderBindGroup>();
+                if self.init_pipeline.is_some()
+                    && self.update_pipeline.is_some()
+                    && passes_ready
+                    && bound
+                {
+                    info!("Compute shader pipelines ready, running init");
+                    world.resource::<ComputeShaderReady>().set();
+                    self.state = ComputeShaderState::Init;
+                }
+            }
+            ComputeShaderState::Init | ComputeShaderState::Update => {
+                self.state = if world.resource::<SimulationControl>().resets() {
+                    ComputeShaderState::Init
+                } else {
+                    ComputeShaderState::Update
+                };
+            }
+        }
+
+        let (pipeline, steps) = match (&self.state, &self.init_pipeline, &self.update_pipeline) {
+            (ComputeShaderState::Init, Some(init), _) => (Some((INIT_ENTRY_POINT, init)), 1),
+            (ComputeShaderState::Update, _, Some(update)) => (
+                Some((UPDATE_ENTRY_POINT, update)),
+                world.resource::<SimulationControl>().steps(),
+            ),
+            _ => (None, 0),
+        };
+        let pipeline = pipeline.map(|(entry_point, pipeline)| (entry_point, pipeline.clone()));
+        world.insert_resource(StepPipeline(pipeline));
+        world.resource_mut::<FrameSteps>().start(steps);
+    }
+
     fn run(
         &self,
-        _graph: &mut RenderGraphContext,
+        graph: &mut RenderGraphContext,
         render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), NodeRunError> {
-        let bind_group = &world.resource::<ComputeShaderBindGroup>();
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let pipeline = world.resource::<ComputeShaderPipeline>();
+        if !world.contains_resource::<ComputeShaderBindGroup>() {
+            return Ok(());
+        }
+        let steps = world.resource::<FrameSteps>().count();
 
-        if let Some(cpipeline) = pipeline_cache.get_compute_pipeline(pipeline.pipeline) {
-            let mut pass = render_context
-                .command_encoder()
-                .begin_compute_pass(&ComputePassDescriptor::default());
+        // A reset starts over from the zeroed resources of the first frame.
+        let control = world.resource::<SimulationControl>();
+        if matches!(self.state, ComputeShaderState::Init) && control.resets() && steps > 0 {
+            let encoder = render_context.command_encoder();
+            let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+            world.resource::<AllocatedBindings>().clear(encoder);
+            world.resource::<PassPipelines>().clear(
+                encoder,
+                world.resource::<PassImages>(),
+                gpu_images,
+            );
+        }
+        for _ in 0..steps {
+            graph.run_sub_graph(SimulationStepGraph, Vec::new(), None)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the compute shader for the step being recorded, after the passes, and moves on to the
+/// next step.
+struct ComputeShaderStepNode;
 
-            pass.set_bind_group(0, &bind_group.0, &[]);
-            pass.set_pipeline(cpipeline);
-            pass.dispatch_workgroups(SIZE.0 / WORKGROUP_SIZE, SIZE.1 / WORKGROUP_SIZE, 1);
+impl render_graph::Node for ComputeShaderStepNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let steps = world.resource::<FrameSteps>();
+        let swapped = steps.swapped();
+        let last = steps.advance();
+        let (
+            Some(ComputeShaderBindGroup(bind_groups)),
+            StepPipeline(Some((entry_point, pipeline))),
+        ) = (world.get_resource(), world.resource())
+        else {
+            return Ok(());
+        };
+        let dispatch = world.resource::<ComputeShaderDispatch>();
+        let dynamic_offset =
+            has_dynamic_offset(&world.resource::<ComputeShaderPipeline>().bindings);
+        let profiler = world.resource::<PassProfiler>();
+        let render_device = render_context.render_device().clone();
+        let encoder = render_context.command_encoder();
+
+        profiler.time(entry_point, encoder, |encoder, timestamp_writes| {
+            bind_groups[swapped as usize].record(
+                encoder,
+                pipeline,
+                dispatch,
+                dynamic_offset,
+                timestamp_writes,
+            );
+        });
+        if last {
+            profiler.finish(encoder, &render_device);
         }
         Ok(())
     }
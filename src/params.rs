@@ -0,0 +1,796 @@
+//! Shader parameters that can be edited while the shader runs.
+//!
+//! A uniform named `params` is bound to a buffer the playground fills from a panel in the
+//! window. The panel has a control for every member of the uniform's struct, picked by the
+//! comments on the member's line or on the lines above it:
+//!
+//! ```wgsl
+//! struct Params {
+//!     // @param range(1, 32) default(4)
+//!     brush_radius: f32,
+//!     brush_color: vec3<f32>, // @color default(1, 1, 1)
+//!     // @toggle
+//!     erase: u32,
+//! }
+//!
+//! @group(0) @binding(5)
+//! var<uniform> params: Params;
+//! ```
+//!
+//! `@param` is a slider per component, from 0 to 1 unless a `range` is given, `@color` a color
+//! picker for `vec3<f32>` and `vec4<f32>`, and `@toggle` a checkbox for a scalar that is 0 or 1.
+//! Members without comments get sliders. Every control starts at its `default`, or 0.
+//!
+//! F6 shows or hides the panel. Passes can bind `params` too; they see the same bytes, so they
+//! should declare the same struct.
+
+use std::collections::HashMap;
+
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::color::Color;
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::prelude::{
+    BackgroundColor, BorderColor, Button, Changed, ChildSpawnerCommands, Children, Commands,
+    Component, DetectChanges, DetectChangesMut, Entity, FlexDirection, FromWorld, Interaction,
+    IntoScheduleConfigs, Local, Node, PositionType, Query, Res, ResMut, Resource, Single,
+    SystemSet, Text, TextColor, TextFont, UiRect, Val, Visibility, With, Without, World, default,
+};
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_resource::{Buffer, BufferDescriptor, BufferUsages};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy::ui::RelativeCursorPosition;
+use naga::{AddressSpace, Module, ScalarKind, TypeInner};
+
+use crate::bindings::{BindingSource, ComputeShaderLayout};
+
+/// Key that shows or hides the panel.
+const PANEL_KEY: KeyCode = KeyCode::F6;
+
+/// Name of the uniform the parameters are bound to.
+pub const PARAMS_NAME: &str = "params";
+
+const SLIDER_WIDTH: f32 = 180.0;
+
+/// The control a member of the parameter struct is edited with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamWidget {
+    /// A slider per component.
+    Slider { min: f32, max: f32 },
+    /// A swatch and a slider per channel, from 0 to 1.
+    Color,
+    /// A checkbox that sets the value to 0 or 1.
+    Toggle,
+}
+
+/// The scalar type of a member's components.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamScalar {
+    Float,
+    Sint,
+    Uint,
+}
+
+/// A member of the parameter struct.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamField {
+    pub name: String,
+    /// Byte offset in the uniform.
+    pub offset: u32,
+    pub scalar: ParamScalar,
+    /// 1 for scalars, 2 to 4 for vectors.
+    pub components: usize,
+    pub widget: ParamWidget,
+    pub default: [f32; 4],
+}
+
+impl ParamField {
+    /// Whether a value of `other` means the same as a value of this field, so edits can be
+    /// kept when the shader is reloaded.
+    fn same_value(&self, other: &ParamField) -> bool {
+        self.name == other.name
+            && self.scalar == other.scalar
+            && self.components == other.components
+            && self.default == other.default
+    }
+}
+
+/// The parameter struct a shader declares.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamsLayout {
+    /// Size of the struct in bytes.
+    pub size: u32,
+    pub fields: Vec<ParamField>,
+}
+
+impl ParamsLayout {
+    pub fn defaults(&self) -> Vec<[f32; 4]> {
+        self.fields.iter().map(|field| field.default).collect()
+    }
+
+    /// The uniform buffer contents for `values`, one per field.
+    pub fn bytes(&self, values: &[[f32; 4]]) -> Vec<u8> {
+        let mut bytes = vec![0; self.size as usize];
+        for (field, value) in self.fields.iter().zip(values) {
+            for (i, component) in value[..field.components].iter().enumerate() {
+                let word = match field.scalar {
+                    ParamScalar::Float => component.to_le_bytes(),
+                    ParamScalar::Sint => (component.round() as i32).to_le_bytes(),
+                    ParamScalar::Uint => (component.round() as u32).to_le_bytes(),
+                };
+                let offset = field.offset as usize + 4 * i;
+                bytes[offset..offset + 4].copy_from_slice(&word);
+            }
+        }
+        bytes
+    }
+}
+
+/// The layout of the `params` uniform a module declares, with the controls its comments in
+/// `source` ask for. `None` if there's no such uniform.
+pub fn reflect_params(module: &Module, source: &str) -> Result<Option<ParamsLayout>, Vec<String>> {
+    let Some((_, global)) = module.global_variables.iter().find(|(_, global)| {
+        global.name.as_deref() == Some(PARAMS_NAME) && global.space == AddressSpace::Uniform
+    }) else {
+        return Ok(None);
+    };
+    let ty = &module.types[global.ty];
+    let TypeInner::Struct { members, span } = &ty.inner else {
+        return Err(vec![format!("`{PARAMS_NAME}` must be a struct")]);
+    };
+    let annotations = ty
+        .name
+        .as_deref()
+        .map(|name| member_annotations(source, name))
+        .unwrap_or_default();
+
+    let mut problems = Vec::new();
+    let mut fields = Vec::new();
+    for member in members {
+        let name = member.name.clone().unwrap_or_default();
+        let annotation = annotations.get(&name).map_or("", String::as_str);
+        match param_field(module, member, &name, annotation) {
+            Ok(field) => fields.push(field),
+            Err(problem) => problems.push(format!("`{PARAMS_NAME}.{name}`: {problem}")),
+        }
+    }
+    if problems.is_empty() {
+        Ok(Some(ParamsLayout {
+            size: *span,
+            fields,
+        }))
+    } else {
+        Err(problems)
+    }
+}
+
+fn param_field(
+    module: &Module,
+    member: &naga::StructMember,
+    name: &str,
+    annotation: &str,
+) -> Result<ParamField, String> {
+    let (scalar, components) = match module.types[member.ty].inner {
+        TypeInner::Scalar(scalar) => (scalar, 1),
+        TypeInner::Vector { size, scalar } => (scalar, size as usize),
+        _ => return Err("only scalars and vectors can be edited".to_string()),
+    };
+    let scalar = match (scalar.kind, scalar.width) {
+        (ScalarKind::Float, 4) => ParamScalar::Float,
+        (ScalarKind::Sint, 4) => ParamScalar::Sint,
+        (ScalarKind::Uint, 4) => ParamScalar::Uint,
+        _ => return Err("only 32-bit scalars can be edited".to_string()),
+    };
+
+    let mut widget = None;
+    let mut range = None;
+    let mut default = None;
+    for (key, args) in parse_annotation(annotation)? {
+        match (key.as_str(), args) {
+            ("@param", None) => widget = Some(ParamWidget::Slider { min: 0.0, max: 1.0 }),
+            ("@color", None) => widget = Some(ParamWidget::Color),
+            ("@toggle", None) => widget = Some(ParamWidget::Toggle),
+            ("range", Some(args)) => match numbers(&args)?[..] {
+                [min, max] if min < max => range = Some((min, max)),
+                _ => return Err("`range` takes a minimum and a larger maximum".to_string()),
+            },
+            ("default", Some(args)) => default = Some(numbers(&args)?),
+            _ => return Err(format!("unknown annotation `{key}`")),
+        }
+    }
+
+    let widget = match (widget, range) {
+        (Some(ParamWidget::Slider { .. }) | None, Some((min, max))) => {
+            ParamWidget::Slider { min, max }
+        }
+        (Some(_), Some(_)) => return Err("only `@param` takes a `range`".to_string()),
+        (widget, None) => widget.unwrap_or(ParamWidget::Slider { min: 0.0, max: 1.0 }),
+    };
+    match widget {
+        ParamWidget::Color if scalar != ParamScalar::Float || components < 3 => {
+            return Err("`@color` needs a vec3<f32> or vec4<f32>".to_string());
+        }
+        ParamWidget::Toggle if components != 1 => {
+            return Err("`@toggle` needs a scalar".to_string());
+        }
+        _ => {}
+    }
+
+    let mut value = [0.0; 4];
+    match default.as_deref() {
+        None => {}
+        Some(&[splat]) => value = [splat; 4],
+        Some(values) if values.len() == components => value[..components].copy_from_slice(values),
+        Some(_) => return Err(format!("`default` needs 1 or {components} values")),
+    }
+    Ok(ParamField {
+        name: name.to_string(),
+        offset: member.offset,
+        scalar,
+        components,
+        widget,
+        default: value,
+    })
+}
+
+/// The `@` comments of each member of `struct name`, on the member's line or the lines above
+/// it, joined by spaces.
+fn member_annotations(source: &str, name: &str) -> HashMap<String, String> {
+    let mut annotations = HashMap::new();
+    let mut lines = source.lines();
+    let declared = |line: &str| {
+        line.trim()
+            .strip_prefix("struct ")
+            .and_then(|rest| rest.split(|c: char| c == '{' || c.is_whitespace()).next())
+            == Some(name)
+    };
+    if lines.find(|line| declared(line)).is_none() {
+        return annotations;
+    }
+
+    let mut pending = String::new();
+    for line in lines {
+        let (code, comment) = line.split_once("//").unwrap_or((line, ""));
+        let comment = comment.trim();
+        if comment.starts_with('@') {
+            pending = format!("{pending} {comment}");
+        }
+        if let Some((member, _)) = code.split_once(':') {
+            // Attributes like `@align(16)` come before the name.
+            let member = member.split_whitespace().last().unwrap_or_default();
+            annotations.insert(member.to_string(), std::mem::take(&mut pending));
+        }
+        if code.contains('}') {
+            break;
+        }
+    }
+    annotations
+}
+
+/// Split an annotation into its `@` words and `key(args)` calls.
+fn parse_annotation(annotation: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut parts = Vec::new();
+    let mut rest = annotation.trim_start();
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| c != '@' && c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let (key, after) = rest.split_at(end);
+        if key.is_empty() {
+            return Err(format!("can't parse the annotation at `{rest}`"));
+        }
+        let after = after.trim_start();
+        match after.strip_prefix('(') {
+            Some(args) => {
+                let (args, after) = args
+                    .split_once(')')
+                    .ok_or_else(|| format!("`{key}(` isn't closed"))?;
+                parts.push((key.to_string(), Some(args.to_string())));
+                rest = after.trim_start();
+            }
+            None => {
+                parts.push((key.to_string(), None));
+                rest = after;
+            }
+        }
+    }
+    Ok(parts)
+}
+
+/// Comma-separated numbers, with `true` and `false` as 1 and 0.
+fn numbers(args: &str) -> Result<Vec<f32>, String> {
+    args.split(',')
+        .map(|arg| match arg.trim() {
+            "true" => Ok(1.0),
+            "false" => Ok(0.0),
+            arg => arg.parse().map_err(|_| format!("`{arg}` isn't a number")),
+        })
+        .collect()
+}
+
+/// The parameters of the main shader and their current values.
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct ShaderParams {
+    pub layout: Option<ParamsLayout>,
+    /// One per field of `layout`.
+    pub values: Vec<[f32; 4]>,
+}
+
+impl ShaderParams {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.layout
+            .as_ref()
+            .map(|layout| layout.bytes(&self.values))
+            .unwrap_or_default()
+    }
+
+    /// Switch to a new layout, keeping the values of the fields that didn't change.
+    fn set_layout(&mut self, layout: Option<ParamsLayout>) {
+        let values = layout.as_ref().map_or_else(Vec::new, |layout| {
+            layout
+                .fields
+                .iter()
+                .map(|field| {
+                    let old = self.layout.as_ref().and_then(|old| {
+                        let index = old.fields.iter().position(|f| f.same_value(field))?;
+                        self.values.get(index).copied()
+                    });
+                    old.unwrap_or(field.default)
+                })
+                .collect()
+        });
+        self.layout = layout;
+        self.values = values;
+    }
+}
+
+/// The uniform buffer bound to every `params` uniform, large enough for the largest of them.
+#[derive(Resource)]
+pub struct ParamsBuffer {
+    pub buffer: Buffer,
+    size: u64,
+}
+
+impl FromWorld for ParamsBuffer {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        Self {
+            buffer: create_params_buffer(render_device, 16),
+            size: 16,
+        }
+    }
+}
+
+fn create_params_buffer(render_device: &RenderDevice, size: u64) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("params"),
+        size,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Shows [`ShaderParams`] in the panel. Systems that set the values run before it.
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShowParams;
+
+pub struct ParamsPlugin;
+
+impl Plugin for ParamsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShaderParams>()
+            .add_plugins(ExtractResourcePlugin::<ShaderParams>::default())
+            .add_systems(Startup, setup_panel)
+            .add_systems(
+                Update,
+                (
+                    sync_params,
+                    rebuild_panel,
+                    (drag_sliders, click_toggles),
+                    panel_hotkey,
+                    update_panel.in_set(ShowParams),
+                )
+                    .chain(),
+            );
+
+        app.sub_app_mut(RenderApp).add_systems(
+            Render,
+            prepare_params_buffer.in_set(RenderSet::PrepareResources),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp).init_resource::<ParamsBuffer>();
+    }
+}
+
+/// Pick up the parameters of the main shader whenever it's reflected again.
+pub fn sync_params(layout: Res<ComputeShaderLayout>, mut params: ResMut<ShaderParams>) {
+    if layout.is_changed() && params.layout != layout.main.params {
+        params.set_layout(layout.main.params.clone());
+    }
+}
+
+/// Upload the values every frame, into a buffer as large as the largest `params` binding.
+fn prepare_params_buffer(
+    mut buffer: ResMut<ParamsBuffer>,
+    params: Res<ShaderParams>,
+    layout: Res<ComputeShaderLayout>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let mut bytes = params.bytes();
+    let shaders = [&layout.main].into_iter().chain(&layout.passes);
+    let largest = shaders
+        .flat_map(|shader| &shader.bindings)
+        .filter(|binding| binding.source == BindingSource::Params)
+        .map(|binding| binding.buffer_size)
+        .max()
+        .unwrap_or(0);
+    let size = largest.max(bytes.len() as u64).max(16).next_multiple_of(16);
+    if size > buffer.size {
+        buffer.buffer = create_params_buffer(&render_device, size);
+        buffer.size = size;
+    }
+    bytes.resize(size as usize, 0);
+    render_queue.write_buffer(&buffer.buffer, 0, &bytes);
+}
+
+#[derive(Resource, Default)]
+struct PanelHidden(bool);
+
+#[derive(Component)]
+struct ParamsPanel;
+
+/// Shows the value of a field.
+#[derive(Component)]
+struct ParamValueText(usize);
+
+/// Sets a component of a field from where it's pressed.
+#[derive(Component)]
+struct ParamSlider {
+    field: usize,
+    component: usize,
+    min: f32,
+    max: f32,
+}
+
+/// The bar of a [`ParamSlider`] that shows its value.
+#[derive(Component)]
+struct ParamSliderFill {
+    field: usize,
+    component: usize,
+    min: f32,
+    max: f32,
+}
+
+#[derive(Component)]
+struct ParamSwatch(usize);
+
+#[derive(Component)]
+struct ParamToggle(usize);
+
+fn setup_panel(mut commands: Commands) {
+    commands.init_resource::<PanelHidden>();
+    commands.spawn((
+        ParamsPanel,
+        // Keeps clicks between the controls from reaching the shader, see `update_mouse`.
+        Interaction::default(),
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            right: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            row_gap: Val::Px(4.0),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+}
+
+/// Replace the controls whenever the parameters change shape.
+fn rebuild_panel(
+    mut commands: Commands,
+    params: Res<ShaderParams>,
+    panel: Single<Entity, With<ParamsPanel>>,
+    mut built: Local<Option<ParamsLayout>>,
+) {
+    if *built == params.layout {
+        return;
+    }
+    built.clone_from(&params.layout);
+    let mut panel = commands.entity(*panel);
+    panel.despawn_related::<Children>();
+    let Some(layout) = &params.layout else {
+        return;
+    };
+    panel.with_children(|panel| {
+        for (index, field) in layout.fields.iter().enumerate() {
+            panel.spawn((
+                Text::new(field.name.clone()),
+                TextFont {
+                    font_size: 12.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                ParamValueText(index),
+            ));
+            match field.widget {
+                ParamWidget::Slider { min, max } => {
+                    for component in 0..field.components {
+                        spawn_slider(panel, index, component, min, max);
+                    }
+                }
+                ParamWidget::Color => {
+                    panel.spawn((
+                        ParamSwatch(index),
+                        BackgroundColor(Color::BLACK),
+                        Node {
+                            width: Val::Px(SLIDER_WIDTH),
+                            height: Val::Px(14.0),
+                            ..default()
+                        },
+                    ));
+                    for component in 0..field.components {
+                        spawn_slider(panel, index, component, 0.0, 1.0);
+                    }
+                }
+                ParamWidget::Toggle => {
+                    panel.spawn((
+                        Button,
+                        ParamToggle(index),
+                        BackgroundColor(Color::NONE),
+                        BorderColor(Color::WHITE),
+                        Node {
+                            width: Val::Px(14.0),
+                            height: Val::Px(14.0),
+                            border: UiRect::all(Val::Px(1.0)),
+                            ..default()
+                        },
+                    ));
+                }
+            }
+        }
+    });
+}
+
+fn spawn_slider(
+    panel: &mut ChildSpawnerCommands,
+    field: usize,
+    component: usize,
+    min: f32,
+    max: f32,
+) {
+    panel
+        .spawn((
+            Button,
+            RelativeCursorPosition::default(),
+            ParamSlider {
+                field,
+                component,
+                min,
+                max,
+            },
+            BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+            Node {
+                width: Val::Px(SLIDER_WIDTH),
+                height: Val::Px(10.0),
+                ..default()
+            },
+        ))
+        .with_child((
+            ParamSliderFill {
+                field,
+                component,
+                min,
+                max,
+            },
+            BackgroundColor(Color::srgb(0.4, 0.6, 1.0)),
+            Node {
+                height: Val::Percent(100.0),
+                ..default()
+            },
+        ));
+}
+
+/// Set the value of a slider from the cursor for as long as it's held, even beyond its ends.
+fn drag_sliders(
+    sliders: Query<(&Interaction, &RelativeCursorPosition, &ParamSlider)>,
+    mut params: ResMut<ShaderParams>,
+) {
+    for (interaction, cursor, slider) in &sliders {
+        let (Interaction::Pressed, Some(cursor)) = (interaction, cursor.normalized) else {
+            continue;
+        };
+        let value = slider.min + (slider.max - slider.min) * cursor.x.clamp(0.0, 1.0);
+        // Only touch the values when they change, so the panel isn't redrawn every frame.
+        if params
+            .values
+            .get(slider.field)
+            .is_some_and(|values| values[slider.component] != value)
+        {
+            params.values[slider.field][slider.component] = value;
+        }
+    }
+}
+
+fn click_toggles(
+    toggles: Query<(&Interaction, &ParamToggle), Changed<Interaction>>,
+    mut params: ResMut<ShaderParams>,
+) {
+    for (interaction, toggle) in &toggles {
+        if *interaction == Interaction::Pressed
+            && let Some(value) = params.values.get_mut(toggle.0)
+        {
+            value[0] = if value[0] != 0.0 { 0.0 } else { 1.0 };
+        }
+    }
+}
+
+fn panel_hotkey(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut hidden: ResMut<PanelHidden>,
+    params: Res<ShaderParams>,
+    mut panel: Single<&mut Visibility, With<ParamsPanel>>,
+) {
+    if keys.just_pressed(PANEL_KEY) {
+        hidden.0 = !hidden.0;
+    }
+    let visible = !hidden.0 && params.layout.as_ref().is_some_and(|l| !l.fields.is_empty());
+    panel.set_if_neq(if visible {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    });
+}
+
+/// Show the current values on the controls.
+fn update_panel(
+    params: Res<ShaderParams>,
+    mut texts: Query<(&mut Text, &ParamValueText)>,
+    mut fills: Query<(&mut Node, &ParamSliderFill)>,
+    mut swatches: Query<(&mut BackgroundColor, &ParamSwatch)>,
+    mut toggles: Query<(&mut BackgroundColor, &ParamToggle), Without<ParamSwatch>>,
+) {
+    let Some(layout) = &params.layout else {
+        return;
+    };
+    if !params.is_changed() {
+        return;
+    }
+
+    for (mut text, ParamValueText(index)) in &mut texts {
+        let (field, value) = (&layout.fields[*index], params.values[*index]);
+        text.0 = format!("{}: {}", field.name, describe_value(field, value));
+    }
+    for (mut node, fill) in &mut fills {
+        let value = params.values[fill.field][fill.component];
+        let fraction = ((value - fill.min) / (fill.max - fill.min)).clamp(0.0, 1.0);
+        node.width = Val::Percent(100.0 * fraction);
+    }
+    for (mut color, ParamSwatch(index)) in &mut swatches {
+        let [r, g, b, a] = params.values[*index];
+        let alpha = if layout.fields[*index].components == 4 {
+            a
+        } else {
+            1.0
+        };
+        color.0 = Color::srgba(r, g, b, alpha);
+    }
+    for (mut color, ParamToggle(index)) in &mut toggles {
+        color.0 = if params.values[*index][0] != 0.0 {
+            Color::WHITE
+        } else {
+            Color::NONE
+        };
+    }
+}
+
+/// A value the way the shader sees it.
+fn describe_value(field: &ParamField, value: [f32; 4]) -> String {
+    let components: Vec<String> = value[..field.components]
+        .iter()
+        .map(|component| match field.scalar {
+            ParamScalar::Float => format!("{component:.3}"),
+            ParamScalar::Sint => (component.round() as i32).to_string(),
+            ParamScalar::Uint => (component.round() as u32).to_string(),
+        })
+        .collect();
+    match field.widget {
+        ParamWidget::Toggle => (value[0] != 0.0).to_string(),
+        _ => components.join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reflect(members: &str) -> Result<Option<ParamsLayout>, Vec<String>> {
+        let source = format!(
+            "struct Params {{\n{members}\n}}\n\n\
+             @group(0) @binding(5)\nvar<uniform> params: Params;\n\n\
+             @compute @workgroup_size(1)\nfn main() {{\n    let x = params;\n}}\n"
+        );
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+        reflect_params(&module, &source)
+    }
+
+    fn fields(members: &str) -> Vec<ParamField> {
+        reflect(members).unwrap().unwrap().fields
+    }
+
+    #[test]
+    fn reads_the_documented_annotation() {
+        let fields = fields("    // @param range(0,1) default(0.5)\n    speed: f32,");
+        assert_eq!(
+            fields,
+            [ParamField {
+                name: "speed".to_string(),
+                offset: 0,
+                scalar: ParamScalar::Float,
+                components: 1,
+                widget: ParamWidget::Slider { min: 0.0, max: 1.0 },
+                default: [0.5; 4],
+            }]
+        );
+    }
+
+    #[test]
+    fn comments_annotate_their_line_or_the_next_member() {
+        let fields = fields(
+            "    radius: f32, // @param range(1, 32)\n    // @toggle\n    erase: u32,\n    \
+             color: vec3<f32>, // @color default(1, 0.5, 0)",
+        );
+        let widgets: Vec<_> = fields.iter().map(|field| field.widget).collect();
+        assert_eq!(
+            widgets,
+            [
+                ParamWidget::Slider {
+                    min: 1.0,
+                    max: 32.0
+                },
+                ParamWidget::Toggle,
+                ParamWidget::Color,
+            ]
+        );
+        assert_eq!(fields[2].default, [1.0, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn attributes_before_a_member_keep_its_name() {
+        let fields = fields("    a: f32,\n    // @param default(2)\n    @align(16) b: vec2<i32>,");
+        assert_eq!(fields[1].name, "b");
+        assert_eq!(fields[1].offset, 16);
+        assert_eq!(fields[1].scalar, ParamScalar::Sint);
+        assert_eq!(fields[1].default, [2.0; 4]);
+        assert_eq!(fields[0].default, [0.0; 4]);
+    }
+
+    #[test]
+    fn rejects_annotations_it_cant_use() {
+        for (members, problem) in [
+            (
+                "    // @param range(0, 1\n    a: f32,",
+                "`params.a`: `range(` isn't closed",
+            ),
+            (
+                "    // @color range(0, 1)\n    a: vec3<f32>,",
+                "`params.a`: only `@param` takes a `range`",
+            ),
+            (
+                "    // @param default(1, 2)\n    a: vec3<f32>,",
+                "`params.a`: `default` needs 1 or 3 values",
+            ),
+        ] {
+            assert_eq!(
+                reflect(members),
+                Err(vec![problem.to_string()]),
+                "{members}"
+            );
+        }
+    }
+}
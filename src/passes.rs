@@ -0,0 +1,541 @@
+//! Extra compute passes that run before the main shader every step, each writing its own
+//! texture, like Shadertoy's Buffer A to D.
+//!
+//! Shaders read the texture a pass wrote this step through a variable named after the pass,
+//! and the one it wrote the step before through `<name>_previous`. A pass runs after the passes
+//! it `reads`; `feedback` reads of the previous step don't constrain the order.
+
+use std::borrow::Cow;
+
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::asset::{AssetId, Assets, Handle, RenderAssetUsages};
+use bevy::image::{Image, TextureFormatPixelInfo};
+use bevy::log::error_once;
+use bevy::math::UVec2;
+use bevy::prelude::{Commands, IntoScheduleConfigs, Res, ResMut, Resource, World};
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{
+    self, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel,
+};
+use bevy::render::render_resource::{
+    BindGroupLayout, CachedComputePipelineId, CachedPipelineState, CommandEncoder, ComputePipeline,
+    ComputePipelineDescriptor, Extent3d, ImageSubresourceRange, Pipeline, PipelineCache, Shader,
+    TextureDimension, TextureUsages,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::texture::GpuImage;
+use bevy::render::{Render, RenderApp, RenderSet};
+use serde::{Deserialize, Serialize};
+
+use crate::ComputeShaderStepLabel;
+use crate::bindings::{
+    AllocatedBindings, BoundResources, ComputeShaderLayout, ShaderBinding, StepBindGroup,
+    has_dynamic_offset, layout_entries,
+};
+use crate::config::{PlaygroundConfig, check_binding_name};
+use crate::dispatch::ComputeShaderDispatch;
+use crate::format::StorageFormat;
+use crate::globals::{ComputeShaderGlobalsBuffer, GlobalsStepLabel};
+use crate::input::KeyboardTexture;
+use crate::params::ParamsBuffer;
+use crate::profiling::PassProfiler;
+use crate::shader_error::ShaderDiagnostic;
+use crate::simulation::{FrameSteps, SimulationControl, SimulationStepGraph, advance_simulation};
+use crate::textures::{InputTextureSamplers, InputTextures};
+
+/// One pass of the manifest in [`PlaygroundConfig::passes`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassConfig {
+    /// Name shaders bind the pass's texture by. Must be a WGSL identifier.
+    pub name: String,
+    /// Asset path of the pass's compute shader.
+    pub shader: String,
+    /// Entry point dispatched every frame.
+    #[serde(default = "default_entry_point")]
+    pub entry_point: String,
+    /// Format of the texture the pass writes.
+    #[serde(default)]
+    pub format: StorageFormat,
+    /// Size of the texture the pass writes. Defaults to the size of the state textures.
+    #[serde(default)]
+    pub size: Option<UVec2>,
+    /// Passes whose texture of the current frame this pass reads. They run first.
+    #[serde(default)]
+    pub reads: Vec<String>,
+    /// Passes whose texture of the previous frame this pass reads, itself included.
+    #[serde(default)]
+    pub feedback: Vec<String>,
+}
+
+fn default_entry_point() -> String {
+    "main".to_string()
+}
+
+/// Check the passes and order them so every pass runs after the passes it reads. Fails on
+/// unknown or duplicate names and on cycles of `reads`.
+pub fn pass_order(passes: &[PassConfig]) -> Result<Vec<usize>, String> {
+    let index = |name: &str| passes.iter().position(|pass| pass.name == name);
+    for (i, pass) in passes.iter().enumerate() {
+        let name = &pass.name;
+        check_binding_name(name)?;
+        if pass.size.is_some_and(|size| size.x == 0 || size.y == 0) {
+            return Err(format!("`{name}` must be at least 1x1"));
+        }
+        if index(name) != Some(i) {
+            return Err(format!("more than one pass is named `{name}`"));
+        }
+        if let Some(unknown) = pass
+            .reads
+            .iter()
+            .chain(&pass.feedback)
+            .find(|read| index(read).is_none())
+        {
+            return Err(format!("`{name}` reads `{unknown}`, which isn't a pass"));
+        }
+    }
+
+    // Repeatedly take the first pass whose reads have all been ordered.
+    let mut order = Vec::with_capacity(passes.len());
+    while order.len() < passes.len() {
+        let next = (0..passes.len()).find(|i| {
+            !order.contains(i)
+                && passes[*i]
+                    .reads
+                    .iter()
+                    .all(|read| order.contains(&index(read).unwrap()))
+        });
+        match next {
+            Some(next) => order.push(next),
+            None => {
+                let cycle: Vec<_> = (0..passes.len())
+                    .filter(|i| !order.contains(i))
+                    .map(|i| format!("`{}`", passes[i].name))
+                    .collect();
+                return Err(format!(
+                    "{} read each other in a cycle; read the previous frame with `feedback` \
+                     instead",
+                    cycle.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(order)
+}
+
+/// The two textures of a pass. Like the state textures they swap every step, so `previous`
+/// holds what the pass wrote the step before while it writes `current`. After a frame,
+/// `current` holds what its last step wrote.
+#[derive(Clone)]
+pub struct PassImage {
+    pub current: Handle<Image>,
+    pub previous: Handle<Image>,
+}
+
+/// The textures of every pass, indexed like [`PlaygroundConfig::passes`].
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct PassImages(pub Vec<PassImage>);
+
+impl PassImages {
+    /// The current and previous texture of every pass, once they're all on the GPU.
+    pub fn gpu_images<'a>(
+        &self,
+        gpu_images: &'a RenderAssets<GpuImage>,
+    ) -> Option<Vec<[&'a GpuImage; 2]>> {
+        self.0
+            .iter()
+            .map(|image| {
+                let current = gpu_images.get(&image.current)?;
+                let previous = gpu_images.get(&image.previous)?;
+                Some([current, previous])
+            })
+            .collect()
+    }
+}
+
+pub struct PassesPlugin;
+
+impl Plugin for PassesPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world().resource::<PlaygroundConfig>();
+        let order = pass_order(&config.passes).expect("passes are checked when loading");
+        let pass_count = config.passes.len();
+
+        app.init_resource::<PassImages>()
+            .add_plugins(ExtractResourcePlugin::<PassImages>::default())
+            .add_systems(Startup, setup_passes)
+            .add_systems(Update, swap_pass_images.after(advance_simulation));
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .insert_resource(PassPipelines(
+                (0..pass_count).map(|_| None).collect::<Vec<_>>(),
+            ))
+            .add_systems(
+                Render,
+                (
+                    (prepare_pass_pipelines, poll_pass_pipelines)
+                        .chain()
+                        .in_set(RenderSet::PrepareResources),
+                    prepare_pass_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                ),
+            );
+
+        // Every step runs the passes between its globals and the compute shader.
+        let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        let step_graph = render_graph.sub_graph_mut(SimulationStepGraph);
+        for &index in &order {
+            step_graph.add_node(PassLabel(index), PassNode(index));
+        }
+        for pair in order.windows(2) {
+            step_graph.add_node_edge(PassLabel(pair[0]), PassLabel(pair[1]));
+        }
+        if let (Some(&first), Some(&last)) = (order.first(), order.last()) {
+            step_graph.add_node_edge(GlobalsStepLabel, PassLabel(first));
+            step_graph.add_node_edge(PassLabel(last), ComputeShaderStepLabel);
+        }
+    }
+}
+
+fn setup_passes(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    config: Res<PlaygroundConfig>,
+) {
+    let passes = config
+        .passes
+        .iter()
+        .map(|pass| {
+            let size = pass.size.unwrap_or(config.size);
+            let format = pass.format.texture_format();
+            let mut image = Image::new_fill(
+                Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                &vec![0; format.pixel_size()],
+                format,
+                RenderAssetUsages::RENDER_WORLD,
+            );
+            image.texture_descriptor.usage = TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::STORAGE_BINDING
+                | TextureUsages::TEXTURE_BINDING;
+            PassImage {
+                current: images.add(image.clone()),
+                previous: images.add(image),
+            }
+        })
+        .collect();
+    commands.insert_resource(PassImages(passes));
+}
+
+/// Swap the textures of every pass like the state textures, when the frame runs an odd number
+/// of steps.
+pub fn swap_pass_images(mut images: ResMut<PassImages>, control: Res<SimulationControl>) {
+    if !control.swaps() {
+        return;
+    }
+    for image in &mut images.0 {
+        std::mem::swap(&mut image.current, &mut image.previous);
+    }
+}
+
+/// The pipeline of a pass for the latest reflected version of its shader, and everything
+/// needed to dispatch it.
+struct PassPipeline {
+    name: String,
+    /// The reflected copy of the shader `pipeline` compiles.
+    shader: AssetId<Shader>,
+    bindings: Vec<ShaderBinding>,
+    bind_group_layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+    /// `pipeline`, once it has compiled.
+    compiled: Option<ComputePipeline>,
+    error: Option<ShaderDiagnostic>,
+    allocated: AllocatedBindings,
+    dispatch: ComputeShaderDispatch,
+    /// The bind groups of the steps that read `previous`, and of those that read `current`.
+    bind_groups: Option<[StepBindGroup; 2]>,
+}
+
+/// The pipelines of every pass, indexed like [`PlaygroundConfig::passes`]. `None` until the
+/// pass's shader has been reflected.
+#[derive(Resource)]
+pub struct PassPipelines(Vec<Option<PassPipeline>>);
+
+impl PassPipelines {
+    /// Whether every pass has a compiled pipeline and bind groups.
+    pub fn ready(&self) -> bool {
+        self.0.iter().all(|pass| {
+            pass.as_ref()
+                .is_some_and(|pass| pass.compiled.is_some() && pass.bind_groups.is_some())
+        })
+    }
+
+    /// The first compile error of any pass.
+    pub fn error(&self) -> Option<ShaderDiagnostic> {
+        self.0.iter().flatten().find_map(|pass| pass.error.clone())
+    }
+
+    /// Zero the textures of every pass and the resources allocated for their shaders.
+    pub fn clear(
+        &self,
+        encoder: &mut CommandEncoder,
+        images: &PassImages,
+        gpu_images: &RenderAssets<GpuImage>,
+    ) {
+        for pass in self.0.iter().flatten() {
+            pass.allocated.clear(encoder);
+        }
+        let images = images.gpu_images(gpu_images).unwrap_or_default();
+        for image in images.iter().flatten() {
+            encoder.clear_texture(&image.texture, &ImageSubresourceRange::default());
+        }
+    }
+}
+
+/// Queue a new pipeline for every pass whose shader has been reflected again.
+fn prepare_pass_pipelines(
+    mut passes: ResMut<PassPipelines>,
+    layout: Res<ComputeShaderLayout>,
+    pipeline_cache: Res<PipelineCache>,
+    config: Res<PlaygroundConfig>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    for (index, (pass, reflected)) in passes.0.iter_mut().zip(&layout.passes).enumerate() {
+        let Some(shader) = &reflected.shader else {
+            continue;
+        };
+        if pass.as_ref().is_some_and(|pass| pass.shader == shader.id()) {
+            continue;
+        }
+
+        let pass_config = &config.passes[index];
+        let size = pass_config.size.unwrap_or(config.size);
+        let mut allocated = pass.take().map(|pass| pass.allocated).unwrap_or_default();
+        allocated.allocate(&reflected.bindings, size, &render_device);
+        let bind_group_layout =
+            render_device.create_bind_group_layout(None, &layout_entries(&reflected.bindings));
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some(Cow::Owned(pass_config.name.clone())),
+            layout: vec![bind_group_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: config.shader_defs(pass_config.format),
+            entry_point: Cow::Owned(pass_config.entry_point.clone()),
+            zero_initialize_workgroup_memory: false,
+        });
+        let mut dispatch = ComputeShaderDispatch::default();
+        let dynamic_offset = has_dynamic_offset(&reflected.bindings);
+        if let Err(err) = dispatch.prepare(
+            size.extend(1),
+            &config,
+            dynamic_offset,
+            &render_device,
+            &render_queue,
+        ) {
+            error_once!("Not dispatching pass `{}`: {err}", pass_config.name);
+        }
+        *pass = Some(PassPipeline {
+            name: pass_config.name.clone(),
+            shader: shader.id(),
+            bindings: reflected.bindings.clone(),
+            bind_group_layout,
+            pipeline,
+            compiled: None,
+            error: None,
+            allocated,
+            dispatch,
+            bind_groups: None,
+        });
+    }
+}
+
+fn poll_pass_pipelines(
+    mut passes: ResMut<PassPipelines>,
+    layout: Res<ComputeShaderLayout>,
+    pipeline_cache: Res<PipelineCache>,
+) {
+    for (pass, reflected) in passes.0.iter_mut().zip(&layout.passes) {
+        let Some(pass) = pass else {
+            continue;
+        };
+        let error = match pipeline_cache.get_compute_pipeline_state(pass.pipeline) {
+            CachedPipelineState::Ok(Pipeline::ComputePipeline(compiled)) => {
+                pass.compiled = Some(compiled.clone());
+                None
+            }
+            CachedPipelineState::Err(err) => ShaderDiagnostic::from_pipeline_error(err),
+            _ => None,
+        };
+        pass.error = reflected.error.clone().or(error);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn prepare_pass_bind_groups(
+    mut passes: ResMut<PassPipelines>,
+    images: Res<PassImages>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    globals_buffer: Res<ComputeShaderGlobalsBuffer>,
+    keyboard_texture: Res<KeyboardTexture>,
+    params: Res<ParamsBuffer>,
+    input_textures: Res<InputTextures>,
+    texture_samplers: Res<InputTextureSamplers>,
+    render_device: Res<RenderDevice>,
+) {
+    let (Some(pass_images), Some(textures), Some(keyboard), Some(globals)) = (
+        images.gpu_images(&gpu_images),
+        input_textures.views(&gpu_images),
+        gpu_images.get(&keyboard_texture.texture),
+        globals_buffer.uniform.binding(),
+    ) else {
+        return;
+    };
+
+    for (index, pass) in passes.0.iter_mut().enumerate() {
+        let Some(pass) = pass else {
+            continue;
+        };
+        let Some(dispatch) = pass.dispatch.offsets.binding() else {
+            pass.bind_groups = None;
+            continue;
+        };
+        pass.bind_groups = Some([false, true].map(|swapped| {
+            let pass_images: Vec<_> = pass_images
+                .iter()
+                .map(|&[current, previous]| match swapped {
+                    false => [current, previous],
+                    true => [previous, current],
+                })
+                .collect();
+            // The pass's own `input` and `output` are its previous and current texture.
+            let [current, previous] = pass_images[index];
+            let resources = BoundResources {
+                input: previous,
+                output: current,
+                globals: globals.clone(),
+                keyboard: &keyboard.texture_view,
+                dispatch: dispatch.clone(),
+                params: params.buffer.as_entire_binding(),
+                allocated: &pass.allocated,
+                passes: &pass_images,
+                textures: &textures,
+                texture_samplers: &texture_samplers.0,
+            };
+            resources.bind_group(&pass.bind_group_layout, &pass.bindings, &render_device)
+        }));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct PassLabel(usize);
+
+/// Runs one pass for the step being recorded, once it's ready.
+struct PassNode(usize);
+
+impl render_graph::Node for PassNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let Some(pass) = &world.resource::<PassPipelines>().0[self.0] else {
+            return Ok(());
+        };
+        let (Some(pipeline), Some(bind_groups)) = (&pass.compiled, &pass.bind_groups) else {
+            return Ok(());
+        };
+        let swapped = world.resource::<FrameSteps>().swapped();
+        let profiler = world.resource::<PassProfiler>();
+        profiler.time(
+            &pass.name,
+            render_context.command_encoder(),
+            |encoder, timestamp_writes| {
+                bind_groups[swapped as usize].record(
+                    encoder,
+                    pipeline,
+                    &pass.dispatch,
+                    has_dynamic_offset(&pass.bindings),
+                    timestamp_writes,
+                );
+            },
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass(name: &str, reads: &[&str], feedback: &[&str]) -> PassConfig {
+        PassConfig {
+            name: name.to_string(),
+            shader: format!("{name}.wgsl"),
+            entry_point: default_entry_point(),
+            format: StorageFormat::default(),
+            size: None,
+            reads: reads.iter().map(|read| read.to_string()).collect(),
+            feedback: feedback.iter().map(|read| read.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn chains_run_after_what_they_read() {
+        let passes = [
+            pass("c", &["b"], &[]),
+            pass("b", &["a"], &[]),
+            pass("a", &[], &[]),
+        ];
+        assert_eq!(pass_order(&passes), Ok(vec![2, 1, 0]));
+    }
+
+    #[test]
+    fn diamonds_run_the_shared_pass_once_first() {
+        let passes = [
+            pass("d", &["b", "c"], &[]),
+            pass("c", &["a"], &[]),
+            pass("b", &["a"], &[]),
+            pass("a", &[], &[]),
+        ];
+        let order = pass_order(&passes).unwrap();
+        let position = |i| order.iter().position(|&pass| pass == i).unwrap();
+        assert_eq!(order.len(), 4);
+        assert_eq!(position(3), 0);
+        assert!(position(1) < position(0) && position(2) < position(0));
+    }
+
+    #[test]
+    fn cycles_need_feedback() {
+        let cycle = [pass("a", &["b"], &[]), pass("b", &["a"], &[])];
+        let err = pass_order(&cycle).unwrap_err();
+        assert!(
+            err.starts_with("`a`, `b` read each other in a cycle"),
+            "{err}"
+        );
+
+        let feedback = [pass("a", &[], &["b"]), pass("b", &["a"], &[])];
+        assert_eq!(pass_order(&feedback), Ok(vec![0, 1]));
+    }
+
+    #[test]
+    fn reads_must_name_a_pass() {
+        let passes = [pass("a", &[], &[]), pass("b", &["a", "c"], &[])];
+        assert_eq!(
+            pass_order(&passes),
+            Err("`b` reads `c`, which isn't a pass".to_string())
+        );
+        let passes = [pass("a", &[], &["a", "c"])];
+        assert_eq!(
+            pass_order(&passes),
+            Err("`a` reads `c`, which isn't a pass".to_string())
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Named snapshots of the shader parameters of [`crate::params`].
+//!
+//! Presets are kept next to the shader, in `shader.presets.ron` for `shader.wgsl`, as a list of
+//! names with the values of each parameter by name:
+//!
+//! ```ron
+//! [
+//!     (name: "fine", values: {"brush_radius": [2.0], "brush_color": [1.0, 0.8, 0.2]}),
+//! ]
+//! ```
+//!
+//! The keys 1 to 9 crossfade to the preset at that place in the list over `preset_crossfade`
+//! seconds, and with Ctrl held save the current values there instead. The file is read again
+//! before every key press, so it can be edited by hand while the playground runs. Parameters a
+//! preset doesn't list keep their values.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use bevy::app::{App, Plugin, Update};
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::log::{error, info};
+use bevy::prelude::{FromWorld, IntoScheduleConfigs, Res, ResMut, Resource, World};
+use bevy::time::Time;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PlaygroundConfig;
+use crate::params::{ParamWidget, ParamsLayout, ShaderParams, ShowParams, sync_params};
+
+/// Keys that select the presets, in order.
+const PRESET_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// The values of some parameters, by name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub name: String,
+    /// The components of each parameter.
+    pub values: BTreeMap<String, Vec<f32>>,
+}
+
+impl Preset {
+    /// A preset of every parameter in `layout`.
+    pub fn capture(name: String, layout: &ParamsLayout, values: &[[f32; 4]]) -> Self {
+        let values = layout
+            .fields
+            .iter()
+            .zip(values)
+            .map(|(field, value)| (field.name.clone(), value[..field.components].to_vec()))
+            .collect();
+        Self { name, values }
+    }
+
+    /// Set the parameters the preset lists. Values with the wrong number of components, from
+    /// before a parameter changed type, are skipped.
+    pub fn apply(&self, layout: &ParamsLayout, values: &mut [[f32; 4]]) {
+        for (field, value) in layout.fields.iter().zip(values) {
+            if let Some(preset) = self.values.get(&field.name)
+                && preset.len() == field.components
+            {
+                value[..field.components].copy_from_slice(preset);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PresetError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Write(PathBuf, io::Error),
+    Unknown(PathBuf, String),
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresetError::Read(path, err) => write!(f, "failed to read {}: {err}", path.display()),
+            PresetError::Parse(path, err) => write!(f, "{}:{err}", path.display()),
+            PresetError::Write(path, err) => {
+                write!(f, "failed to write {}: {err}", path.display())
+            }
+            PresetError::Unknown(path, name) => {
+                write!(f, "{} has no preset named `{name}`", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PresetError {}
+
+/// File the presets of the configured shader are kept in.
+pub fn presets_path(config: &PlaygroundConfig) -> PathBuf {
+    config.shader_path().with_extension("presets.ron")
+}
+
+/// The presets in `path`, none if it doesn't exist yet.
+pub fn load_presets(path: &Path) -> Result<Vec<Preset>, PresetError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(PresetError::Read(path.into(), err)),
+    };
+    ron::from_str(&text).map_err(|err| PresetError::Parse(path.into(), err))
+}
+
+pub fn save_presets(path: &Path, presets: &[Preset]) -> Result<(), PresetError> {
+    let text = ron::ser::to_string_pretty(presets, ron::ser::PrettyConfig::default())
+        .map_err(|err| PresetError::Write(path.into(), io::Error::other(err)))?;
+    fs::write(path, text + "\n").map_err(|err| PresetError::Write(path.into(), err))
+}
+
+/// The preset named `name` in `path`.
+pub fn find_preset(path: &Path, name: &str) -> Result<Preset, PresetError> {
+    load_presets(path)?
+        .into_iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| PresetError::Unknown(path.into(), name.to_string()))
+}
+
+/// A blend from one set of values of `layout` to another.
+struct Crossfade {
+    layout: ParamsLayout,
+    from: Vec<[f32; 4]>,
+    to: Vec<[f32; 4]>,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl Crossfade {
+    /// The values `fraction` of the way from `from` to `to`. Toggles flip halfway.
+    fn blend(&self, fraction: f32) -> Vec<[f32; 4]> {
+        self.layout
+            .fields
+            .iter()
+            .zip(self.from.iter().zip(&self.to))
+            .map(|(field, (from, to))| match field.widget {
+                ParamWidget::Toggle => {
+                    if fraction < 0.5 {
+                        *from
+                    } else {
+                        *to
+                    }
+                }
+                _ => std::array::from_fn(|i| from[i] + (to[i] - from[i]) * fraction),
+            })
+            .collect()
+    }
+}
+
+#[derive(Resource)]
+pub struct ParamPresets {
+    path: PathBuf,
+    /// The preset from the config, until the parameters it applies to are known.
+    start: Option<String>,
+    fade: Option<Crossfade>,
+}
+
+impl FromWorld for ParamPresets {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.resource::<PlaygroundConfig>();
+        Self {
+            path: presets_path(config),
+            start: config.preset.clone(),
+            fade: None,
+        }
+    }
+}
+
+pub struct PresetsPlugin;
+
+impl Plugin for PresetsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParamPresets>().add_systems(
+            Update,
+            (apply_start_preset, preset_hotkeys, crossfade)
+                .chain()
+                .after(sync_params)
+                .before(ShowParams),
+        );
+    }
+}
+
+fn apply_start_preset(mut presets: ResMut<ParamPresets>, mut params: ResMut<ShaderParams>) {
+    let (Some(layout), Some(_)) = (params.layout.clone(), &presets.start) else {
+        return;
+    };
+    let name = presets.start.take().unwrap();
+    match find_preset(&presets.path, &name) {
+        Ok(preset) => preset.apply(&layout, &mut params.values),
+        Err(err) => error!("{err}"),
+    }
+}
+
+fn preset_hotkeys(
+    keys: Res<ButtonInput<KeyCode>>,
+    config: Res<PlaygroundConfig>,
+    mut presets: ResMut<ParamPresets>,
+    mut params: ResMut<ShaderParams>,
+) {
+    let Some(slot) = PRESET_KEYS.iter().position(|key| keys.just_pressed(*key)) else {
+        return;
+    };
+    let Some(layout) = params.layout.clone() else {
+        return;
+    };
+    let mut list = match load_presets(&presets.path) {
+        Ok(list) => list,
+        Err(err) => {
+            error!("{err}");
+            return;
+        }
+    };
+
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        // Saving past the end of the list appends.
+        let slot = slot.min(list.len());
+        let name = list.get(slot).map_or_else(
+            || format!("preset {}", slot + 1),
+            |preset| preset.name.clone(),
+        );
+        let preset = Preset::capture(name, &layout, &params.values);
+        match list.get_mut(slot) {
+            Some(existing) => *existing = preset,
+            None => list.push(preset),
+        }
+        match save_presets(&presets.path, &list) {
+            Ok(()) => info!(
+                "Saved preset {} `{}` to {}",
+                slot + 1,
+                list[slot].name,
+                presets.path.display()
+            ),
+            Err(err) => error!("{err}"),
+        }
+        return;
+    }
+
+    let Some(preset) = list.get(slot) else {
+        info!(
+            "There's no preset {} in {}",
+            slot + 1,
+            presets.path.display()
+        );
+        return;
+    };
+    info!("Preset {} `{}`", slot + 1, preset.name);
+    let mut to = params.values.clone();
+    preset.apply(&layout, &mut to);
+    if config.preset_crossfade > 0.0 {
+        presets.fade = Some(Crossfade {
+            layout,
+            from: params.values.clone(),
+            to,
+            elapsed: 0.0,
+            duration: config.preset_crossfade,
+        });
+    } else {
+        presets.fade = None;
+        params.values = to;
+    }
+}
+
+pub fn crossfade(
+    time: Res<Time>,
+    mut presets: ResMut<ParamPresets>,
+    mut params: ResMut<ShaderParams>,
+) {
+    let Some(fade) = &mut presets.fade else {
+        return;
+    };
+    // A reload that changed the parameters ends the fade.
+    if params.layout.as_ref() != Some(&fade.layout) {
+        presets.fade = None;
+        return;
+    }
+    fade.elapsed += time.delta_secs();
+    let fraction = (fade.elapsed / fade.duration).min(1.0);
+    params.values = fade.blend(fraction);
+    if fraction >= 1.0 {
+        presets.fade = None;
+    }
+}
@@ -0,0 +1,509 @@
+//! How long each compute pass takes, measured with GPU timestamp queries when the device
+//! supports them and by how long the pass takes to encode otherwise.
+//!
+//! F4 shows the timings of the last frames, and F5 writes them to a CSV file in the capture
+//! directory.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, io, mem};
+
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::color::Color;
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::log::{error, info};
+use bevy::prelude::{
+    BackgroundColor, Commands, Component, DetectChanges, DetectChangesMut, FromWorld,
+    IntoScheduleConfigs, Node, PositionType, Query, Res, ResMut, Resource, Text, TextColor,
+    TextFont, UiRect, Val, Visibility, With, World, default,
+};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, MapMode, WgpuFeatures,
+};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::{Render, RenderApp, RenderSet};
+use wgpu::{
+    ComputePassTimestampWrites, QUERY_SET_MAX_QUERIES, QuerySet, QuerySetDescriptor, QueryType,
+};
+
+use crate::config::PlaygroundConfig;
+use crate::globals::ComputeShaderGlobals;
+use crate::simulation::SimulationControl;
+
+/// Key that shows or hides the timings.
+const TIMINGS_KEY: KeyCode = KeyCode::F4;
+/// Key that saves the timings as CSV.
+const EXPORT_KEY: KeyCode = KeyCode::F5;
+
+/// Frames the average and 95th percentile are taken over.
+const HISTORY_FRAMES: usize = 120;
+
+/// Where a timing comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingSource {
+    /// GPU timestamps written at the start and end of the pass.
+    Gpu,
+    /// Time spent encoding the pass, for devices without timestamp queries.
+    Cpu,
+}
+
+impl TimingSource {
+    fn name(self) -> &'static str {
+        match self {
+            TimingSource::Gpu => "gpu",
+            TimingSource::Cpu => "cpu",
+        }
+    }
+}
+
+/// The time each pass took in one frame, summed over the steps of the frame.
+#[derive(Clone, Debug)]
+struct FrameTimings {
+    source: TimingSource,
+    passes: Vec<(String, Duration)>,
+}
+
+/// Frame timings handed from the render world to the main world.
+#[derive(Resource, Clone, Default)]
+struct CompletedTimings(Arc<Mutex<Vec<FrameTimings>>>);
+
+/// The timings of one pass over the last [`HISTORY_FRAMES`] frames that ran it.
+#[derive(Clone, Debug)]
+pub struct PassTiming {
+    pub name: String,
+    pub source: TimingSource,
+    history: VecDeque<Duration>,
+}
+
+impl PassTiming {
+    pub fn last(&self) -> Duration {
+        self.history.back().copied().unwrap_or_default()
+    }
+
+    pub fn average(&self) -> Duration {
+        let total: Duration = self.history.iter().sum();
+        total / self.history.len().max(1) as u32
+    }
+
+    /// The time 95% of the frames took at most.
+    pub fn p95(&self) -> Duration {
+        let mut sorted: Vec<_> = self.history.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (sorted.len() * 95).div_ceil(100);
+        sorted
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn samples(&self) -> usize {
+        self.history.len()
+    }
+}
+
+/// Timings of every pass, in the order the passes ran in their latest frame.
+#[derive(Resource, Default)]
+pub struct PassTimings {
+    pub passes: Vec<PassTiming>,
+}
+
+impl PassTimings {
+    fn add(&mut self, frame: FrameTimings) {
+        for (name, duration) in frame.passes {
+            let position = self.passes.iter().position(|pass| pass.name == name);
+            let pass = match position {
+                Some(position) => &mut self.passes[position],
+                None => {
+                    self.passes.push(PassTiming {
+                        name,
+                        source: frame.source,
+                        history: VecDeque::new(),
+                    });
+                    self.passes.last_mut().unwrap()
+                }
+            };
+            pass.source = frame.source;
+            if pass.history.len() == HISTORY_FRAMES {
+                pass.history.pop_front();
+            }
+            pass.history.push_back(duration);
+        }
+    }
+
+    /// The timings as CSV, one pass per row, in milliseconds.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "pass,source,last_ms,average_ms,p95_ms,frames\n".to_string();
+        for pass in &self.passes {
+            csv += &format!(
+                "{},{},{},{},{},{}\n",
+                pass.name,
+                pass.source.name(),
+                milliseconds(pass.last()),
+                milliseconds(pass.average()),
+                milliseconds(pass.p95()),
+                pass.samples()
+            );
+        }
+        csv
+    }
+
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_csv())
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub struct ProfilingPlugin;
+
+impl Plugin for ProfilingPlugin {
+    fn build(&self, app: &mut App) {
+        let completed = CompletedTimings::default();
+        app.insert_resource(completed.clone())
+            .init_resource::<PassTimings>()
+            .init_resource::<TimingsOverlayVisible>()
+            .add_systems(Startup, setup_overlay)
+            .add_systems(
+                Update,
+                (collect_timings, timings_hotkeys, update_overlay).chain(),
+            );
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.insert_resource(completed).add_systems(
+            Render,
+            (
+                prepare_profiler.in_set(RenderSet::PrepareResources),
+                map_timestamps.in_set(RenderSet::Cleanup),
+            ),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp).init_resource::<PassProfiler>();
+    }
+}
+
+/// The timestamp queries of the passes of a frame.
+struct TimestampQueries {
+    query_set: QuerySet,
+    capacity: u32,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+/// Timestamps copied into a buffer to be mapped after the frame has been submitted.
+struct PendingTimestamps {
+    passes: Vec<String>,
+    buffer: Buffer,
+}
+
+/// The passes recorded so far this frame.
+#[derive(Default)]
+struct ProfiledFrame {
+    passes: Vec<String>,
+    /// Encoding times when there are no timestamp queries.
+    encoded: Vec<Duration>,
+    /// Whether more passes ran than there are queries, so the frame can't be timed.
+    overflowed: bool,
+}
+
+/// Times the compute passes as they're recorded. The render graph nodes only get shared access
+/// to it, so the frame being recorded is behind a lock.
+#[derive(Resource)]
+pub struct PassProfiler {
+    /// `None` when the device doesn't support timestamp queries.
+    queries: Option<TimestampQueries>,
+    frame: Mutex<ProfiledFrame>,
+    pending: Mutex<Vec<PendingTimestamps>>,
+    completed: CompletedTimings,
+}
+
+impl FromWorld for PassProfiler {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let queries = render_device
+            .features()
+            .contains(WgpuFeatures::TIMESTAMP_QUERY)
+            .then(|| TimestampQueries {
+                query_set: create_query_set(render_device, 0),
+                capacity: 0,
+                period: world.resource::<RenderQueue>().get_timestamp_period(),
+            });
+        if queries.is_some() {
+            info!("Timing compute passes with GPU timestamps");
+        } else {
+            info!(
+                "The device doesn't support timestamp queries, timing compute passes by how long \
+                 they take to encode"
+            );
+        }
+        Self {
+            queries,
+            frame: Mutex::default(),
+            pending: Mutex::default(),
+            completed: world.resource::<CompletedTimings>().clone(),
+        }
+    }
+}
+
+fn create_query_set(render_device: &RenderDevice, count: u32) -> QuerySet {
+    render_device
+        .wgpu_device()
+        .create_query_set(&QuerySetDescriptor {
+            label: Some("pass_timestamps"),
+            ty: QueryType::Timestamp,
+            count: count.max(2),
+        })
+}
+
+impl PassProfiler {
+    /// Record a compute pass named `name` with `record`, which must pass the timestamp writes it
+    /// gets on to the pass. Without timestamp queries the time `record` takes is used instead.
+    pub fn time(
+        &self,
+        name: &str,
+        encoder: &mut CommandEncoder,
+        record: impl FnOnce(&mut CommandEncoder, Option<ComputePassTimestampWrites>),
+    ) {
+        let mut frame = self.frame.lock().unwrap();
+        let index = frame.passes.len() as u32;
+        frame.passes.push(name.to_string());
+        match &self.queries {
+            Some(queries) => {
+                let writes =
+                    (2 * index + 1 < queries.capacity).then(|| ComputePassTimestampWrites {
+                        query_set: &queries.query_set,
+                        beginning_of_pass_write_index: Some(2 * index),
+                        end_of_pass_write_index: Some(2 * index + 1),
+                    });
+                frame.overflowed |= writes.is_none();
+                drop(frame);
+                record(encoder, writes);
+            }
+            None => {
+                drop(frame);
+                let start = Instant::now();
+                record(encoder, None);
+                let encoded = start.elapsed();
+                self.frame.lock().unwrap().encoded.push(encoded);
+            }
+        }
+    }
+
+    /// Copy the timestamps of the passes recorded this frame into a buffer to be read once the
+    /// frame has been submitted. Encoding times are published right away.
+    pub fn finish(&self, encoder: &mut CommandEncoder, render_device: &RenderDevice) {
+        let frame = mem::take(&mut *self.frame.lock().unwrap());
+        if frame.passes.is_empty() {
+            return;
+        }
+        let Some(queries) = &self.queries else {
+            let passes = frame.passes.into_iter().zip(frame.encoded).collect();
+            self.completed.0.lock().unwrap().push(FrameTimings {
+                source: TimingSource::Cpu,
+                passes,
+            });
+            return;
+        };
+        if frame.overflowed {
+            return;
+        }
+
+        let count = 2 * frame.passes.len() as u32;
+        let size = count as u64 * size_of::<u64>() as u64;
+        let resolve = render_device.create_buffer(&BufferDescriptor {
+            label: Some("pass_timestamps_resolve"),
+            size,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("pass_timestamps_readback"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.resolve_query_set(&queries.query_set, 0..count, &resolve, 0);
+        encoder.copy_buffer_to_buffer(&resolve, 0, &buffer, 0, size);
+        self.pending.lock().unwrap().push(PendingTimestamps {
+            passes: frame.passes,
+            buffer,
+        });
+    }
+}
+
+/// Start a new frame, with enough queries for the passes it runs.
+fn prepare_profiler(
+    mut profiler: ResMut<PassProfiler>,
+    control: Res<SimulationControl>,
+    config: Res<PlaygroundConfig>,
+    render_device: Res<RenderDevice>,
+) {
+    let profiler = &mut *profiler;
+    *profiler.frame.get_mut().unwrap() = ProfiledFrame::default();
+    let Some(queries) = &mut profiler.queries else {
+        return;
+    };
+    let passes = control.steps().max(1) * (config.passes.len() as u32 + 1);
+    let count = (2 * passes).min(QUERY_SET_MAX_QUERIES);
+    if count > queries.capacity {
+        queries.query_set = create_query_set(&render_device, count);
+        queries.capacity = count;
+    }
+}
+
+/// Map the timestamps once the frame has been submitted, and publish the time between the
+/// start and end of each pass.
+fn map_timestamps(profiler: Res<PassProfiler>) {
+    let Some(queries) = &profiler.queries else {
+        return;
+    };
+    let period = queries.period as f64;
+    for pending in mem::take(&mut *profiler.pending.lock().unwrap()) {
+        let completed = profiler.completed.clone();
+        let buffer = pending.buffer.clone();
+        pending
+            .buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                if let Err(err) = result {
+                    error!("Failed to map timestamp buffer: {err}");
+                    return;
+                }
+                let timestamps: Vec<u64> = {
+                    let mapped = buffer.slice(..).get_mapped_range();
+                    mapped
+                        .chunks_exact(size_of::<u64>())
+                        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                        .collect()
+                };
+                buffer.unmap();
+
+                let mut passes: Vec<(String, Duration)> = Vec::new();
+                for (name, pair) in pending.passes.into_iter().zip(timestamps.chunks_exact(2)) {
+                    let ticks = pair[1].saturating_sub(pair[0]);
+                    let duration = Duration::from_nanos((ticks as f64 * period) as u64);
+                    // Passes that run every step are summed over the frame.
+                    match passes.iter_mut().find(|(other, _)| *other == name) {
+                        Some((_, total)) => *total += duration,
+                        None => passes.push((name, duration)),
+                    }
+                }
+                completed.0.lock().unwrap().push(FrameTimings {
+                    source: TimingSource::Gpu,
+                    passes,
+                });
+            });
+    }
+}
+
+fn collect_timings(completed: Res<CompletedTimings>, mut timings: ResMut<PassTimings>) {
+    for frame in mem::take(&mut *completed.0.lock().unwrap()) {
+        timings.add(frame);
+    }
+}
+
+#[derive(Resource, Default)]
+struct TimingsOverlayVisible(bool);
+
+#[derive(Component)]
+struct TimingsOverlay;
+
+fn setup_overlay(mut commands: Commands) {
+    commands.spawn((
+        TimingsOverlay,
+        Text::default(),
+        TextFont {
+            font_size: 12.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.0),
+            right: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+}
+
+fn timings_hotkeys(
+    keys: Res<ButtonInput<KeyCode>>,
+    timings: Res<PassTimings>,
+    config: Res<PlaygroundConfig>,
+    globals: Res<ComputeShaderGlobals>,
+    mut visible: ResMut<TimingsOverlayVisible>,
+) {
+    if keys.just_pressed(TIMINGS_KEY) {
+        visible.0 = !visible.0;
+    }
+    if keys.just_pressed(EXPORT_KEY) {
+        let path = config
+            .capture_dir
+            .join(format!("timings-{:06}.csv", globals.frame));
+        match timings.save_csv(&path) {
+            Ok(()) => info!("Saved {}", path.display()),
+            Err(err) => error!("Failed to save {}: {err}", path.display()),
+        }
+    }
+}
+
+fn update_overlay(
+    timings: Res<PassTimings>,
+    visible: Res<TimingsOverlayVisible>,
+    mut overlay: Query<(&mut Text, &mut Visibility), With<TimingsOverlay>>,
+) {
+    for (mut text, mut visibility) in &mut overlay {
+        visibility.set_if_neq(if visible.0 {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        });
+        if !visible.0 || !timings.is_changed() {
+            continue;
+        }
+        text.0 = describe_timings(&timings);
+    }
+}
+
+/// A table of the timings in milliseconds.
+fn describe_timings(timings: &PassTimings) -> String {
+    let Some(first) = timings.passes.first() else {
+        return "No passes timed yet".to_string();
+    };
+    let width = timings
+        .passes
+        .iter()
+        .map(|pass| pass.name.len())
+        .max()
+        .unwrap_or(0);
+    let mut text = match first.source {
+        TimingSource::Gpu => "GPU time (ms)".to_string(),
+        TimingSource::Cpu => "Encoding time (ms), no timestamp queries".to_string(),
+    };
+    text += &format!(
+        "\n{:width$} {:>8} {:>8} {:>8}",
+        "pass", "last", "average", "p95"
+    );
+    for pass in &timings.passes {
+        text += &format!(
+            "\n{:width$} {:>8.3} {:>8.3} {:>8.3}",
+            pass.name,
+            milliseconds(pass.last()),
+            milliseconds(pass.average()),
+            milliseconds(pass.p95())
+        );
+    }
+    text
+}
@@ -0,0 +1,254 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use bevy::app::{App, Plugin};
+use bevy::asset::Handle;
+use bevy::image::{Image, TextureFormatPixelInfo};
+use bevy::math::{URect, UVec2};
+use bevy::prelude::{Event, IntoScheduleConfigs, Res, ResMut, Resource, World};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{
+    self, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel,
+};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, Extent3d, MapMode, Origin3d, TexelCopyBufferInfo,
+    TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect, TextureFormat,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::texture::GpuImage;
+use bevy::render::{ExtractSchedule, MainWorld, Render, RenderApp, RenderSet};
+
+use crate::ComputeShaderLabel;
+
+/// Identifies a readback between [`TextureReadbacks::request`] and its
+/// [`TextureReadbackComplete`] event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReadbackId(u64);
+
+/// Copy (part of) a texture back to the CPU after this frame's compute passes.
+#[derive(Clone, Debug)]
+pub struct ReadbackRequest {
+    pub image: Handle<Image>,
+    /// Texels to read, clamped to the texture. `None` reads the whole texture.
+    pub region: Option<URect>,
+}
+
+impl ReadbackRequest {
+    pub fn new(image: Handle<Image>) -> Self {
+        Self {
+            image,
+            region: None,
+        }
+    }
+}
+
+/// Readbacks requested by the main world, handed to the render world during extraction.
+#[derive(Resource, Default)]
+pub struct TextureReadbacks {
+    next_id: u64,
+    requested: Vec<(ReadbackId, ReadbackRequest)>,
+}
+
+impl TextureReadbacks {
+    pub fn request(&mut self, request: ReadbackRequest) -> ReadbackId {
+        let id = ReadbackId(self.next_id);
+        self.next_id += 1;
+        self.requested.push((id, request));
+        id
+    }
+}
+
+/// Texels copied back from the GPU, with the row padding of the copy removed.
+#[derive(Clone, Debug)]
+pub struct TextureReadback {
+    pub size: UVec2,
+    pub format: TextureFormat,
+    /// Tightly packed rows of `size.x * format.pixel_size()` bytes.
+    pub data: Vec<u8>,
+}
+
+/// Sent in the main world a few frames after a readback was requested.
+#[derive(Event, Clone, Debug)]
+pub struct TextureReadbackComplete {
+    pub id: ReadbackId,
+    pub readback: TextureReadback,
+}
+
+pub struct ReadbackPlugin;
+
+impl Plugin for ReadbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TextureReadbacks>()
+            .add_event::<TextureReadbackComplete>();
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<RenderReadbacks>()
+            .add_systems(ExtractSchedule, sync_readbacks)
+            .add_systems(
+                Render,
+                (
+                    prepare_readbacks.in_set(RenderSet::PrepareResources),
+                    map_readbacks.in_set(RenderSet::Cleanup),
+                ),
+            );
+
+        let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        render_graph.add_node(ReadbackLabel, ReadbackNode);
+        render_graph.add_node_edge(ComputeShaderLabel, ReadbackLabel);
+        render_graph.add_node_edge(ReadbackLabel, bevy::render::graph::CameraDriverLabel);
+    }
+}
+
+struct PendingCopy {
+    id: ReadbackId,
+    texture: Texture,
+    origin: UVec2,
+    size: UVec2,
+    format: TextureFormat,
+    padded_bytes_per_row: u32,
+    buffer: Buffer,
+}
+
+#[derive(Resource, Default)]
+struct RenderReadbacks {
+    queued: Vec<(ReadbackId, ReadbackRequest)>,
+    copies: Vec<PendingCopy>,
+    /// Filled by the buffer mapping callbacks, drained into the main world on extraction.
+    completed: Arc<Mutex<Vec<TextureReadbackComplete>>>,
+}
+
+fn sync_readbacks(mut main_world: ResMut<MainWorld>, mut readbacks: ResMut<RenderReadbacks>) {
+    let requested = mem::take(&mut main_world.resource_mut::<TextureReadbacks>().requested);
+    readbacks.queued.extend(requested);
+
+    let completed = mem::take(&mut *readbacks.completed.lock().unwrap());
+    for event in completed {
+        main_world.send_event(event);
+    }
+}
+
+fn prepare_readbacks(
+    mut readbacks: ResMut<RenderReadbacks>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+) {
+    let readbacks = &mut *readbacks;
+    // Requests for textures that aren't on the GPU yet wait for a later frame.
+    readbacks.queued.retain(|(id, request)| {
+        let Some(gpu_image) = gpu_images.get(&request.image) else {
+            return true;
+        };
+
+        let bounds = URect::new(0, 0, gpu_image.size.width, gpu_image.size.height);
+        let region = request
+            .region
+            .map_or(bounds, |region| region.intersect(bounds));
+        if region.is_empty() {
+            return false;
+        }
+
+        let size = region.size();
+        let padded_bytes_per_row = RenderDevice::align_copy_bytes_per_row(
+            size.x as usize * gpu_image.texture_format.pixel_size(),
+        ) as u32;
+        let buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("texture_readback"),
+            size: padded_bytes_per_row as u64 * size.y as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        readbacks.copies.push(PendingCopy {
+            id: *id,
+            texture: gpu_image.texture.clone(),
+            origin: region.min,
+            size,
+            format: gpu_image.texture_format,
+            padded_bytes_per_row,
+            buffer,
+        });
+        false
+    });
+}
+
+/// Map the buffers once the copies have been submitted. Each callback strips the row padding
+/// and hands the texels to [`sync_readbacks`].
+fn map_readbacks(mut readbacks: ResMut<RenderReadbacks>) {
+    for copy in mem::take(&mut readbacks.copies) {
+        let completed = readbacks.completed.clone();
+        let buffer = copy.buffer.clone();
+        copy.buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                if let Err(err) = result {
+                    bevy::log::error!("Failed to map readback buffer: {err}");
+                    return;
+                }
+
+                let row_bytes = copy.size.x as usize * copy.format.pixel_size();
+                let data = {
+                    let mapped = buffer.slice(..).get_mapped_range();
+                    mapped
+                        .chunks_exact(copy.padded_bytes_per_row as usize)
+                        .flat_map(|row| &row[..row_bytes])
+                        .copied()
+                        .collect()
+                };
+                buffer.unmap();
+
+                completed.lock().unwrap().push(TextureReadbackComplete {
+                    id: copy.id,
+                    readback: TextureReadback {
+                        size: copy.size,
+                        format: copy.format,
+                        data,
+                    },
+                });
+            });
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct ReadbackLabel;
+
+/// Copies the requested textures into their readback buffers after the compute passes.
+struct ReadbackNode;
+
+impl render_graph::Node for ReadbackNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let readbacks = world.resource::<RenderReadbacks>();
+        for copy in &readbacks.copies {
+            render_context.command_encoder().copy_texture_to_buffer(
+                TexelCopyTextureInfo {
+                    texture: &copy.texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: copy.origin.x,
+                        y: copy.origin.y,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                TexelCopyBufferInfo {
+                    buffer: &copy.buffer,
+                    layout: TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(copy.padded_bytes_per_row),
+                        rows_per_image: None,
+                    },
+                },
+                Extent3d {
+                    width: copy.size.x,
+                    height: copy.size.y,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        Ok(())
+    }
+}
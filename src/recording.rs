@@ -0,0 +1,460 @@
+//! Recording the compute output as a numbered PNG sequence, a GIF or an APNG.
+//!
+//! F9 starts and stops a recording into the capture directory. Every `record_every`th frame that
+//! runs `update` is read back, without waiting for the GPU: the readbacks arrive a few frames
+//! later and are saved then. An animation's file is opened when the recording starts and its
+//! frames are encoded on a thread of their own in order as they arrive, each one once the next
+//! has arrived to say how long it shows. Recordings stop by themselves after `record_max_frames`
+//! frames.
+//!
+//! With `record_fps` set, every frame of a recording advances the simulation by exactly
+//! `1 / record_fps` seconds, however long it takes to render, so the recording plays back at
+//! the speed the simulation runs at.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use bevy::app::{App, Plugin, Update};
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::log::{error, info};
+use bevy::math::UVec2;
+use bevy::prelude::{EventReader, IntoScheduleConfigs, Res, ResMut, Resource};
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{IoTaskPool, Task, block_on};
+use bevy::time::TimeUpdateStrategy;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::{CaptureError, save_png};
+use crate::config::PlaygroundConfig;
+use crate::export::ExportFormat;
+use crate::format::to_rgba8;
+use crate::gif::GifEncoder;
+use crate::globals::{ComputeShaderGlobals, update_globals};
+use crate::headless::frame_file_name;
+use crate::readback::{ReadbackId, ReadbackRequest, TextureReadbackComplete, TextureReadbacks};
+use crate::{ComputeShaderImage, swap_textures};
+
+/// Key that starts and stops recording.
+const RECORD_KEY: KeyCode = KeyCode::F9;
+
+/// What a recording is saved as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordFormat {
+    /// A directory of numbered PNG files, written as the frames arrive.
+    #[default]
+    Png,
+    /// An animated GIF with a fixed palette and no alpha.
+    Gif,
+    /// An animated PNG.
+    Apng,
+}
+
+/// How long the last frame of an animation shows when it's the only one.
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// Where the frame count of an APNG's acTL chunk starts: after the signature, the whole IHDR
+/// chunk and acTL's length and type.
+const ACTL_NUM_FRAMES_OFFSET: u64 = 8 + 25 + 8;
+
+/// A frame of an animation, as RGBA8.
+struct AnimationFrame {
+    rgba: Vec<u8>,
+    /// `globals.time` when the frame was rendered.
+    time: f32,
+}
+
+/// The file an animation is encoded into.
+enum AnimationEncoder {
+    Gif(GifEncoder<BufWriter<File>>),
+    Apng {
+        writer: png::Writer<BufWriter<File>>,
+        /// Another handle to the file, to write the frame count once it's known.
+        file: File,
+    },
+}
+
+impl AnimationEncoder {
+    fn create(path: &Path, format: RecordFormat, size: UVec2) -> Result<Self, CaptureError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(CaptureError::Io)?;
+        }
+        let file = File::create(path).map_err(CaptureError::Io)?;
+        match format {
+            RecordFormat::Png => unreachable!("PNG sequences are saved frame by frame"),
+            RecordFormat::Gif => {
+                let encoder =
+                    GifEncoder::new(BufWriter::new(file), size).map_err(CaptureError::Io)?;
+                Ok(AnimationEncoder::Gif(encoder))
+            }
+            RecordFormat::Apng => {
+                let patch = file.try_clone().map_err(CaptureError::Io)?;
+                let mut encoder = png::Encoder::new(BufWriter::new(file), size.x, size.y);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                // The frame count isn't known until the recording stops, so it's written then.
+                // 0 plays forever.
+                encoder
+                    .set_animated(u32::MAX, 0)
+                    .map_err(CaptureError::Png)?;
+                let writer = encoder.write_header().map_err(CaptureError::Png)?;
+                Ok(AnimationEncoder::Apng {
+                    writer,
+                    file: patch,
+                })
+            }
+        }
+    }
+
+    fn write_frame(&mut self, rgba: &[u8], delay: Duration) -> Result<(), CaptureError> {
+        match self {
+            AnimationEncoder::Gif(encoder) => {
+                // GIF delays are in hundredths of a second.
+                let delay = (delay.as_secs_f64() * 100.0)
+                    .round()
+                    .clamp(1.0, u16::MAX as f64) as u16;
+                encoder.write_frame(rgba, delay).map_err(CaptureError::Io)
+            }
+            AnimationEncoder::Apng { writer, .. } => {
+                let millis = delay.as_millis().clamp(1, u16::MAX as u128) as u16;
+                writer
+                    .set_frame_delay(millis, 1000)
+                    .map_err(CaptureError::Png)?;
+                writer.write_image_data(rgba).map_err(CaptureError::Png)
+            }
+        }
+    }
+
+    fn finish(self, frames: u32) -> Result<(), CaptureError> {
+        match self {
+            AnimationEncoder::Gif(encoder) => {
+                encoder.finish().map_err(CaptureError::Io)?;
+                Ok(())
+            }
+            AnimationEncoder::Apng { writer, mut file } => {
+                writer.finish().map_err(CaptureError::Png)?;
+                let mut actl = *b"acTL\0\0\0\0\0\0\0\0";
+                actl[4..8].copy_from_slice(&frames.to_be_bytes());
+                let crc = crc32fast::hash(&actl);
+                file.seek(SeekFrom::Start(ACTL_NUM_FRAMES_OFFSET))
+                    .map_err(CaptureError::Io)?;
+                file.write_all(&actl[4..]).map_err(CaptureError::Io)?;
+                file.write_all(&crc.to_be_bytes()).map_err(CaptureError::Io)
+            }
+        }
+    }
+}
+
+/// An animation being encoded on its own thread, which takes each frame with how long it
+/// shows, and finishes the file once the sender is dropped.
+struct AnimationWriter {
+    frames: Sender<(Vec<u8>, Duration)>,
+    thread: JoinHandle<Result<(), CaptureError>>,
+}
+
+impl AnimationWriter {
+    fn start(path: &Path, format: RecordFormat, size: UVec2) -> Result<Self, CaptureError> {
+        let mut encoder = AnimationEncoder::create(path, format, size)?;
+        let path = path.to_owned();
+        let (frames, received) = mpsc::channel::<(Vec<u8>, Duration)>();
+        let thread = thread::spawn(move || {
+            let mut written = 0;
+            for (rgba, delay) in received {
+                encoder.write_frame(&rgba, delay)?;
+                written += 1;
+            }
+            if written == 0 {
+                drop(encoder);
+                return fs::remove_file(&path).map_err(CaptureError::Io);
+            }
+            encoder.finish(written)
+        });
+        Ok(Self { frames, thread })
+    }
+}
+
+struct Recording {
+    path: PathBuf,
+    format: RecordFormat,
+    /// `globals.frame` of the last frame seen.
+    last_frame: Option<u32>,
+    /// Frames that ran `update` since the recording started, counting the one that was
+    /// showing.
+    seen: u32,
+    /// Frames read back so far.
+    requested: u32,
+    /// Readbacks in flight, with the frame's number in the recording and `globals.time`.
+    pending: HashMap<ReadbackId, (u32, f32)>,
+    size: UVec2,
+    /// Where an animation's frames go, or `None` for a PNG sequence or once it failed.
+    animation: Option<AnimationWriter>,
+    /// The number of the next frame of an animation to arrive in order.
+    next: u32,
+    /// The last frame in order, which is written once the next one says how long it shows.
+    held: Option<AnimationFrame>,
+    /// How long the last written frame showed.
+    last_delay: Duration,
+    /// Frames that arrived before the ones ahead of them, by number.
+    out_of_order: BTreeMap<u32, AnimationFrame>,
+}
+
+impl Recording {
+    /// Take frame `number` of an animation, and write the frames that are now in order.
+    fn add_frame(&mut self, number: u32, frame: AnimationFrame) {
+        self.out_of_order.insert(number, frame);
+        while let Some(frame) = self.out_of_order.remove(&self.next) {
+            self.next += 1;
+            if let Some(held) = self.held.take() {
+                self.last_delay = Duration::from_secs_f32((frame.time - held.time).max(0.0));
+                self.send(held.rgba, self.last_delay);
+            }
+            self.held = Some(frame);
+        }
+    }
+
+    fn send(&self, rgba: Vec<u8>, delay: Duration) {
+        if let Some(animation) = &self.animation {
+            // This only fails once the thread stopped on an error, which it reports when the
+            // recording finishes.
+            let _ = animation.frames.send((rgba, delay));
+        }
+    }
+
+    /// Write the held frame, showing as long as the one before it, and finish the file.
+    fn finish_animation(mut self) -> Option<Task<(PathBuf, Result<(), CaptureError>)>> {
+        if let Some(held) = self.held.take() {
+            self.send(held.rgba, self.last_delay);
+        }
+        let animation = self.animation.take()?;
+        drop(animation.frames);
+        let path = self.path;
+        Some(IoTaskPool::get().spawn(async move {
+            let result = animation.thread.join().unwrap_or_else(|_| {
+                Err(CaptureError::Io(std::io::Error::other(
+                    "the encoding thread panicked",
+                )))
+            });
+            (path, result)
+        }))
+    }
+}
+
+#[derive(Resource, Default)]
+struct Recorder {
+    recording: Option<Recording>,
+    /// Recordings that were stopped and wait for their last readbacks.
+    stopping: Vec<Recording>,
+    /// Files being written.
+    saving: Vec<Task<(PathBuf, Result<(), CaptureError>)>>,
+    /// The time strategy `record_fps` replaced while recording.
+    previous_time_strategy: Option<TimeUpdateStrategy>,
+}
+
+impl Recorder {
+    fn start(&mut self, config: &PlaygroundConfig, frame: u32, time: &mut TimeUpdateStrategy) {
+        let name = format!("recording-{frame:06}");
+        let path = match config.record_format {
+            RecordFormat::Png => config.capture_dir.join(name),
+            RecordFormat::Gif => config.capture_dir.join(name + ".gif"),
+            RecordFormat::Apng => config.capture_dir.join(name + ".png"),
+        };
+        let animation = match config.record_format {
+            RecordFormat::Png => None,
+            format => match AnimationWriter::start(&path, format, config.size) {
+                Ok(animation) => Some(animation),
+                Err(err) => {
+                    error!("Can't record to {}: {err}", path.display());
+                    return;
+                }
+            },
+        };
+        info!("Recording to {}", path.display());
+        self.recording = Some(Recording {
+            path,
+            format: config.record_format,
+            last_frame: None,
+            seen: 0,
+            requested: 0,
+            pending: HashMap::new(),
+            size: config.size,
+            animation,
+            next: 0,
+            held: None,
+            last_delay: DEFAULT_FRAME_DELAY,
+            out_of_order: BTreeMap::new(),
+        });
+        if let Some(fps) = config.record_fps {
+            let fixed = TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(1.0 / fps));
+            self.previous_time_strategy = Some(mem::replace(time, fixed));
+        }
+    }
+
+    fn stop(&mut self, time: &mut TimeUpdateStrategy) {
+        if let Some(recording) = self.recording.take() {
+            info!(
+                "Stopped recording {} frames to {}",
+                recording.requested,
+                recording.path.display()
+            );
+            self.stopping.push(recording);
+        }
+        if let Some(previous) = self.previous_time_strategy.take() {
+            *time = previous;
+        }
+    }
+}
+
+pub struct RecordingPlugin;
+
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Recorder>().add_systems(
+            Update,
+            (
+                recording_hotkey,
+                request_recording_frames
+                    .after(swap_textures)
+                    .after(update_globals),
+                collect_recording_frames,
+                finish_recordings,
+            )
+                .chain(),
+        );
+    }
+}
+
+fn recording_hotkey(
+    keys: Res<ButtonInput<KeyCode>>,
+    config: Res<PlaygroundConfig>,
+    globals: Res<ComputeShaderGlobals>,
+    mut recorder: ResMut<Recorder>,
+    mut time: ResMut<TimeUpdateStrategy>,
+) {
+    if !keys.just_pressed(RECORD_KEY) {
+        return;
+    }
+    if recorder.recording.is_some() {
+        recorder.stop(&mut time);
+    } else {
+        recorder.start(&config, globals.frame, &mut time);
+    }
+}
+
+/// Read back every `record_every`th new frame of the output.
+fn request_recording_frames(
+    config: Res<PlaygroundConfig>,
+    globals: Res<ComputeShaderGlobals>,
+    image: Res<ComputeShaderImage>,
+    mut readbacks: ResMut<TextureReadbacks>,
+    mut recorder: ResMut<Recorder>,
+    mut time: ResMut<TimeUpdateStrategy>,
+) {
+    let Some(recording) = &mut recorder.recording else {
+        return;
+    };
+    // Paused frames show the same output again. The frame that's showing when recording
+    // starts is the first one recorded.
+    if recording.last_frame == Some(globals.frame) {
+        return;
+    }
+    recording.last_frame = Some(globals.frame);
+    recording.seen += 1;
+    if !(recording.seen - 1).is_multiple_of(config.record_every) {
+        return;
+    }
+
+    let id = readbacks.request(ReadbackRequest::new(image.output.clone()));
+    recording
+        .pending
+        .insert(id, (recording.requested, globals.time));
+    recording.requested += 1;
+    if config.record_max_frames == Some(recording.requested) {
+        recorder.stop(&mut time);
+    }
+}
+
+fn collect_recording_frames(
+    mut readbacks: EventReader<TextureReadbackComplete>,
+    mut recorder: ResMut<Recorder>,
+) {
+    let recorder = &mut *recorder;
+    for complete in readbacks.read() {
+        let recordings = recorder.recording.iter_mut().chain(&mut recorder.stopping);
+        for recording in recordings {
+            let Some((number, time)) = recording.pending.remove(&complete.id) else {
+                continue;
+            };
+            if recording.format == RecordFormat::Png {
+                let readback = complete.readback.clone();
+                let path = recording
+                    .path
+                    .join(frame_file_name(number + 1, ExportFormat::Png));
+                // Encode off the main thread so saving doesn't stall the frame.
+                recorder.saving.push(IoTaskPool::get().spawn(async move {
+                    let result = save_png(&readback, &path);
+                    (path, result)
+                }));
+                continue;
+            }
+            if recording.animation.is_none() {
+                continue;
+            }
+            let readback = &complete.readback;
+            if readback.size != recording.size {
+                error!(
+                    "Can't record a {}x{} frame to {}, which is {}x{}",
+                    readback.size.x,
+                    readback.size.y,
+                    recording.path.display(),
+                    recording.size.x,
+                    recording.size.y
+                );
+                recording.animation = None;
+                continue;
+            }
+            match to_rgba8(readback.format, &readback.data) {
+                Some(rgba) => recording.add_frame(number, AnimationFrame { rgba, time }),
+                None => {
+                    error!(
+                        "Can't record {:?} to {}",
+                        readback.format,
+                        recording.path.display()
+                    );
+                    recording.animation = None;
+                }
+            }
+        }
+    }
+}
+
+/// Finish the animations whose frames have all arrived, and report the files that were
+/// written.
+fn finish_recordings(mut recorder: ResMut<Recorder>) {
+    let recorder = &mut *recorder;
+    let (done, stopping) = mem::take(&mut recorder.stopping)
+        .into_iter()
+        .partition(|recording| recording.pending.is_empty());
+    recorder.stopping = stopping;
+    for recording in done {
+        recorder.saving.extend(recording.finish_animation());
+    }
+
+    recorder.saving.retain_mut(|task| {
+        let Some((path, result)) = block_on(future::poll_once(task)) else {
+            return true;
+        };
+        match result {
+            Ok(()) => info!("Saved {}", path.display()),
+            Err(err) => error!("Failed to save {}: {err}", path.display()),
+        }
+        false
+    });
+}
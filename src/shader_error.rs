@@ -0,0 +1,177 @@
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::color::Color;
+use bevy::prelude::{
+    BackgroundColor, Commands, Component, DetectChangesMut, Node, PositionType, Query, Res,
+    Resource, Text, TextColor, TextFont, UiRect, Val, Visibility, With, default,
+};
+use bevy::render::RenderApp;
+use bevy::render::render_resource::PipelineCacheError;
+use naga_oil::compose::{ComposerError, ComposerErrorInner, ErrSource};
+
+/// naga_oil stores the index of the module a span belongs to in its upper bits.
+const SPAN_SHIFT: usize = 21;
+
+/// A shader compile error, located in the source file it came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderDiagnostic {
+    pub path: String,
+    /// 1-based line, 0 when the error has no location.
+    pub line: u32,
+    /// 1-based column, 0 when the error has no location.
+    pub column: u32,
+    pub message: String,
+}
+
+impl ShaderDiagnostic {
+    /// Describe a pipeline error, or `None` for the transient errors the pipeline cache retries
+    /// on its own.
+    pub fn from_pipeline_error(error: &PipelineCacheError) -> Option<Self> {
+        match error {
+            PipelineCacheError::ShaderNotLoaded(_)
+            | PipelineCacheError::ShaderImportNotYetAvailable => None,
+            PipelineCacheError::CreateShaderModule(message) => Some(Self {
+                path: String::new(),
+                line: 0,
+                column: 0,
+                message: message.clone(),
+            }),
+            PipelineCacheError::ProcessShaderError(error) => Some(Self::from_composer_error(error)),
+        }
+    }
+
+    /// Describe an error from preprocessing or validating a shader with naga_oil.
+    pub fn from_composer_error(error: &ComposerError) -> Self {
+        let (path, source, offset) = match &error.source {
+            ErrSource::Constructing {
+                path,
+                source,
+                offset,
+            } => (path.clone(), Some(source.as_str()), *offset),
+            // Errors inside imported modules only carry the module name.
+            ErrSource::Module { name, offset, .. } => (name.clone(), None, *offset),
+        };
+
+        let (span, message) = match &error.inner {
+            ComposerErrorInner::WgslParseError(e) => (
+                e.labels().next().and_then(|(span, _)| span.to_range()),
+                e.message().to_string(),
+            ),
+            ComposerErrorInner::ShaderValidationError(e)
+            | ComposerErrorInner::HeaderValidationError(e) => (
+                e.spans().next().and_then(|(span, _)| span.to_range()),
+                e.as_inner().to_string(),
+            ),
+            inner => (None, inner.to_string()),
+        };
+
+        let (line, column) = span
+            .zip(source)
+            .and_then(|(span, source)| locate(source, unshift(span, offset)))
+            .unwrap_or((0, 0));
+        Self {
+            path,
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}:{}: ", self.path, self.line, self.column)?;
+        } else if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+fn unshift(span: Range<usize>, offset: usize) -> usize {
+    (span.start & ((1 << SPAN_SHIFT) - 1)).saturating_sub(offset)
+}
+
+/// 1-based line and column of a byte offset into `source`.
+fn locate(source: &str, offset: usize) -> Option<(u32, u32)> {
+    let prefix = source.get(..offset)?;
+    let line = prefix.matches('\n').count() + 1;
+    let column = offset - prefix.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    Some((line as u32, column as u32))
+}
+
+/// The current shader error, written by the render world and shown by the main world.
+#[derive(Resource, Clone, Default)]
+pub struct ShaderErrors(Arc<Mutex<Option<ShaderDiagnostic>>>);
+
+impl ShaderErrors {
+    pub fn set(&self, diagnostic: Option<ShaderDiagnostic>) {
+        *self.0.lock().unwrap() = diagnostic;
+    }
+
+    pub fn get(&self) -> Option<ShaderDiagnostic> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[derive(Component)]
+struct ShaderErrorOverlay;
+
+pub struct ShaderErrorPlugin;
+
+impl Plugin for ShaderErrorPlugin {
+    fn build(&self, app: &mut App) {
+        let errors = ShaderErrors::default();
+        app.insert_resource(errors.clone())
+            .add_systems(Startup, setup_overlay)
+            .add_systems(Update, update_overlay);
+        app.sub_app_mut(RenderApp).insert_resource(errors);
+    }
+}
+
+fn setup_overlay(mut commands: Commands) {
+    commands.spawn((
+        ShaderErrorOverlay,
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(Color::srgb(1.0, 0.45, 0.45)),
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            right: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+}
+
+fn update_overlay(
+    errors: Res<ShaderErrors>,
+    mut overlay: Query<(&mut Text, &mut Visibility), With<ShaderErrorOverlay>>,
+) {
+    let diagnostic = errors.get();
+    for (mut text, mut visibility) in &mut overlay {
+        let message = diagnostic
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        if text.0 != message {
+            text.0 = message;
+        }
+        visibility.set_if_neq(if diagnostic.is_some() {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
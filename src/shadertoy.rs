@@ -0,0 +1,225 @@
+//! Shadertoy-style shaders: a `mainImage` function wrapped in generated compute entry points.
+
+use std::borrow::Cow;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::BindingSource;
+
+/// How the shader asset is turned into the compute shader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ShaderMode {
+    /// The asset is the compute shader, with `init` and `update` entry points.
+    #[default]
+    Compute,
+    /// The asset defines `fn mainImage(fragCoord: vec2<f32>) -> vec4<f32>`, which is called
+    /// once per texel of `output` with Shadertoy's inputs.
+    Shadertoy,
+}
+
+impl ShaderMode {
+    /// The compute shader source for a shader asset. Like the asset, it writes state textures
+    /// of `#{STATE_FORMAT}`.
+    pub fn expand<'a>(self, source: &'a str, channels: &[ChannelInput]) -> Cow<'a, str> {
+        match self {
+            ShaderMode::Compute => Cow::Borrowed(source),
+            // Appended, so line numbers in errors still point into the asset. WGSL doesn't
+            // care about declaration order.
+            ShaderMode::Shadertoy => Cow::Owned(format!(
+                "{source}\n{}\n{}",
+                SHADERTOY_WRAPPER,
+                channel_functions(channels)
+            )),
+        }
+    }
+}
+
+/// What an `iChannelN` texture shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelInput {
+    /// A black texture the size of the state textures.
+    #[default]
+    None,
+    /// The 256x3 keyboard state texture.
+    Keyboard,
+    /// The previous frame's output.
+    Previous,
+}
+
+/// Number of `iChannel` textures.
+pub const CHANNEL_COUNT: usize = 4;
+
+/// The source of an `iChannelNTexture` binding, `None` if `name` isn't one.
+pub fn channel_source(name: &str, channels: &[ChannelInput]) -> Option<BindingSource> {
+    let index: usize = name
+        .strip_prefix("iChannel")?
+        .strip_suffix("Texture")?
+        .parse()
+        .ok()?;
+    if index >= CHANNEL_COUNT {
+        return None;
+    }
+    Some(match channels.get(index).copied().unwrap_or_default() {
+        ChannelInput::None => BindingSource::Allocated,
+        ChannelInput::Keyboard => BindingSource::Keyboard,
+        ChannelInput::Previous => BindingSource::Input,
+    })
+}
+
+/// Shadertoy's inputs and the entry points that call `mainImage`.
+const SHADERTOY_WRAPPER: &str = r"
+// Generated by the playground's Shadertoy mode.
+
+struct ShadertoyGlobals {
+    time: f32,
+    delta_time: f32,
+    frame: u32,
+    resolution: vec2<u32>,
+    mouse: vec2<f32>,
+    mouse_click: vec2<f32>,
+    mouse_buttons: u32,
+    mouse_drag: vec2<f32>,
+    mouse_pressed: u32,
+    tick: u32,
+}
+
+struct ShadertoyDispatch {
+    offset: vec3<u32>,
+}
+
+@group(0) @binding(0) var output: texture_storage_2d<#{STATE_FORMAT}, write>;
+@group(0) @binding(1) var<uniform> globals: ShadertoyGlobals;
+@group(0) @binding(2) var<uniform> dispatch: ShadertoyDispatch;
+@group(0) @binding(3) var iChannel0Texture: texture_2d<f32>;
+@group(0) @binding(4) var iChannel1Texture: texture_2d<f32>;
+@group(0) @binding(5) var iChannel2Texture: texture_2d<f32>;
+@group(0) @binding(6) var iChannel3Texture: texture_2d<f32>;
+@group(0) @binding(7) var iChannelSampler: sampler;
+
+// Channels are passed to `texture`, `texelFetch` and `textureSize` by index, like GLSL samplers.
+const iChannel0 = 0u;
+const iChannel1 = 1u;
+const iChannel2 = 2u;
+const iChannel3 = 3u;
+
+var<private> iTime: f32;
+var<private> iTimeDelta: f32;
+var<private> iFrameRate: f32;
+var<private> iFrame: i32;
+var<private> iResolution: vec3<f32>;
+var<private> iMouse: vec4<f32>;
+var<private> iChannelResolution: array<vec3<f32>, 4>;
+
+fn shadertoy_channel_resolution(channel: u32) -> vec3<f32> {
+    return vec3<f32>(vec2<f32>(textureSize(channel, 0)), 1.0);
+}
+
+fn shadertoy_main(invocation_id: vec3<u32>) {
+    let id = invocation_id + dispatch.offset;
+    let size = textureDimensions(output);
+    if (id.x >= size.x || id.y >= size.y) { return; }
+
+    let height = f32(size.y);
+    iTime = globals.time;
+    iTimeDelta = globals.delta_time;
+    iFrameRate = select(0.0, 1.0 / globals.delta_time, globals.delta_time > 0.0);
+    iFrame = i32(globals.tick);
+    iResolution = vec3<f32>(vec2<f32>(size), 1.0);
+
+    // Shadertoy's y axis points up. xy is the cursor while the left button was last held, zw
+    // the last click, with z negative once the button is released and w negative after the
+    // frame of the click. Everything stays 0 until the first click.
+    if (any(globals.mouse_click != vec2<f32>(0.0))) {
+        let down = (globals.mouse_buttons & 1u) != 0u;
+        let clicked = (globals.mouse_pressed & 1u) != 0u;
+        let click = vec2<f32>(globals.mouse_click.x, height - globals.mouse_click.y);
+        iMouse = vec4<f32>(
+            globals.mouse_drag.x,
+            height - globals.mouse_drag.y,
+            select(-click.x, click.x, down),
+            select(-click.y, click.y, clicked),
+        );
+    }
+
+    iChannelResolution = array<vec3<f32>, 4>(
+        shadertoy_channel_resolution(0u),
+        shadertoy_channel_resolution(1u),
+        shadertoy_channel_resolution(2u),
+        shadertoy_channel_resolution(3u),
+    );
+
+    // Texel centers, counted from the bottom-left corner.
+    let fragCoord = vec2<f32>(f32(id.x) + 0.5, height - f32(id.y) - 0.5);
+    textureStore(output, id.xy, mainImage(fragCoord));
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn init(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    shadertoy_main(invocation_id);
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE_X}, #{WORKGROUP_SIZE_Y}, #{WORKGROUP_SIZE_Z})
+fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    shadertoy_main(invocation_id);
+}
+";
+
+/// GLSL's `texture`, `texelFetch` and `textureSize` for the channels. `mainImage` writes its
+/// rows bottom-up, so channels showing the previous frame flip y back to Shadertoy's
+/// orientation. The keyboard texture is already laid out the way Shadertoy reads it.
+fn channel_functions(channels: &[ChannelInput]) -> String {
+    let cases = |body: &dyn Fn(usize, bool) -> String| {
+        (0..CHANNEL_COUNT)
+            .map(|index| {
+                let flip = channels.get(index) == Some(&ChannelInput::Previous);
+                let label = if index + 1 == CHANNEL_COUNT {
+                    "default".to_string()
+                } else {
+                    format!("case {index}u")
+                };
+                format!("        {label}: {{ {} }}\n", body(index, flip))
+            })
+            .collect::<String>()
+    };
+    let texture = cases(&|index, flip| {
+        let uv = if flip {
+            "vec2<f32>(uv.x, 1.0 - uv.y)"
+        } else {
+            "uv"
+        };
+        format!("return textureSampleLevel(iChannel{index}Texture, iChannelSampler, {uv}, 0.0);")
+    });
+    let texel_fetch = cases(&|index, flip| {
+        let coord = if flip {
+            let height = format!("i32(textureDimensions(iChannel{index}Texture, lod).y)");
+            format!("vec2<i32>(coord.x, {height} - 1 - coord.y)")
+        } else {
+            "coord".to_string()
+        };
+        format!("return textureLoad(iChannel{index}Texture, {coord}, lod);")
+    });
+    let texture_size = cases(&|index, _| {
+        format!("return vec2<i32>(textureDimensions(iChannel{index}Texture, lod));")
+    });
+    format!(
+        "// Sampled at mip level 0, since compute shaders have no derivatives.
+fn texture(channel: u32, uv: vec2<f32>) -> vec4<f32> {{
+    switch channel {{
+{texture}    }}
+}}
+
+fn texelFetch(channel: u32, coord: vec2<i32>, lod: i32) -> vec4<f32> {{
+    switch channel {{
+{texel_fetch}    }}
+}}
+
+fn textureSize(channel: u32, lod: i32) -> vec2<i32> {{
+    switch channel {{
+{texture_size}    }}
+}}
+"
+    )
+}
@@ -0,0 +1,404 @@
+//! Pausing, stepping, resetting and speeding up the simulation, and the fixed timestep it
+//! advances at when the config sets a `tick_rate`.
+//!
+//! | Key         | Action                                                 |
+//! |-------------|--------------------------------------------------------|
+//! | Space       | Pause or resume                                        |
+//! | `.`         | Pause, and run a single `update` step                  |
+//! | Shift + `.` | Pause, and run [`SimulationControl::step_count`] steps |
+//! | Backspace   | Run `init` again and restart the clock                 |
+//! | `]` / `[`   | Double or halve the steps per frame                    |
+
+use std::mem;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use bevy::app::{App, Plugin, Update};
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::log::info;
+use bevy::prelude::{FromWorld, IntoScheduleConfigs, Res, ResMut, Resource, Time, World};
+use bevy::render::RenderApp;
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_graph::RenderSubGraph;
+
+use crate::ComputeShaderReady;
+use crate::config::PlaygroundConfig;
+
+const PAUSE_KEY: KeyCode = KeyCode::Space;
+const STEP_KEY: KeyCode = KeyCode::Period;
+const RESET_KEY: KeyCode = KeyCode::Backspace;
+const FASTER_KEY: KeyCode = KeyCode::BracketRight;
+const SLOWER_KEY: KeyCode = KeyCode::BracketLeft;
+
+/// Upper bound of [`SimulationControl::steps_per_frame`], so speeding up can't stall the GPU.
+pub const MAX_STEPS_PER_FRAME: u32 = 64;
+
+/// Runs `update` steps of a fixed duration as simulated time comes due, so the simulation
+/// advances at the same rate whatever the frame rate. Time is counted in whole nanoseconds, so
+/// the same frame durations always come out as the same steps.
+#[derive(Clone, Debug)]
+pub struct FixedTimestep {
+    timestep: Duration,
+    max_steps: u32,
+    /// Time that has come due but is shorter than a step.
+    accumulated: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: f64, max_steps: u32) -> Self {
+        Self {
+            // Never zero, or no time would ever be short of a step.
+            timestep: Duration::from_secs_f64(1.0 / tick_rate).max(Duration::from_nanos(1)),
+            max_steps,
+            accumulated: Duration::ZERO,
+        }
+    }
+
+    pub fn from_config(config: &PlaygroundConfig) -> Option<Self> {
+        config
+            .tick_rate
+            .map(|tick_rate| Self::new(tick_rate, config.max_catch_up_steps))
+    }
+
+    /// Seconds each step simulates.
+    pub fn timestep(&self) -> f64 {
+        self.timestep.as_secs_f64()
+    }
+
+    /// Add `elapsed` and take the steps that have come due, at most `max_steps` times `speed`.
+    /// The rest are dropped.
+    pub fn advance(&mut self, elapsed: Duration, speed: u32) -> u32 {
+        let accumulated = (self.accumulated + elapsed * speed).as_nanos();
+        let timestep = self.timestep.as_nanos();
+        self.accumulated = Duration::from_nanos((accumulated % timestep) as u64);
+        let due = accumulated / timestep;
+        due.min(self.max_steps.saturating_mul(speed) as u128) as u32
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulated = Duration::ZERO;
+    }
+}
+
+/// How the simulation advances, and what the render world runs this frame.
+///
+/// Every `update` step reads the state the step before it wrote. The last step of a frame
+/// writes `output`, so when a frame runs several steps they alternate between the two state
+/// textures counting back from it.
+#[derive(Resource, Clone, Debug, ExtractResource)]
+pub struct SimulationControl {
+    pub paused: bool,
+    /// `update` steps per frame while running. With a fixed timestep, how many times faster
+    /// than real time steps come due instead.
+    pub steps_per_frame: u32,
+    /// Steps the step-N key runs.
+    pub step_count: u32,
+    /// The timestep steps run at, if the config sets a `tick_rate`.
+    pub fixed: Option<FixedTimestep>,
+    /// Steps requested while paused, run on the next frame.
+    pending_steps: u32,
+    reset_requested: bool,
+    /// `update` steps this frame runs.
+    steps: u32,
+    /// Whether this frame runs `init` again instead of `update`.
+    reset: bool,
+    /// Frames that ran `update` before this one.
+    frame: u32,
+    /// `update` steps run before this frame.
+    tick: u32,
+    /// Seconds simulated before this frame.
+    time: f64,
+    /// Seconds each step of this frame advances the clock by.
+    delta_time: f64,
+}
+
+impl FromWorld for SimulationControl {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            paused: false,
+            steps_per_frame: 1,
+            step_count: 10,
+            fixed: FixedTimestep::from_config(world.resource::<PlaygroundConfig>()),
+            pending_steps: 0,
+            reset_requested: false,
+            steps: 0,
+            reset: false,
+            frame: 0,
+            tick: 0,
+            time: 0.0,
+            delta_time: 0.0,
+        }
+    }
+}
+
+impl SimulationControl {
+    /// Pause, and run `count` more steps on the next frame.
+    pub fn step(&mut self, count: u32) {
+        self.paused = true;
+        self.pending_steps = self.pending_steps.saturating_add(count);
+    }
+
+    /// Run `init` again on the next frame, and count time and frames from zero.
+    pub fn reset(&mut self) {
+        self.reset_requested = true;
+        self.pending_steps = 0;
+    }
+
+    /// `update` steps this frame runs.
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// Whether this frame runs an odd number of steps, so the state textures swap roles before
+    /// it for its last step to write `output`.
+    pub fn swaps(&self) -> bool {
+        !self.steps.is_multiple_of(2)
+    }
+
+    /// Whether this frame runs `init` again.
+    pub fn resets(&self) -> bool {
+        self.reset
+    }
+
+    /// The `frame`, `tick`, `time` and `delta_time` globals of `step` of this frame. When the
+    /// frame runs no steps these describe the last step that ran.
+    pub fn clock(&self, step: u32) -> (u32, u32, f32, f32) {
+        if self.steps == 0 {
+            return (
+                self.frame,
+                self.tick,
+                self.time as f32,
+                self.delta_time as f32,
+            );
+        }
+        let tick = self.tick.wrapping_add(step + 1);
+        (
+            self.frame.wrapping_add(1),
+            tick,
+            self.time_at(tick, step + 1) as f32,
+            self.delta_time as f32,
+        )
+    }
+
+    /// Seconds simulated once `tick` has run, `steps` steps after this frame's start. With a
+    /// fixed timestep this only depends on `tick`, however the steps fell into frames.
+    fn time_at(&self, tick: u32, steps: u32) -> f64 {
+        match &self.fixed {
+            Some(fixed) => tick as f64 * fixed.timestep(),
+            None => self.time + steps as f64 * self.delta_time,
+        }
+    }
+}
+
+/// Whether `step` of a frame that runs `steps` reads `output` and writes `input`, since the
+/// last step writes `output` and the steps before it alternate.
+pub fn swapped(step: u32, steps: u32) -> bool {
+    (steps - 1 - step) % 2 == 1
+}
+
+/// The render graph of one step: the passes in order, then the compute shader. The compute
+/// shader's node in the main graph runs it once for every step of the frame.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderSubGraph)]
+pub struct SimulationStepGraph;
+
+/// The steps the render world records this frame, and which of them [`SimulationStepGraph`] is
+/// running. The nodes of the sub graph only get shared access, so the step is an atomic that
+/// the last of them advances.
+#[derive(Resource, Default)]
+pub struct FrameSteps {
+    count: u32,
+    current: AtomicU32,
+}
+
+impl FrameSteps {
+    /// Record `count` steps, from the first.
+    pub fn start(&mut self, count: u32) {
+        self.count = count;
+        *self.current.get_mut() = 0;
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// The step being recorded.
+    pub fn current(&self) -> u32 {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Whether the step being recorded reads `output` and writes `input`. See [`swapped`].
+    pub fn swapped(&self) -> bool {
+        swapped(self.current(), self.count)
+    }
+
+    /// Move on to the next step. Returns whether the step that was recorded is the last.
+    pub fn advance(&self) -> bool {
+        self.current.fetch_add(1, Ordering::Relaxed) + 1 >= self.count
+    }
+}
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        // Needs the `PlaygroundConfig` resource.
+        app.init_resource::<SimulationControl>()
+            .add_plugins(ExtractResourcePlugin::<SimulationControl>::default())
+            .add_systems(Update, (simulation_hotkeys, advance_simulation).chain());
+        app.sub_app_mut(RenderApp).init_resource::<FrameSteps>();
+    }
+}
+
+fn simulation_hotkeys(keys: Res<ButtonInput<KeyCode>>, mut control: ResMut<SimulationControl>) {
+    if keys.just_pressed(PAUSE_KEY) {
+        control.paused = !control.paused;
+        control.pending_steps = 0;
+        info!(
+            "Simulation {}",
+            if control.paused { "paused" } else { "resumed" }
+        );
+    }
+    if keys.just_pressed(STEP_KEY) {
+        let count = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            control.step_count
+        } else {
+            1
+        };
+        control.step(count);
+    }
+    if keys.just_pressed(RESET_KEY) {
+        control.reset();
+        info!("Simulation reset");
+    }
+    let steps_per_frame = control.steps_per_frame;
+    if keys.just_pressed(FASTER_KEY) {
+        control.steps_per_frame = (steps_per_frame * 2).min(MAX_STEPS_PER_FRAME);
+    }
+    if keys.just_pressed(SLOWER_KEY) {
+        control.steps_per_frame = (steps_per_frame / 2).max(1);
+    }
+    if control.steps_per_frame != steps_per_frame {
+        if control.fixed.is_some() {
+            info!("Simulating at {}x speed", control.steps_per_frame);
+        } else {
+            info!("{} steps per frame", control.steps_per_frame);
+        }
+    }
+}
+
+/// Count the steps the previous frame ran, and decide what this frame runs. Nothing is counted
+/// until `init` has run, and the frame `init` runs on doesn't `update`. Time only comes due
+/// while the simulation runs, so pausing or loading doesn't leave a backlog of steps.
+pub fn advance_simulation(
+    time: Res<Time>,
+    ready: Res<ComputeShaderReady>,
+    mut control: ResMut<SimulationControl>,
+) {
+    let control = &mut *control;
+    let (frame, tick, ..) = control.clock(control.steps.saturating_sub(1));
+    control.time = control.time_at(tick, control.steps);
+    (control.frame, control.tick) = (frame, tick);
+    control.delta_time = match &control.fixed {
+        Some(fixed) => fixed.timestep(),
+        None => time.delta_secs_f64(),
+    };
+
+    control.reset = ready.get() && mem::take(&mut control.reset_requested);
+    if control.reset {
+        control.frame = 0;
+        control.tick = 0;
+        control.time = 0.0;
+        if let Some(fixed) = &mut control.fixed {
+            fixed.reset();
+        }
+    }
+    control.steps = if !ready.get() || control.reset {
+        0
+    } else if control.paused {
+        mem::take(&mut control.pending_steps)
+    } else if let Some(fixed) = &mut control.fixed {
+        fixed.advance(time.delta(), control.steps_per_frame)
+    } else {
+        control.steps_per_frame
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn steps_come_due_at_the_tick_rate() {
+        let mut fixed = FixedTimestep::new(100.0, 8);
+        assert_eq!(fixed.timestep(), 0.01);
+        for _ in 0..10 {
+            assert_eq!(fixed.advance(10 * MS, 1), 1);
+        }
+        assert_eq!(fixed.advance(10 * MS, 4), 4);
+    }
+
+    #[test]
+    fn fractions_of_a_step_accumulate() {
+        let mut fixed = FixedTimestep::new(100.0, 8);
+        let steps: Vec<_> = (0..5).map(|_| fixed.advance(4 * MS, 1)).collect();
+        assert_eq!(steps, [0, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn catching_up_drops_what_is_over_the_cap() {
+        let mut fixed = FixedTimestep::new(100.0, 3);
+        assert_eq!(fixed.advance(105 * MS, 1), 3);
+        // Only the fraction of a step is kept.
+        assert_eq!(fixed.advance(5 * MS, 1), 1);
+        assert_eq!(fixed.advance(Duration::ZERO, 1), 0);
+        // Speeding up raises the cap with the rate.
+        assert_eq!(fixed.advance(100 * MS, 2), 6);
+    }
+
+    #[test]
+    fn resetting_drops_the_fraction() {
+        let mut fixed = FixedTimestep::new(100.0, 8);
+        assert_eq!(fixed.advance(6 * MS, 1), 0);
+        fixed.reset();
+        assert_eq!(fixed.advance(6 * MS, 1), 0);
+        assert_eq!(fixed.advance(6 * MS, 1), 1);
+    }
+
+    #[test]
+    fn paused_time_doesnt_come_due() {
+        let mut world = World::new();
+        world.insert_resource(PlaygroundConfig {
+            tick_rate: Some(100.0),
+            ..Default::default()
+        });
+        let ready = ComputeShaderReady::default();
+        ready.set();
+        world.insert_resource(ready);
+        world.init_resource::<SimulationControl>();
+        world.init_resource::<Time>();
+        let frame = |world: &mut World, elapsed: Duration| {
+            world.resource_mut::<Time>().advance_by(elapsed);
+            world.run_system_once(advance_simulation).unwrap();
+            world.resource::<SimulationControl>().steps()
+        };
+
+        assert_eq!(frame(&mut world, 25 * MS), 2);
+        world.resource_mut::<SimulationControl>().paused = true;
+        assert_eq!(frame(&mut world, 500 * MS), 0);
+        world.resource_mut::<SimulationControl>().paused = false;
+        // The half step from before the pause is still due.
+        assert_eq!(frame(&mut world, 5 * MS), 1);
+
+        frame(&mut world, 5 * MS);
+        world.resource_mut::<SimulationControl>().reset();
+        assert_eq!(frame(&mut world, 20 * MS), 0);
+        assert!(world.resource::<SimulationControl>().resets());
+        assert_eq!(frame(&mut world, 5 * MS), 0);
+        assert_eq!(frame(&mut world, 5 * MS), 1);
+    }
+}
@@ -0,0 +1,355 @@
+//! The storage texture accesses the adapter supports, and the fallback for `read_write` storage
+//! textures it doesn't.
+//!
+//! Binding a texture `read_write` needs format features that most adapters only have for a few
+//! formats, such as `r32float`; for any other format the pipeline never becomes valid. Instead,
+//! such a texture is bound write-only, and the shader's loads from it read a read-only copy that
+//! is made before every dispatch.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use bevy::app::{App, Plugin, Startup};
+use bevy::log::info;
+use bevy::prelude::{Commands, Res, Resource};
+use bevy::render::render_resource::{
+    CommandEncoder, Texture, TextureFormat, TextureFormatFeatureFlags,
+};
+use bevy::render::renderer::{RenderAdapter, RenderDevice};
+use bevy::render::settings::{WgpuFeatures, WgpuLimits};
+use naga::back::wgsl::WriterFlags;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{
+    AddressSpace, Expression, GlobalVariable, ImageClass, Module, ResourceBinding, Span,
+    StorageAccess, TypeInner,
+};
+
+use crate::bindings::storage_format;
+use crate::config::PlaygroundConfig;
+
+/// What the adapter supports, queried at startup. Shaders are reflected without it when there
+/// is no adapter, as when validating.
+#[derive(Resource, Clone)]
+pub struct StorageSupport {
+    adapter: RenderAdapter,
+    features: WgpuFeatures,
+    limits: WgpuLimits,
+}
+
+impl StorageSupport {
+    /// The limits of the device.
+    pub fn limits(&self) -> WgpuLimits {
+        self.limits.clone()
+    }
+
+    /// The storage accesses textures of `format` can be bound with.
+    pub fn flags(&self, format: TextureFormat) -> TextureFormatFeatureFlags {
+        let flags = TextureFormatFeatureFlags::STORAGE_READ_ONLY
+            | TextureFormatFeatureFlags::STORAGE_WRITE_ONLY
+            | TextureFormatFeatureFlags::STORAGE_READ_WRITE;
+        // Without adapter-specific format features the device only allows what WebGPU
+        // guarantees, whatever the adapter could do.
+        let features = if self
+            .features
+            .contains(WgpuFeatures::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            self.adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(self.features)
+        };
+        features.flags & flags
+    }
+
+    pub fn read_write(&self, format: TextureFormat) -> bool {
+        self.flags(format)
+            .contains(TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+    }
+}
+
+pub struct StoragePlugin;
+
+impl Plugin for StoragePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, detect_storage_support);
+    }
+}
+
+/// Query the adapter, and log how the state and pass textures will be bound if a shader
+/// declares them `read_write`.
+fn detect_storage_support(
+    mut commands: Commands,
+    adapter: Option<Res<RenderAdapter>>,
+    device: Option<Res<RenderDevice>>,
+    config: Res<PlaygroundConfig>,
+) {
+    let (Some(adapter), Some(device)) = (adapter, device) else {
+        return;
+    };
+    let support = StorageSupport {
+        adapter: adapter.clone(),
+        features: device.features(),
+        limits: device.limits(),
+    };
+    let mut formats = vec![config.format];
+    for pass in &config.passes {
+        if !formats.contains(&pass.format) {
+            formats.push(pass.format);
+        }
+    }
+    for format in formats {
+        if support.read_write(format.texture_format()) {
+            info!(
+                "{} supports read_write storage, read_write textures are bound as declared",
+                format.wgsl_name()
+            );
+        } else {
+            info!(
+                "{} doesn't support read_write storage on this adapter, read_write textures are \
+                 bound write-only and loads read a copy made before each dispatch",
+                format.wgsl_name()
+            );
+        }
+    }
+    commands.insert_resource(support);
+}
+
+/// Make every `@group(0)` storage texture whose format `flags` can't bind `read_write`
+/// write-only, and point the loads from it to a new read-only copy at an unused binding. Returns
+/// the bindings of the textures and of their copies, or `None` if the module didn't change.
+/// Textures that are never loaded don't get a copy.
+pub fn copy_read_write(
+    module: &mut Module,
+    flags: impl Fn(TextureFormat) -> TextureFormatFeatureFlags,
+) -> Result<Option<Vec<(u32, u32)>>, Vec<String>> {
+    let mut loaded = Vec::new();
+    let functions = module.functions.iter().map(|(_, function)| function);
+    let functions = functions.chain(module.entry_points.iter().map(|ep| &ep.function));
+    for function in functions {
+        for (_, expression) in function.expressions.iter() {
+            if let Expression::ImageLoad { image, .. } = expression
+                && let Expression::GlobalVariable(global) = function.expressions[*image]
+            {
+                loaded.push(global);
+            }
+        }
+    }
+
+    let mut next_binding = module
+        .global_variables
+        .iter()
+        .filter_map(|(_, global)| global.binding.as_ref())
+        .filter(|binding| binding.group == 0)
+        .map(|binding| binding.binding + 1)
+        .max()
+        .unwrap_or(0);
+    let handles: Vec<_> = module.global_variables.iter().map(|(h, _)| h).collect();
+    let mut problems = Vec::new();
+    let mut changed = false;
+    let mut copies = Vec::new();
+    let mut redirect = HashMap::new();
+    for handle in handles {
+        let global = &module.global_variables[handle];
+        let Some(binding) = global.binding.clone() else {
+            continue;
+        };
+        let TypeInner::Image {
+            dim,
+            arrayed,
+            class: ImageClass::Storage { format, access },
+        } = module.types[global.ty].inner
+        else {
+            continue;
+        };
+        if binding.group != 0 || access != StorageAccess::LOAD | StorageAccess::STORE {
+            continue;
+        }
+        let texture_format = storage_format(format);
+        let supported = flags(texture_format);
+        if supported.contains(TextureFormatFeatureFlags::STORAGE_READ_WRITE) {
+            continue;
+        }
+        let name = global.name.clone().unwrap_or_default();
+        if !supported.contains(
+            TextureFormatFeatureFlags::STORAGE_READ_ONLY
+                | TextureFormatFeatureFlags::STORAGE_WRITE_ONLY,
+        ) {
+            problems.push(format!(
+                "`{name}`: this adapter can't bind {} storage textures read_write, nor both \
+                 read-only and write-only to load from a copy",
+                format!("{texture_format:?}").to_lowercase()
+            ));
+            continue;
+        }
+
+        let mut storage_type = |access| {
+            let inner = TypeInner::Image {
+                dim,
+                arrayed,
+                class: ImageClass::Storage { format, access },
+            };
+            module
+                .types
+                .insert(naga::Type { name: None, inner }, Span::UNDEFINED)
+        };
+        let write_only = storage_type(StorageAccess::STORE);
+        let read_only = storage_type(StorageAccess::LOAD);
+        module.global_variables[handle].ty = write_only;
+        changed = true;
+        if !loaded.contains(&handle) {
+            continue;
+        }
+
+        let mut copy_name = format!("{name}_copy");
+        while module
+            .global_variables
+            .iter()
+            .any(|(_, global)| global.name.as_ref() == Some(&copy_name))
+        {
+            copy_name.push('_');
+        }
+        let copy = module.global_variables.append(
+            GlobalVariable {
+                name: Some(copy_name),
+                space: AddressSpace::Handle,
+                binding: Some(ResourceBinding {
+                    group: 0,
+                    binding: next_binding,
+                }),
+                ty: read_only,
+                init: None,
+            },
+            Span::UNDEFINED,
+        );
+        copies.push((binding.binding, next_binding));
+        redirect.insert(handle, copy);
+        next_binding += 1;
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    if !changed {
+        return Ok(None);
+    }
+
+    // Every use of a global in WGSL is an expression of its own, so the loads can be pointed at
+    // the copy without touching the stores.
+    let functions = module.functions.iter_mut().map(|(_, function)| function);
+    let functions = functions.chain(module.entry_points.iter_mut().map(|ep| &mut ep.function));
+    for function in functions {
+        let images: Vec<_> = function
+            .expressions
+            .iter()
+            .filter_map(|(_, expression)| match expression {
+                Expression::ImageLoad { image, .. } => Some(*image),
+                _ => None,
+            })
+            .collect();
+        for image in images {
+            if let Expression::GlobalVariable(global) = &mut function.expressions[image]
+                && let Some(copy) = redirect.get(global)
+            {
+                *global = *copy;
+            }
+        }
+    }
+    Ok(Some(copies))
+}
+
+/// WGSL for a module [`copy_read_write`] changed, for the pipelines to compile.
+pub fn write_wgsl(module: &Module) -> Result<String, String> {
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(module)
+        .map_err(|err| {
+            // naga's errors only say which function is invalid, the reason is in the sources.
+            let mut message = err.as_inner().to_string();
+            let mut source = err.as_inner().source();
+            while let Some(err) = source {
+                message = format!("{message}: {err}");
+                source = err.source();
+            }
+            format!(
+                "this adapter needs read_write textures bound write-only, which the shader \
+                 doesn't allow; is one passed to a function? {message}"
+            )
+        })?;
+    naga::back::wgsl::write_string(module, &info, WriterFlags::empty())
+        .map_err(|err| err.to_string())
+}
+
+/// A copy from a texture bound write-only into the read-only copy its loads read.
+#[derive(Clone)]
+pub struct TextureCopy {
+    pub source: Texture,
+    pub destination: Texture,
+}
+
+impl TextureCopy {
+    pub fn record(&self, encoder: &mut CommandEncoder) {
+        encoder.copy_texture_to_texture(
+            self.source.as_image_copy(),
+            self.destination.as_image_copy(),
+            self.source.size(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use naga::front::wgsl;
+
+    use super::*;
+
+    #[test]
+    fn read_write_textures_load_from_a_copy() {
+        let source = "
+            @group(0) @binding(0) var state: texture_storage_2d<rgba8unorm, read_write>;
+            @group(0) @binding(2) var field: texture_storage_2d<r32float, read_write>;
+
+            @compute @workgroup_size(8, 8)
+            fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+                let texel = textureLoad(state, id.xy) + textureLoad(field, id.xy).x;
+                textureStore(state, id.xy, texel);
+            }
+        ";
+        let mut module = wgsl::parse_str(source).unwrap();
+        // WebGPU only guarantees read_write for the 32-bit single-channel formats.
+        let copies = copy_read_write(&mut module, |format| {
+            format
+                .guaranteed_format_features(WgpuFeatures::empty())
+                .flags
+        });
+        assert_eq!(copies, Ok(Some(vec![(0, 3)])));
+        write_wgsl(&module).unwrap();
+
+        let access = |name: &str| {
+            let (handle, global) = module
+                .global_variables
+                .iter()
+                .find(|(_, global)| global.name.as_deref() == Some(name))
+                .unwrap();
+            let TypeInner::Image {
+                class: ImageClass::Storage { access, .. },
+                ..
+            } = module.types[global.ty].inner
+            else {
+                panic!("`{name}` isn't a storage texture");
+            };
+            (handle, global.binding.as_ref().unwrap().binding, access)
+        };
+        let (state, _, state_access) = access("state");
+        let (_, copy_binding, copy_access) = access("state_copy");
+        assert_eq!(state_access, StorageAccess::STORE);
+        assert_eq!((copy_binding, copy_access), (3, StorageAccess::LOAD));
+        assert_eq!(
+            access("field").2,
+            StorageAccess::LOAD | StorageAccess::STORE
+        );
+
+        let function = &module.entry_points[0].function;
+        let loads_state = function.expressions.iter().any(|(_, expression)| {
+            matches!(expression, Expression::ImageLoad { image, .. }
+                if function.expressions[*image] == Expression::GlobalVariable(state))
+        });
+        assert!(!loads_state);
+    }
+}
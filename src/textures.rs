@@ -0,0 +1,217 @@
+//! Image files bound to the compute shaders as sampled textures.
+//!
+//! A texture is bound to the shader variable with its name, declared as `texture_2d<f32>`, and
+//! its sampler to the variable named `<name>_sampler`.
+
+use std::str::FromStr;
+
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::asset::{AssetEvent, AssetServer, Assets, Handle};
+use bevy::image::Image;
+use bevy::prelude::{Commands, EventReader, FromWorld, Res, ResMut, Resource, World};
+use bevy::render::RenderApp;
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{
+    AddressMode, FilterMode, Sampler, SamplerBindingType, SamplerDescriptor, TextureFormat,
+    TextureSampleType, TextureView,
+};
+use bevy::render::renderer::RenderDevice;
+use bevy::render::texture::GpuImage;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PlaygroundConfig;
+
+/// An image file from the asset folder, bound by name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextureInput {
+    /// Name of the texture variable. The sampler is bound to `<name>_sampler`.
+    pub name: String,
+    /// Asset path of a PNG, JPEG, HDR or EXR image.
+    pub path: String,
+    #[serde(default)]
+    pub filter: TextureFilter,
+    #[serde(default)]
+    pub address_mode: TextureAddressMode,
+}
+
+impl TextureInput {
+    pub fn new(name: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            path: path.into(),
+            filter: TextureFilter::default(),
+            address_mode: TextureAddressMode::default(),
+        }
+    }
+
+    /// The sample type the texture is bound with. Linear filtering needs a filterable texture,
+    /// see [`make_filterable`].
+    pub fn sample_type(&self) -> TextureSampleType {
+        TextureSampleType::Float {
+            filterable: self.filter == TextureFilter::Linear,
+        }
+    }
+
+    pub fn sampler_type(&self) -> SamplerBindingType {
+        match self.filter {
+            TextureFilter::Linear => SamplerBindingType::Filtering,
+            TextureFilter::Nearest => SamplerBindingType::NonFiltering,
+        }
+    }
+}
+
+/// Parses `name=path`, as given to `--texture`.
+impl FromStr for TextureInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, path) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=PATH, got `{s}`"))?;
+        Ok(Self::new(name, path))
+    }
+}
+
+/// How a texture is sampled between texels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextureFilter {
+    #[default]
+    Linear,
+    Nearest,
+}
+
+/// How a texture is sampled outside of [0, 1].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureAddressMode {
+    #[default]
+    ClampToEdge,
+    Repeat,
+    MirrorRepeat,
+}
+
+impl TextureAddressMode {
+    fn address_mode(self) -> AddressMode {
+        match self {
+            TextureAddressMode::ClampToEdge => AddressMode::ClampToEdge,
+            TextureAddressMode::Repeat => AddressMode::Repeat,
+            TextureAddressMode::MirrorRepeat => AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+/// The images of [`PlaygroundConfig::textures`], in the same order.
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct InputTextures(pub Vec<Handle<Image>>);
+
+impl InputTextures {
+    /// The views of every texture, once they have all loaded.
+    pub fn views<'a>(
+        &self,
+        gpu_images: &'a RenderAssets<GpuImage>,
+    ) -> Option<Vec<&'a TextureView>> {
+        self.0
+            .iter()
+            .map(|image| Some(&gpu_images.get(image)?.texture_view))
+            .collect()
+    }
+}
+
+/// The samplers of [`PlaygroundConfig::textures`], in the same order.
+#[derive(Resource)]
+pub struct InputTextureSamplers(pub Vec<Sampler>);
+
+impl FromWorld for InputTextureSamplers {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let config = world.resource::<PlaygroundConfig>();
+        let samplers = config
+            .textures
+            .iter()
+            .map(|texture| {
+                let filter = match texture.filter {
+                    TextureFilter::Linear => FilterMode::Linear,
+                    TextureFilter::Nearest => FilterMode::Nearest,
+                };
+                let address_mode = texture.address_mode.address_mode();
+                render_device.create_sampler(&SamplerDescriptor {
+                    label: Some(&texture.name),
+                    address_mode_u: address_mode,
+                    address_mode_v: address_mode,
+                    mag_filter: filter,
+                    min_filter: filter,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        Self(samplers)
+    }
+}
+
+pub struct TexturesPlugin;
+
+impl Plugin for TexturesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputTextures>()
+            .add_plugins(ExtractResourcePlugin::<InputTextures>::default())
+            .add_systems(Startup, load_textures)
+            .add_systems(Update, make_filterable);
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<InputTextureSamplers>();
+    }
+}
+
+fn load_textures(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<PlaygroundConfig>,
+) {
+    let textures = config
+        .textures
+        .iter()
+        .map(|texture| asset_server.load(texture.path.clone()))
+        .collect();
+    commands.insert_resource(InputTextures(textures));
+}
+
+/// HDR and EXR images load as 32-bit floats, which most adapters can't filter. Convert those
+/// that are sampled linearly to 16-bit floats.
+fn make_filterable(
+    mut events: EventReader<AssetEvent<Image>>,
+    textures: Res<InputTextures>,
+    config: Res<PlaygroundConfig>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for event in events.read() {
+        let AssetEvent::LoadedWithDependencies { id } = event else {
+            continue;
+        };
+        for (handle, texture) in textures.0.iter().zip(&config.textures) {
+            if handle.id() != *id || texture.filter != TextureFilter::Linear {
+                continue;
+            }
+            let Some(image) = images.get_mut(handle) else {
+                continue;
+            };
+            if image.texture_descriptor.format != TextureFormat::Rgba32Float {
+                continue;
+            }
+            if let Some(data) = &mut image.data {
+                *data = data
+                    .chunks_exact(4)
+                    .flat_map(|texel| {
+                        let value = f32::from_le_bytes(texel.try_into().unwrap());
+                        half::f16::from_f32(value).to_le_bytes()
+                    })
+                    .collect();
+            }
+            image.texture_descriptor.format = TextureFormat::Rgba16Float;
+        }
+    }
+}
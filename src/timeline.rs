@@ -0,0 +1,597 @@
+//! Keyframe animation of the shader parameters of [`crate::params`].
+//!
+//! A timeline is kept next to the shader, in `shader.timeline.ron` for `shader.wgsl`, with a
+//! track of keyframes per parameter by name:
+//!
+//! ```ron
+//! (
+//!     duration: Some(4.0),
+//!     looping: true,
+//!     tracks: {
+//!         "brush_radius": [
+//!             (time: 0.0, value: [4.0], interpolation: EaseInOut),
+//!             (time: 2.0, value: [32.0]),
+//!         ],
+//!     },
+//! )
+//! ```
+//!
+//! The playhead follows `globals.time`, so the timeline pauses, steps and resets with the
+//! simulation, and renders without a window animate the same way every time. Every step of a
+//! frame sees the values at the frame's time.
+//!
+//! The bar at the bottom of the window scrubs the playhead. F7 stops or starts playback, which
+//! leaves the parameters to the panel, and F8 keys every parameter at the playhead with its
+//! current value and saves the timeline.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use bevy::app::{App, Plugin, Startup, Update};
+use bevy::color::Color;
+use bevy::input::ButtonInput;
+use bevy::input::keyboard::KeyCode;
+use bevy::log::{error, info};
+use bevy::prelude::{
+    BackgroundColor, Button, Commands, Component, DetectChangesMut, FlexDirection, FromWorld,
+    Interaction, IntoScheduleConfigs, Node, PositionType, Res, ResMut, Resource, Single, Text,
+    TextColor, TextFont, UiRect, Val, Visibility, With, World, default,
+};
+use bevy::ui::RelativeCursorPosition;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PlaygroundConfig;
+use crate::globals::{ComputeShaderGlobals, update_globals};
+use crate::params::{ParamsLayout, ShaderParams, ShowParams};
+use crate::presets::crossfade;
+
+/// Key that stops or starts playback.
+const PLAY_KEY: KeyCode = KeyCode::F7;
+
+/// Key that keys every parameter at the playhead.
+const KEY_KEY: KeyCode = KeyCode::F8;
+
+const BAR_WIDTH: f32 = 300.0;
+
+/// How the value between a keyframe and the next one is found.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// The keyframe's value until the next keyframe.
+    Step,
+    #[default]
+    Linear,
+    /// A curve through the keyframes, with the slope at each one set by its neighbors.
+    Cubic,
+    /// Starts slow.
+    EaseIn,
+    /// Ends slow.
+    EaseOut,
+    /// Starts and ends slow.
+    EaseInOut,
+}
+
+impl Interpolation {
+    /// How far from the previous to the next keyframe the value is, `fraction` of the way
+    /// between their times. [`Interpolation::Cubic`] doesn't use this.
+    fn ease(self, fraction: f32) -> f32 {
+        match self {
+            Interpolation::Step => 0.0,
+            Interpolation::Linear | Interpolation::Cubic => fraction,
+            Interpolation::EaseIn => fraction * fraction,
+            Interpolation::EaseOut => 1.0 - (1.0 - fraction) * (1.0 - fraction),
+            Interpolation::EaseInOut => fraction * fraction * (3.0 - 2.0 * fraction),
+        }
+    }
+}
+
+/// A parameter's value at a time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keyframe {
+    /// Seconds from the start of the timeline.
+    pub time: f32,
+    /// The components of the parameter.
+    pub value: Vec<f32>,
+    /// How the value changes from this keyframe to the next.
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+/// Keyframes for some of the parameters, by name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeline {
+    /// Seconds the timeline runs for. Defaults to the time of the last keyframe.
+    pub duration: Option<f32>,
+    /// Whether the timeline starts over after `duration`, instead of holding its last values.
+    pub looping: bool,
+    /// The keyframes of each parameter, in time order.
+    pub tracks: BTreeMap<String, Vec<Keyframe>>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            duration: None,
+            looping: true,
+            tracks: BTreeMap::new(),
+        }
+    }
+}
+
+impl Timeline {
+    pub fn duration(&self) -> f32 {
+        self.duration.unwrap_or_else(|| {
+            let last = self.tracks.values().filter_map(|keys| keys.last());
+            last.map(|key| key.time).fold(0.0, f32::max)
+        })
+    }
+
+    /// Where the playhead is `time` seconds after the start.
+    pub fn playhead(&self, time: f64) -> f32 {
+        let duration = self.duration() as f64;
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        let time = if self.looping {
+            time.rem_euclid(duration)
+        } else {
+            time.clamp(0.0, duration)
+        };
+        time as f32
+    }
+
+    /// Set the parameters that have a track to their values at `playhead`. Tracks of values
+    /// with the wrong number of components, from before a parameter changed type, are skipped.
+    pub fn apply(&self, layout: &ParamsLayout, playhead: f32, values: &mut [[f32; 4]]) {
+        for (field, value) in layout.fields.iter().zip(values) {
+            let Some(keys) = self.tracks.get(&field.name) else {
+                continue;
+            };
+            if keys.iter().any(|key| key.value.len() != field.components) {
+                continue;
+            }
+            for (i, component) in value[..field.components].iter_mut().enumerate() {
+                if let Some(sample) = sample(keys, i, playhead) {
+                    *component = sample;
+                }
+            }
+        }
+    }
+
+    /// Key every parameter at `playhead`, replacing keyframes at the same time.
+    pub fn insert_keys(&mut self, layout: &ParamsLayout, playhead: f32, values: &[[f32; 4]]) {
+        for (field, value) in layout.fields.iter().zip(values) {
+            let keys = self.tracks.entry(field.name.clone()).or_default();
+            let key = Keyframe {
+                time: playhead,
+                value: value[..field.components].to_vec(),
+                interpolation: Interpolation::default(),
+            };
+            match keys.binary_search_by(|k| k.time.total_cmp(&playhead)) {
+                Ok(index) => {
+                    keys[index].value = key.value;
+                }
+                Err(index) => keys.insert(index, key),
+            }
+        }
+    }
+}
+
+/// The value of component `i` of a track at `time`, `None` if it has no keyframes.
+fn sample(keys: &[Keyframe], i: usize, time: f32) -> Option<f32> {
+    let next = keys.partition_point(|key| key.time <= time);
+    if next == 0 || next == keys.len() {
+        // Before the first or after the last keyframe.
+        let key = keys.get(next.saturating_sub(1)).or(keys.first())?;
+        return Some(key.value[i]);
+    }
+    let (from, to) = (&keys[next - 1], &keys[next]);
+    let span = to.time - from.time;
+    let fraction = (time - from.time) / span;
+    let (p1, p2) = (from.value[i], to.value[i]);
+    if from.interpolation != Interpolation::Cubic {
+        return Some(p1 + (p2 - p1) * from.interpolation.ease(fraction));
+    }
+
+    // A cubic Hermite curve with the slopes of the lines through each keyframe's neighbors.
+    let slope = |index: usize| {
+        let before = &keys[index.saturating_sub(1)];
+        let after = &keys[(index + 1).min(keys.len() - 1)];
+        let span = after.time - before.time;
+        if span > 0.0 {
+            (after.value[i] - before.value[i]) / span
+        } else {
+            0.0
+        }
+    };
+    let (m1, m2) = (slope(next - 1) * span, slope(next) * span);
+    let (t, t2, t3) = (
+        fraction,
+        fraction * fraction,
+        fraction * fraction * fraction,
+    );
+    Some(
+        (2.0 * t3 - 3.0 * t2 + 1.0) * p1
+            + (t3 - 2.0 * t2 + t) * m1
+            + (-2.0 * t3 + 3.0 * t2) * p2
+            + (t3 - t2) * m2,
+    )
+}
+
+#[derive(Debug)]
+pub enum TimelineError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Invalid(PathBuf, String),
+    Write(PathBuf, io::Error),
+}
+
+impl fmt::Display for TimelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineError::Read(path, err) => {
+                write!(f, "failed to read {}: {err}", path.display())
+            }
+            TimelineError::Parse(path, err) => write!(f, "{}:{err}", path.display()),
+            TimelineError::Invalid(path, reason) => write!(f, "{}: {reason}", path.display()),
+            TimelineError::Write(path, err) => {
+                write!(f, "failed to write {}: {err}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TimelineError {}
+
+/// File the timeline of the configured shader is kept in.
+pub fn timeline_path(config: &PlaygroundConfig) -> PathBuf {
+    config.shader_path().with_extension("timeline.ron")
+}
+
+/// The timeline in `path`, `None` if it doesn't exist.
+pub fn load_timeline(path: &Path) -> Result<Option<Timeline>, TimelineError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(TimelineError::Read(path.into(), err)),
+    };
+    let mut timeline: Timeline =
+        ron::from_str(&text).map_err(|err| TimelineError::Parse(path.into(), err))?;
+    let invalid = |reason: String| TimelineError::Invalid(path.into(), reason);
+    if let Some(duration) = timeline.duration
+        && !(duration.is_finite() && duration >= 0.0)
+    {
+        return Err(invalid("`duration` must be 0 or more seconds".to_string()));
+    }
+    for (name, keys) in &mut timeline.tracks {
+        if let Some(key) = keys.iter().find(|key| !key.time.is_finite()) {
+            return Err(invalid(format!("`{name}` has a keyframe at {}", key.time)));
+        }
+        if keys
+            .iter()
+            .any(|key| key.value.len() != keys[0].value.len())
+        {
+            return Err(invalid(format!(
+                "the keyframes of `{name}` have different numbers of components"
+            )));
+        }
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+    Ok(Some(timeline))
+}
+
+pub fn save_timeline(path: &Path, timeline: &Timeline) -> Result<(), TimelineError> {
+    let text = ron::ser::to_string_pretty(timeline, ron::ser::PrettyConfig::default())
+        .map_err(|err| TimelineError::Write(path.into(), io::Error::other(err)))?;
+    fs::write(path, text + "\n").map_err(|err| TimelineError::Write(path.into(), err))
+}
+
+#[derive(Resource)]
+struct TimelinePlayer {
+    path: PathBuf,
+    timeline: Option<Timeline>,
+    playing: bool,
+    /// Seconds the playhead was scrubbed ahead of `globals.time`.
+    offset: f64,
+    /// Where the playhead is this frame.
+    playhead: f32,
+}
+
+impl FromWorld for TimelinePlayer {
+    fn from_world(world: &mut World) -> Self {
+        let path = timeline_path(world.resource::<PlaygroundConfig>());
+        let timeline = load_timeline(&path).unwrap_or_else(|err| {
+            error!("{err}");
+            None
+        });
+        Self {
+            path,
+            timeline,
+            playing: true,
+            offset: 0.0,
+            playhead: 0.0,
+        }
+    }
+}
+
+#[derive(Component)]
+struct TimelineBar;
+
+#[derive(Component)]
+struct TimelineText;
+
+#[derive(Component)]
+struct TimelineScrubber;
+
+#[derive(Component)]
+struct TimelinePlayhead;
+
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimelinePlayer>()
+            .add_systems(Startup, setup_bar)
+            .add_systems(
+                Update,
+                (timeline_hotkeys, scrub, play_timeline, update_bar)
+                    .chain()
+                    .after(update_globals)
+                    .after(crossfade)
+                    .before(ShowParams),
+            );
+    }
+}
+
+fn setup_bar(mut commands: Commands) {
+    commands
+        .spawn((
+            TimelineBar,
+            // Keeps clicks around the bar from reaching the shader, see `update_mouse`.
+            Interaction::default(),
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(8.0),
+                left: Val::Percent(50.0),
+                margin: UiRect::left(Val::Px(-BAR_WIDTH / 2.0 - 6.0)),
+                padding: UiRect::all(Val::Px(6.0)),
+                row_gap: Val::Px(4.0),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            Visibility::Hidden,
+        ))
+        .with_children(|bar| {
+            bar.spawn((
+                TimelineText,
+                Text::default(),
+                TextFont {
+                    font_size: 12.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+            bar.spawn((
+                Button,
+                TimelineScrubber,
+                RelativeCursorPosition::default(),
+                BackgroundColor(Color::srgb(0.25, 0.25, 0.25)),
+                Node {
+                    width: Val::Px(BAR_WIDTH),
+                    height: Val::Px(10.0),
+                    ..default()
+                },
+            ))
+            .with_child((
+                TimelinePlayhead,
+                BackgroundColor(Color::srgb(1.0, 0.6, 0.2)),
+                Node {
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn timeline_hotkeys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut player: ResMut<TimelinePlayer>,
+    params: Res<ShaderParams>,
+) {
+    if keys.just_pressed(PLAY_KEY) {
+        player.playing = !player.playing;
+        // Pick up edits made by hand while playback was stopped.
+        if player.playing {
+            match load_timeline(&player.path) {
+                Ok(timeline) => player.timeline = timeline,
+                Err(err) => error!("{err}"),
+            }
+        }
+        info!(
+            "Timeline {}",
+            if player.playing { "playing" } else { "stopped" }
+        );
+    }
+    if keys.just_pressed(KEY_KEY)
+        && let Some(layout) = &params.layout
+    {
+        let player = &mut *player;
+        let playhead = player.playhead;
+        let timeline = player.timeline.get_or_insert_default();
+        timeline.insert_keys(layout, playhead, &params.values);
+        match save_timeline(&player.path, timeline) {
+            Ok(()) => info!(
+                "Keyed {} parameters at {playhead:.3}s in {}",
+                layout.fields.len(),
+                player.path.display()
+            ),
+            Err(err) => error!("{err}"),
+        }
+    }
+}
+
+/// Move the playhead to where the bar is held.
+fn scrub(
+    scrubber: Single<(&Interaction, &RelativeCursorPosition), With<TimelineScrubber>>,
+    globals: Res<ComputeShaderGlobals>,
+    mut player: ResMut<TimelinePlayer>,
+) {
+    let (Interaction::Pressed, Some(cursor)) = (scrubber.0, scrubber.1.normalized) else {
+        return;
+    };
+    let Some(timeline) = &player.timeline else {
+        return;
+    };
+    let target = cursor.x.clamp(0.0, 1.0) as f64 * timeline.duration() as f64;
+    player.offset = target - globals.time as f64;
+}
+
+fn play_timeline(
+    globals: Res<ComputeShaderGlobals>,
+    mut player: ResMut<TimelinePlayer>,
+    mut params: ResMut<ShaderParams>,
+) {
+    let player = &mut *player;
+    let Some(timeline) = &player.timeline else {
+        return;
+    };
+    let playhead = timeline.playhead(globals.time as f64 + player.offset);
+    player.playhead = playhead;
+    let (Some(layout), true) = (&params.layout, player.playing) else {
+        return;
+    };
+    let mut values = params.values.clone();
+    timeline.apply(layout, playhead, &mut values);
+    // Only touch the values when they change, so the panel isn't redrawn every frame.
+    if values != params.values {
+        params.values = values;
+    }
+}
+
+fn update_bar(
+    player: Res<TimelinePlayer>,
+    mut bar: Single<&mut Visibility, With<TimelineBar>>,
+    mut text: Single<&mut Text, With<TimelineText>>,
+    mut playhead: Single<&mut Node, With<TimelinePlayhead>>,
+) {
+    let Some(timeline) = &player.timeline else {
+        bar.set_if_neq(Visibility::Hidden);
+        return;
+    };
+    bar.set_if_neq(Visibility::Visible);
+    let duration = timeline.duration();
+    let state = if player.playing { "" } else { " (stopped)" };
+    let label = format!("{:.2} / {duration:.2}s{state}", player.playhead);
+    if text.0 != label {
+        text.0 = label;
+    }
+    let fraction = if duration > 0.0 {
+        player.playhead / duration
+    } else {
+        0.0
+    };
+    let width = Val::Percent(100.0 * fraction);
+    if playhead.width != width {
+        playhead.width = width;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, value: f32, interpolation: Interpolation) -> Keyframe {
+        Keyframe {
+            time,
+            value: vec![value],
+            interpolation,
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn interpolations_meet_the_keyframes() {
+        for (interpolation, middle) in [
+            (Interpolation::Step, 0.0),
+            (Interpolation::Linear, 5.0),
+            (Interpolation::Cubic, 5.0),
+            (Interpolation::EaseIn, 2.5),
+            (Interpolation::EaseOut, 7.5),
+            (Interpolation::EaseInOut, 5.0),
+        ] {
+            let keys = [key(0.0, 0.0, interpolation), key(1.0, 10.0, interpolation)];
+            assert_eq!(sample(&keys, 0, 0.0), Some(0.0), "{interpolation:?}");
+            let value = sample(&keys, 0, 0.5).unwrap();
+            assert!(close(value, middle), "{interpolation:?}: {value}");
+            assert_eq!(sample(&keys, 0, 1.0), Some(10.0), "{interpolation:?}");
+        }
+    }
+
+    #[test]
+    fn cubic_ends_slope_toward_their_neighbor() {
+        let keys = [
+            key(0.0, 0.0, Interpolation::Cubic),
+            key(1.0, 10.0, Interpolation::Cubic),
+            key(2.0, 0.0, Interpolation::Cubic),
+        ];
+        let slope = |time: f32| {
+            let h = 1e-3;
+            (sample(&keys, 0, time + h).unwrap() - sample(&keys, 0, time).unwrap()) / h
+        };
+        assert!((slope(0.0) - 10.0).abs() < 0.1, "{}", slope(0.0));
+        assert!(slope(1.0).abs() < 0.1, "{}", slope(1.0));
+        assert!(
+            (slope(2.0 - 1e-3) + 10.0).abs() < 0.1,
+            "{}",
+            slope(2.0 - 1e-3)
+        );
+    }
+
+    #[test]
+    fn keyframes_at_the_same_time_jump() {
+        let keys = [
+            key(0.0, 0.0, Interpolation::Cubic),
+            key(1.0, 5.0, Interpolation::Cubic),
+            key(1.0, 10.0, Interpolation::Cubic),
+            key(2.0, 0.0, Interpolation::Linear),
+        ];
+        assert!(close(sample(&keys, 0, 1.0 - 1e-5).unwrap(), 5.0));
+        assert_eq!(sample(&keys, 0, 1.0), Some(10.0));
+        for time in [0.5, 1.5] {
+            assert!(sample(&keys, 0, time).unwrap().is_finite());
+        }
+    }
+
+    #[test]
+    fn tracks_hold_their_ends() {
+        let keys = [
+            key(1.0, 3.0, Interpolation::Linear),
+            key(2.0, 7.0, Interpolation::Linear),
+        ];
+        assert_eq!(sample(&keys, 0, 0.0), Some(3.0));
+        assert_eq!(sample(&keys, 0, 5.0), Some(7.0));
+        assert_eq!(sample(&[], 0, 1.0), None);
+    }
+
+    #[test]
+    fn looping_wraps_at_the_duration() {
+        let mut timeline = Timeline {
+            duration: Some(4.0),
+            ..default()
+        };
+        assert_eq!(timeline.playhead(4.0), 0.0);
+        assert_eq!(timeline.playhead(5.0), 1.0);
+        assert_eq!(timeline.playhead(-1.0), 3.0);
+
+        timeline.looping = false;
+        assert_eq!(timeline.playhead(4.0), 4.0);
+        assert_eq!(timeline.playhead(9.0), 4.0);
+        assert_eq!(timeline.playhead(-1.0), 0.0);
+    }
+}
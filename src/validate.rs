@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::render::settings::WgpuLimits;
+use clap::Args;
+
+use crate::bindings::{ShaderTarget, reflect};
+use crate::config::{PlaygroundConfig, expand_state_format};
+use crate::cpu::compose_module;
+use crate::params::reflect_params;
+use crate::shader_error::ShaderDiagnostic;
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// Shader file to check instead of the configured `shader` asset.
+    pub path: Option<PathBuf>,
+}
+
+/// Check a shader the way the playground would, without a GPU: preprocess it with the
+/// pipeline's shader defs, validate it with naga, then check its entry points and that every
+/// binding can be bound. Without a path the shaders of the passes are checked too. Returns the
+/// paths that were checked.
+pub fn validate(
+    config: &PlaygroundConfig,
+    args: &ValidateArgs,
+) -> Result<Vec<PathBuf>, Vec<ShaderDiagnostic>> {
+    let shaders = match &args.path {
+        Some(path) => vec![(path.clone(), ShaderTarget::Main)],
+        None => {
+            let passes = config.passes.iter().enumerate().map(|(index, pass)| {
+                (
+                    PlaygroundConfig::asset_path(&pass.shader),
+                    ShaderTarget::Pass(index),
+                )
+            });
+            [(config.shader_path(), ShaderTarget::Main)]
+                .into_iter()
+                .chain(passes)
+                .collect()
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    for (path, target) in &shaders {
+        if let Err(errors) = validate_shader(config, path, *target) {
+            diagnostics.extend(errors);
+        }
+    }
+    if diagnostics.is_empty() {
+        Ok(shaders.into_iter().map(|(path, _)| path).collect())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+fn validate_shader(
+    config: &PlaygroundConfig,
+    path: &Path,
+    target: ShaderTarget,
+) -> Result<(), Vec<ShaderDiagnostic>> {
+    let file_path = path.to_string_lossy().into_owned();
+    let diagnostic = |message: String| ShaderDiagnostic {
+        path: file_path.clone(),
+        line: 0,
+        column: 0,
+        message,
+    };
+
+    let source = fs::read_to_string(path)
+        .map_err(|err| vec![diagnostic(format!("failed to read: {err}"))])?;
+    let source = match target {
+        ShaderTarget::Main => config.mode.expand(&source, &config.channels),
+        ShaderTarget::Pass(_) => source.into(),
+    };
+    let format = target.state_format(config);
+    let source = expand_state_format(&source, format);
+    let module = compose_module(&source, &file_path, &config.shader_defs(format))
+        .map_err(|err| vec![err])?;
+    // Without a device, bindings are checked against the limits every device has.
+    let mut problems = reflect(&module, config, target, &WgpuLimits::default())
+        .err()
+        .unwrap_or_default();
+    problems.extend(reflect_params(&module, &source).err().unwrap_or_default());
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.into_iter().map(diagnostic).collect())
+    }
+}